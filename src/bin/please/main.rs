@@ -1,4 +1,8 @@
-use std::{fs, thread};
+use std::{
+    fs,
+    io::{self, Read},
+    thread,
+};
 
 use please::Engine;
 
@@ -12,9 +16,22 @@ fn main() -> anyhow::Result<()> {
 
             let mut engine = Engine::new();
 
+            let subcommand = args.subcommand()?;
+            if subcommand.as_deref() == Some("fmt") {
+                return format_files(&mut engine, args);
+            }
+            // Otherwise it's just the first file
+            let mut first_file = subcommand;
+
             let do_repl = args.contains("--repl");
+            if let Some(seed) = args.opt_value_from_str::<_, u64>("--seed")? {
+                engine.seed_rng(seed);
+            }
             let mut had_any_files = false;
-            while let Some(path_stub) = args.opt_free_from_str::<String>()? {
+            while let Some(path_stub) = match first_file.take() {
+                Some(it) => Some(it),
+                None => args.opt_free_from_str::<String>()?,
+            } {
                 had_any_files = true;
 
                 let path = root.join(path_stub);
@@ -48,3 +65,57 @@ fn main() -> anyhow::Result<()> {
         .unwrap()?;
     Ok(())
 }
+
+/// `please fmt [--check] [files...]`
+///
+/// Formats the files in place, or stdin to stdout if there aren't any.
+/// With `--check`, just complains about the ones that aren't formatted.
+fn format_files(engine: &mut Engine, mut args: pico_args::Arguments) -> anyhow::Result<()> {
+    let check = args.contains("--check");
+    let paths = args.finish();
+
+    if paths.is_empty() {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source)?;
+        let formatted = format_or_report(engine, &source, "<stdin>".to_owned())?;
+        if check {
+            if formatted != source {
+                anyhow::bail!("<stdin> is not formatted");
+            }
+        } else {
+            print!("{}", formatted);
+        }
+        return Ok(());
+    }
+
+    let mut unformatted = Vec::new();
+    for path in paths {
+        let name = path.to_string_lossy().into_owned();
+        let source = fs::read_to_string(&path)?;
+        let formatted = format_or_report(engine, &source, name.clone())?;
+        if formatted != source {
+            if check {
+                eprintln!("{} is not formatted", name);
+            } else {
+                fs::write(&path, formatted)?;
+            }
+            unformatted.push(name);
+        }
+    }
+
+    if check && !unformatted.is_empty() {
+        anyhow::bail!("{} file(s) are not formatted", unformatted.len());
+    }
+    Ok(())
+}
+
+fn format_or_report(engine: &mut Engine, source: &str, name: String) -> anyhow::Result<String> {
+    match engine.format_source(source, name.clone()) {
+        Ok(it) => Ok(it),
+        Err(ono) => {
+            ono.report()
+                .eprint(ariadne::sources(std::iter::once((name.clone(), source))))?;
+            anyhow::bail!("could not format {}", name)
+        }
+    }
+}
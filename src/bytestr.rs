@@ -0,0 +1,232 @@
+//! Byte strings that share their buffer, so slicing doesn't copy.
+//!
+//! While a string is being built up inside a transient, it owns its buffer outright
+//! so it can be appended to in place. Once it's persisted it gets shared.
+//!
+//! A small view of a big buffer keeps the whole buffer alive. Strings that get `define`d
+//! or put in a map are compacted on the way in, since those tend to stick around;
+//! `string/compact` does it by hand anywhere else.
+
+use std::{
+    fmt::Debug,
+    hash::{Hash, Hasher},
+    ops::{Bound, Deref, RangeBounds},
+};
+
+use gc::{Finalize, Gc, Trace};
+
+/// Below this many bytes, there's no point compacting a buffer.
+const COMPACT_MIN_BUF: usize = 4096;
+
+/// A view of `len` bytes at `start` in a buffer.
+///
+/// This compares and hashes just like the bytes it's looking at.
+#[derive(Clone)]
+pub struct ByteStr {
+    buf: Buf,
+    start: usize,
+    len: usize,
+}
+
+#[derive(Clone)]
+enum Buf {
+    Shared(Gc<Vec<u8>>),
+    /// Only ever used by one string, which covers all of it.
+    Unique(Vec<u8>),
+}
+
+impl Finalize for ByteStr {}
+unsafe impl Trace for ByteStr {
+    custom_trace!(this, {
+        if let Buf::Shared(buf) = &this.buf {
+            mark(buf);
+        }
+    });
+}
+
+impl ByteStr {
+    /// Wrap a buffer without sharing it, so it can be appended to later.
+    pub fn unique(buf: Vec<u8>) -> ByteStr {
+        let len = buf.len();
+        ByteStr {
+            buf: Buf::Unique(buf),
+            start: 0,
+            len,
+        }
+    }
+
+    /// Get the bytes out to append to. This only copies if the buffer was shared.
+    pub fn into_vec(self) -> Vec<u8> {
+        match self.buf {
+            Buf::Unique(buf) => buf,
+            Buf::Shared(_) => self.to_vec(),
+        }
+    }
+
+    /// Make the buffer shareable. This doesn't copy.
+    pub fn share(self) -> ByteStr {
+        match self.buf {
+            Buf::Unique(buf) => ByteStr::from(buf),
+            Buf::Shared(_) => self,
+        }
+    }
+
+    /// Get a view of part of this string. This doesn't copy, unless the buffer isn't shared yet.
+    ///
+    /// Panics if the range is out of bounds, like slicing would.
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> ByteStr {
+        let start = match range.start_bound() {
+            Bound::Included(&it) => it,
+            Bound::Excluded(&it) => it + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&it) => it + 1,
+            Bound::Excluded(&it) => it,
+            Bound::Unbounded => self.len,
+        };
+        assert!(
+            start <= end && end <= self.len,
+            "slice {}..{} out of bounds of a string of len {}",
+            start,
+            end,
+            self.len
+        );
+        match &self.buf {
+            Buf::Shared(buf) => ByteStr {
+                buf: Buf::Shared(buf.clone()),
+                start: self.start + start,
+                len: end - start,
+            },
+            Buf::Unique(buf) => ByteStr::from(&buf[start..end]),
+        }
+    }
+
+    /// Does this view keep alive a buffer much bigger than itself?
+    pub fn is_pinning(&self) -> bool {
+        match &self.buf {
+            Buf::Shared(buf) => buf.len() >= COMPACT_MIN_BUF && buf.len() / 2 > self.len,
+            Buf::Unique(_) => false,
+        }
+    }
+
+    /// Copy this out into its own buffer if it's pinning a much bigger one,
+    /// so the big one can be freed.
+    pub fn compact(&self) -> ByteStr {
+        if self.is_pinning() {
+            ByteStr::from(self.to_vec())
+        } else {
+            self.clone()
+        }
+    }
+}
+
+impl Default for ByteStr {
+    fn default() -> Self {
+        ByteStr::unique(Vec::new())
+    }
+}
+
+impl Deref for ByteStr {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        let buf = match &self.buf {
+            Buf::Shared(buf) => &**buf,
+            Buf::Unique(buf) => buf,
+        };
+        &buf[self.start..self.start + self.len]
+    }
+}
+
+impl AsRef<[u8]> for ByteStr {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl From<Vec<u8>> for ByteStr {
+    fn from(buf: Vec<u8>) -> Self {
+        let len = buf.len();
+        ByteStr {
+            buf: Buf::Shared(Gc::new(buf)),
+            start: 0,
+            len,
+        }
+    }
+}
+
+impl From<&[u8]> for ByteStr {
+    fn from(bytes: &[u8]) -> Self {
+        bytes.to_vec().into()
+    }
+}
+
+impl From<String> for ByteStr {
+    fn from(s: String) -> Self {
+        s.into_bytes().into()
+    }
+}
+
+impl From<&str> for ByteStr {
+    fn from(s: &str) -> Self {
+        s.as_bytes().into()
+    }
+}
+
+impl PartialEq for ByteStr {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for ByteStr {}
+
+impl Hash for ByteStr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl Debug for ByteStr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+#[cfg(test)]
+fn shares(a: &ByteStr, b: &ByteStr) -> bool {
+    match (&a.buf, &b.buf) {
+        (Buf::Shared(a), Buf::Shared(b)) => Gc::ptr_eq(a, b),
+        _ => false,
+    }
+}
+
+#[test]
+fn test_slice_shares() {
+    let big = ByteStr::from(vec![b'x'; COMPACT_MIN_BUF * 2]);
+    let small = big.slice(10..20).slice(2..);
+    assert!(shares(&big, &small));
+    assert_eq!(&*small, &[b'x'; 8][..]);
+    assert_eq!(small, ByteStr::from("xxxxxxxx"));
+
+    assert!(small.is_pinning());
+    let compacted = small.compact();
+    assert!(!shares(&big, &compacted));
+    assert_eq!(compacted, small);
+    assert!(!big.is_pinning());
+}
+
+#[test]
+fn test_unique_appends() {
+    let mut buf = ByteStr::from("abc").into_vec();
+    buf.extend_from_slice(b"def");
+    let built = ByteStr::unique(buf);
+    assert_eq!(built, ByteStr::from("abcdef"));
+
+    let buf = built.into_vec();
+    let ptr = buf.as_ptr();
+    let shared = ByteStr::unique(buf).share();
+    assert_eq!(shared.as_ptr(), ptr);
+    assert!(shares(&shared, &shared.slice(1..3)));
+}
@@ -4,236 +4,469 @@ use std::fmt::{Debug, Display};
 
 use super::*;
 
+pub(crate) mod pretty;
+
+use pretty::Token;
+
+/// How far printing will go into lazy lists before giving up with a `...`.
+///
+/// Strict lists are always printed in full; they're finite anyways.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrintLimits {
+    /// Elements of a lazy list to print before cutting it off.
+    pub length: Option<usize>,
+    /// How deeply nested a lazy list can be before it's printed as just `...`.
+    pub depth: Option<usize>,
+}
+
+impl Default for PrintLimits {
+    fn default() -> Self {
+        Self {
+            length: Some(100),
+            depth: Some(32),
+        }
+    }
+}
+
 impl Engine {
     /// Write an expression to a string. Reading this string
     /// should yield the same as the original (with some caveats for procedures &c).
     pub fn write_expr(&mut self, expr: Gc<Expr>) -> Result<String, Exception> {
-        self.reify(&expr)?;
-        fn recur(
-            engine: &mut Engine,
-            w: &mut impl Write,
-            expr: Gc<Expr>,
-        ) -> Result<(), fmt::Error> {
-            match &*expr {
-                Expr::Integer(i) => write!(w, "{}", i),
-                Expr::Float(f) => write!(w, "{:?}", f),
-                Expr::Symbol(sym) => {
-                    if let Some(s) = engine.get_symbol_str(*sym) {
-                        write!(w, "{}", BstrFmt(s))
-                    } else {
-                        write!(w, "<unknown #{}>", sym)
-                    }
-                }
-                Expr::Bool(b) => {
-                    write!(w, "{}", b)
-                }
-                Expr::Pair(..) | Expr::LazyPair(..) => {
-                    let (car, cdr) = engine.split_cons(expr).unwrap();
-                    fn write_list<W: Write>(
-                        engine: &mut Engine,
-                        w: &mut W,
-                        car: Gc<Expr>,
-                        cdr: Gc<Expr>,
-                    ) -> Result<(), fmt::Error> {
-                        recur(engine, w, car)?;
-                        match &*cdr {
-                            // Proper list's end, do nothing
-                            Expr::Nil => Ok(()),
-                            // Proper list, leave space for the next thing.
-                            Expr::Pair(..) | Expr::LazyPair(..) => {
-                                let (cdar, cddr) = engine.split_cons(cdr).unwrap();
-                                write!(w, " ")?;
-                                write_list(engine, w, cdar, cddr)
-                            }
-                            // Just a pair
-                            _ => {
-                                write!(w, " . ")?;
-                                recur(engine, w, cdr)
-                            }
-                        }
-                    }
+        Printer::run(self, expr, true, None)
+    }
 
-                    write!(w, "(")?;
-                    write_list(engine, w, car, cdr)?;
-                    write!(w, ")")
-                }
-                Expr::Nil => {
-                    write!(w, "()")
-                }
-                Expr::String(s) => {
-                    write!(w, "{:?}", BstrFmt(s))
-                }
-                Expr::SpecialForm { name, .. } => {
-                    if let Some(name) = engine.get_symbol_str(*name) {
-                        write!(w, "<special form {}>", BstrFmt(name))
-                    } else {
-                        write!(w, "<anonymous special form>")
-                    }
-                }
-                Expr::NativeProcedure { name, .. } => {
-                    if let Some(name) = engine.get_symbol_str(*name) {
-                        write!(w, "<native proc {}>", BstrFmt(name))
-                    } else {
-                        write!(w, "<anonymous native proc>")
-                    }
-                }
-                Expr::Procedure {
-                    arg_spec,
-                    body,
-                    env,
-                    ..
-                } => {
-                    write!(w, "(")?;
-                    if env.is_some() {
-                        write!(w, "lambda ")?;
-                    } else {
-                        write!(w, "macro ")?;
-                    }
+    /// Print the expression to a string
+    /// in a nice and human-readable way.
+    pub fn print_expr(&mut self, expr: Gc<Expr>) -> Result<String, Exception> {
+        Printer::run(self, expr, false, None)
+    }
 
-                    recur(engine, w, arg_spec.to_owned())?;
+    /// Write an expression to a string, breaking it over lines to try and fit it in `width` columns.
+    ///
+    /// Long strings are cut off, so unlike `write_expr` this won't always read back in.
+    pub fn pprint_expr(&mut self, expr: Gc<Expr>, width: usize) -> Result<String, Exception> {
+        Printer::run(self, expr, true, Some(width))
+    }
 
-                    for body_expr in body {
-                        write!(w, " ")?;
-                        recur(engine, w, body_expr.clone())?;
-                    }
+    pub fn print_limits(&self) -> PrintLimits {
+        self.print_limits
+    }
 
-                    write!(w, ")")
-                }
-                Expr::Map(m) => {
-                    write!(w, "#{{")?;
-                    for (idx, (k, v)) in m.iter().enumerate() {
-                        recur(engine, w, k.to_owned())?;
-                        write!(w, " ")?;
-                        recur(engine, w, v.to_owned())?;
-                        if idx != m.len() - 1 {
-                            write!(w, " ")?;
-                        }
-                    }
-                    write!(w, "}}")
-                }
-                Expr::Transient(t) => {
-                    let lock = t.borrow();
-                    match &*lock {
-                        Some(t) => {
-                            write!(w, "(transient/new ")?;
-                            let waaugh = Gc::new((**t).clone());
-                            recur(engine, w, waaugh)?;
-                            write!(w, ")")
-                        }
-                        None => write!(w, "<taken-transient>"),
-                    }
-                }
+    pub fn set_print_limits(&mut self, limits: PrintLimits) {
+        self.print_limits = limits;
+    }
+}
+
+enum PrintError {
+    Fmt(fmt::Error),
+    Exn(Exception),
+}
+
+impl From<fmt::Error> for PrintError {
+    fn from(e: fmt::Error) -> Self {
+        Self::Fmt(e)
+    }
+}
+
+impl From<Exception> for PrintError {
+    fn from(e: Exception) -> Self {
+        Self::Exn(e)
+    }
+}
+
+/// Shared guts of writing and printing.
+///
+/// The only way to get a cycle is to put a transient inside itself, so those are
+/// the only things we track. If the first pass finds any, we print again with
+/// datum labels, like `#0=(transient/new (#0#))`.
+struct Printer<'a> {
+    engine: &'a mut Engine,
+    out: String,
+    /// Write it to be read back in, or print it for people?
+    readable: bool,
+    limits: PrintLimits,
+    depth: usize,
+    /// Transients we're currently inside of.
+    path: Vec<*const Expr>,
+    /// Transients found inside themselves, and their labels once given one.
+    labels: HashMap<*const Expr, Option<usize>>,
+    next_label: usize,
+    /// If we're pretty-printing, the stream to lay out once we're done.
+    tokens: Option<Vec<Token>>,
+    /// Strings with more chars than this get cut off.
+    max_string: Option<usize>,
+}
+
+impl<'a> Printer<'a> {
+    fn run(
+        engine: &'a mut Engine,
+        expr: Gc<Expr>,
+        readable: bool,
+        width: Option<usize>,
+    ) -> Result<String, Exception> {
+        let limits = engine.print_limits;
+        let mut printer = Printer {
+            engine,
+            out: String::new(),
+            readable,
+            limits,
+            depth: 0,
+            path: Vec::new(),
+            labels: HashMap::new(),
+            next_label: 0,
+            tokens: width.map(|_| Vec::new()),
+            max_string: width,
+        };
+        printer.expr(&expr)?;
+        if !printer.labels.is_empty() {
+            printer.out.clear();
+            printer.tokens = width.map(|_| Vec::new());
+            printer.expr(&expr)?;
+        }
+        Ok(match (printer.tokens.take(), width) {
+            (Some(mut tokens), Some(width)) => {
+                tokens.push(Token::Text(std::mem::take(&mut printer.out)));
+                pretty::layout(&tokens, width)
             }
+            _ => printer.out,
+        })
+    }
+
+    /// Move the text written so far into the token stream.
+    fn flush(&mut self) -> Option<&mut Vec<Token>> {
+        let tokens = self.tokens.as_mut()?;
+        if !self.out.is_empty() {
+            tokens.push(Token::Text(std::mem::take(&mut self.out)));
         }
-        let mut writer = String::new();
-        recur(self, &mut writer, expr).unwrap();
-        Ok(writer)
+        Some(tokens)
     }
 
-    /// Print the expression to a string
-    /// in a nice and human-readable way.
-    pub fn print_expr(&mut self, expr: Gc<Expr>) -> Result<String, Exception> {
-        self.reify(&expr)?;
-        fn recur<W: Write>(
-            engine: &mut Engine,
-            w: &mut W,
-            expr: Gc<Expr>,
-        ) -> Result<(), fmt::Error> {
-            match &*expr {
-                Expr::Integer(i) => write!(w, "{}", i),
-                Expr::Float(f) => write!(w, "{:?}", f),
-                Expr::String(s) => {
-                    write!(w, "{}", BstrFmt(s))
+    /// Somewhere the pretty-printer may break the line.
+    fn line(&mut self) -> fmt::Result {
+        match self.flush() {
+            Some(tokens) => tokens.push(Token::Line),
+            None => self.out.push(' '),
+        }
+        Ok(())
+    }
+
+    /// Start a group of line breaks, returning where it is so its indent can be fixed up later.
+    fn begin(&mut self, indent: usize, fill: bool) -> usize {
+        match self.flush() {
+            Some(tokens) => {
+                tokens.push(Token::Begin { indent, fill });
+                tokens.len() - 1
+            }
+            None => 0,
+        }
+    }
+
+    fn set_indent(&mut self, group: usize, new_indent: usize) {
+        if let Some(Token::Begin { indent, .. }) = self
+            .tokens
+            .as_mut()
+            .and_then(|tokens| tokens.get_mut(group))
+        {
+            *indent = new_indent;
+        }
+    }
+
+    fn end(&mut self) {
+        if let Some(tokens) = self.flush() {
+            tokens.push(Token::End);
+        }
+    }
+
+    fn expr(&mut self, expr: &Gc<Expr>) -> Result<(), Exception> {
+        match self.expr_inner(expr) {
+            Ok(()) => Ok(()),
+            Err(PrintError::Exn(e)) => Err(e),
+            // Writing to a String can't fail
+            Err(PrintError::Fmt(e)) => panic!("error writing to a string: {}", e),
+        }
+    }
+
+    fn expr_inner(&mut self, expr: &Gc<Expr>) -> Result<(), PrintError> {
+        let w = &mut self.out;
+        match &**expr {
+            Expr::Integer(i) => write!(w, "{}", i)?,
+            Expr::Rational(n, d) => write!(w, "{}/{}", n, d)?,
+            Expr::Float(f) if self.readable && f.is_nan() => write!(w, "+nan.0")?,
+            Expr::Float(f) if self.readable && f.is_infinite() => {
+                write!(w, "{}inf.0", if *f > 0.0 { '+' } else { '-' })?
+            }
+            Expr::Float(f) => write!(w, "{:?}", f)?,
+            Expr::Symbol(sym) => write_symbol(self.engine, w, *sym)?,
+            Expr::Bool(b) => write!(w, "{}", b)?,
+            Expr::Nil => write!(w, "()")?,
+            Expr::String(s) => self.string(s)?,
+            Expr::Char(c) if self.readable => write_char_literal(w, *c)?,
+            Expr::Char(c) => write!(w, "{}", c)?,
+            Expr::Pair(..) | Expr::LazyPair(..) => self.list(expr)?,
+            Expr::SpecialForm { name, .. } => {
+                let kind = if self.readable {
+                    "special form"
+                } else {
+                    "native func"
+                };
+                if let Some(name) = self.engine.get_symbol_str(*name) {
+                    write!(w, "<{} {}>", kind, BstrFmt(name))?
+                } else {
+                    write!(w, "<anonymous {}>", kind)?
                 }
-                Expr::Bool(b) => {
-                    write!(w, "{}", b)
+            }
+            Expr::NativeProcedure { name, .. } => {
+                if let Some(name) = self.engine.get_symbol_str(*name) {
+                    write!(w, "<native proc {}>", BstrFmt(name))?
+                } else {
+                    write!(w, "<anonymous native proc>")?
                 }
-                Expr::Symbol(sym) => {
-                    if let Some(s) = engine.get_symbol_str(*sym) {
-                        write!(w, "{}", BstrFmt(s))
-                    } else {
-                        write!(w, "<unknown #{}>", sym)
-                    }
+            }
+            Expr::Procedure { .. } if !self.readable => write!(w, "<procedure>")?,
+            Expr::Procedure {
+                arg_spec,
+                body,
+                env,
+                ..
+            } => {
+                self.begin(pretty::BODY_INDENT, false);
+                if env.is_some() {
+                    write!(self.out, "(lambda ")?;
+                } else {
+                    write!(self.out, "(macro ")?;
                 }
-                Expr::Pair(..) | Expr::LazyPair(..) => {
-                    let (car, cdr) = engine.split_cons(expr).unwrap();
-                    fn write_list<W: Write>(
-                        engine: &mut Engine,
-                        w: &mut W,
-                        car: Gc<Expr>,
-                        cdr: Gc<Expr>,
-                    ) -> Result<(), fmt::Error> {
-                        recur(engine, w, car)?;
-                        match &*cdr {
-                            // Proper list's end, do nothing
-                            Expr::Nil => Ok(()),
-                            // Proper list, leave space for the next thing.
-                            Expr::Pair(..) | Expr::LazyPair(..) => {
-                                let (cdar, cddr) = engine.split_cons(cdr).unwrap();
-                                write!(w, " ")?;
-                                write_list(engine, w, cdar, cddr)
-                            }
-                            // Just a pair
-                            _ => {
-                                write!(w, " . ")?;
-                                recur(engine, w, cdr.to_owned())
-                            }
-                        }
-                    }
-
-                    write!(w, "(")?;
-                    write_list(engine, w, car, cdr)?;
-                    write!(w, ")")
+                self.expr_inner(arg_spec)?;
+                for body_expr in body {
+                    self.line()?;
+                    self.expr_inner(body_expr)?;
                 }
-                Expr::Nil => {
-                    write!(w, "()")
+                write!(self.out, ")")?;
+                self.end();
+            }
+            Expr::Map(m) => {
+                self.begin(2, false);
+                write!(self.out, "#{{")?;
+                self.depth += 1;
+                for (idx, (k, v)) in m.iter().enumerate() {
+                    if idx != 0 {
+                        self.line()?;
+                    }
+                    self.expr_inner(k)?;
+                    write!(self.out, " ")?;
+                    self.expr_inner(v)?;
                 }
-                Expr::SpecialForm { name, .. } => {
-                    if let Some(name) = engine.get_symbol_str(*name) {
-                        write!(w, "<native func {}>", BstrFmt(name))
-                    } else {
-                        write!(w, "<anonymous native func>")
+                self.depth -= 1;
+                write!(self.out, "}}")?;
+                self.end();
+            }
+            Expr::Record(ty, fields) => {
+                let group = self.begin(0, false);
+                let start = self.out.len();
+                write!(self.out, "#")?;
+                write_symbol(self.engine, &mut self.out, ty.name)?;
+                write!(self.out, "{{")?;
+                let indent = self.out[start..].chars().count();
+                self.set_indent(group, indent);
+                self.depth += 1;
+                for (idx, (field, v)) in ty.fields.iter().zip(fields).enumerate() {
+                    if idx != 0 {
+                        self.line()?;
                     }
+                    write_symbol(self.engine, &mut self.out, *field)?;
+                    write!(self.out, " ")?;
+                    self.expr_inner(v)?;
                 }
-                Expr::NativeProcedure { name, .. } => {
-                    if let Some(name) = engine.get_symbol_str(*name) {
-                        write!(w, "<native proc {}>", BstrFmt(name))
-                    } else {
-                        write!(w, "<anonymous native proc>")
+                self.depth -= 1;
+                write!(self.out, "}}")?;
+                self.end();
+            }
+            Expr::RecordType(ty) => {
+                write!(w, "<record type ")?;
+                write_symbol(self.engine, w, ty.name)?;
+                write!(w, ">")?;
+            }
+            Expr::Transient(t) => {
+                let ptr = &**expr as *const Expr;
+                if self.path.contains(&ptr) {
+                    match self.labels.entry(ptr).or_insert(None) {
+                        Some(label) => write!(w, "#{}#", label)?,
+                        // This is the first pass, we'll find out the label on the second
+                        None => write!(w, "#?#")?,
                     }
+                    return Ok(());
                 }
-                Expr::Procedure { .. } => {
-                    write!(w, "<procedure>")
+                if let Some(label @ None) = self.labels.get_mut(&ptr) {
+                    *label = Some(self.next_label);
+                    write!(w, "#{}=", self.next_label)?;
+                    self.next_label += 1;
                 }
-                Expr::Map(m) => {
-                    write!(w, "#{{")?;
-                    for (idx, (k, v)) in m.iter().enumerate() {
-                        recur(engine, w, k.to_owned())?;
-                        write!(w, " ")?;
-                        recur(engine, w, v.to_owned())?;
-                        if idx != m.len() - 1 {
-                            write!(w, " ")?;
-                        }
+
+                let inner = t.borrow().as_ref().map(|t| Gc::new((**t).clone()));
+                match inner {
+                    Some(inner) => {
+                        write!(self.out, "(transient/new ")?;
+                        self.path.push(ptr);
+                        self.depth += 1;
+                        self.expr_inner(&inner)?;
+                        self.depth -= 1;
+                        self.path.pop();
+                        write!(self.out, ")")?;
                     }
-                    write!(w, "}}")
+                    None => write!(self.out, "<taken-transient>")?,
                 }
-                Expr::Transient(t) => {
-                    let lock = t.borrow();
-                    match &*lock {
-                        Some(t) => {
-                            write!(w, "(transient/new ")?;
-                            let waaugh = Gc::new((**t).clone());
-                            recur(engine, w, waaugh)?;
-                            write!(w, ")")
-                        }
-                        None => write!(w, "<taken-transient>"),
-                    }
+            }
+        }
+        Ok(())
+    }
+
+    /// Print a list, forcing lazy bits only as far as the limits say.
+    fn list(&mut self, expr: &Gc<Expr>) -> Result<(), PrintError> {
+        let is_lazy = |expr: &Expr| matches!(expr, Expr::LazyPair(..));
+        if is_lazy(expr) && self.limits.depth.is_some_and(|max| self.depth >= max) {
+            write!(self.out, "...")?;
+            return Ok(());
+        }
+
+        let (inline, indent, fill) = self.list_style(expr);
+        self.begin(indent, fill);
+        write!(self.out, "(")?;
+        self.depth += 1;
+        let mut cell = expr.to_owned();
+        let mut count = 0;
+        loop {
+            let (car, cdr) = self.engine.split_cons(cell)?;
+            self.expr_inner(&car)?;
+            count += 1;
+            if cdr.is_nil() {
+                // Proper list's end, do nothing
+                break;
+            }
+            // Leave space for the next thing.
+            if count < inline {
+                write!(self.out, " ")?;
+            } else {
+                self.line()?;
+            }
+            match &*cdr {
+                Expr::LazyPair(..) if self.limits.length.is_some_and(|max| count >= max) => {
+                    write!(self.out, "...")?;
+                    break;
+                }
+                Expr::Pair(..) | Expr::LazyPair(..) => cell = cdr,
+                // Just a pair
+                _ => {
+                    write!(self.out, ". ")?;
+                    self.expr_inner(&cdr)?;
+                    break;
                 }
             }
         }
-        let mut writer = String::new();
-        recur(self, &mut writer, expr).unwrap();
-        Ok(writer)
+        self.depth -= 1;
+        write!(self.out, ")")?;
+        self.end();
+        Ok(())
+    }
+
+    /// How many elements of a list to keep on its first line, how far to indent
+    /// the rest, and whether to fill lines instead of putting each on its own.
+    fn list_style(&self, expr: &Expr) -> (usize, usize, bool) {
+        let head = match expr {
+            Expr::Pair(head, _) if self.tokens.is_some() => head,
+            // Lazy lists are always data
+            _ => return (1, 1, true),
+        };
+        let name = match &**head {
+            Expr::Symbol(sym) => match self.engine.get_symbol_str(*sym) {
+                Some(name) => String::from_utf8_lossy(name),
+                None => return (1, 1, true),
+            },
+            _ => return (1, 1, true),
+        };
+
+        if let Some((_, args)) = pretty::BODY_FORMS.iter().find(|(form, _)| *form == name) {
+            (args + 1, pretty::BODY_INDENT, false)
+        } else if name.chars().count() <= pretty::MAX_HANG {
+            // Line the args up under the first one
+            (2, name.chars().count() + 2, false)
+        } else {
+            (1, pretty::BODY_INDENT, false)
+        }
+    }
+
+    fn string(&mut self, s: &ByteStr) -> fmt::Result {
+        let cut = self.max_string.and_then(|max| nth_char_start(s, max));
+        let shown = &s[..cut.unwrap_or(s.len())];
+        if self.readable {
+            write!(self.out, "{:?}", BstrFmt(shown))?;
+            if cut.is_some() {
+                self.out.pop();
+                write!(self.out, "...\"")?;
+            }
+        } else {
+            write!(self.out, "{}", BstrFmt(shown))?;
+            if cut.is_some() {
+                write!(self.out, "...")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where the `n`th char starts, if there are more than `n`.
+///
+/// Bytes that aren't UTF-8 count as a char each.
+fn nth_char_start(bytes: &[u8], n: usize) -> Option<usize> {
+    let mut seen = 0;
+    let mut offset = 0;
+    for chunk in bytes.utf8_chunks() {
+        for (idx, _) in chunk.valid().char_indices() {
+            if seen == n {
+                return Some(offset + idx);
+            }
+            seen += 1;
+        }
+        offset += chunk.valid().len();
+        for _ in chunk.invalid() {
+            if seen == n {
+                return Some(offset);
+            }
+            seen += 1;
+            offset += 1;
+        }
+    }
+    None
+}
+
+/// Write a symbol's name, or a placeholder if somehow it doesn't have one.
+fn write_symbol(engine: &Engine, w: &mut impl Write, sym: Symbol) -> fmt::Result {
+    if let Some(s) = engine.get_symbol_str(sym) {
+        write!(w, "{}", BstrFmt(s))
+    } else {
+        write!(w, "<unknown #{}>", sym)
+    }
+}
+
+/// Names for characters that would be hard to read written out.
+pub const CHAR_NAMES: &[(&str, char)] = &[
+    ("space", ' '),
+    ("newline", '\n'),
+    ("tab", '\t'),
+    ("return", '\r'),
+    ("nul", '\0'),
+    ("alarm", '\x07'),
+    ("backspace", '\x08'),
+    ("delete", '\x7f'),
+    ("escape", '\x1b'),
+];
+
+/// Write a char as a `#\` literal.
+fn write_char_literal(w: &mut impl Write, c: char) -> fmt::Result {
+    if let Some((name, _)) = CHAR_NAMES.iter().find(|(_, named)| *named == c) {
+        write!(w, "#\\{}", name)
+    } else if c.is_control() || c.is_whitespace() {
+        write!(w, "#\\x{:X}", c as u32)
+    } else {
+        write!(w, "#\\{}", c)
     }
 }
 
@@ -241,12 +474,18 @@ pub struct BstrFmt<B: AsRef<[u8]>>(pub B);
 
 impl<B: AsRef<[u8]>> Display for BstrFmt<B> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for &b in self.0.as_ref() {
-            match b {
-                // Printable characters, some needed printing chars, and bell
-                0x20..=0x7e | b'\n' | b'\r' | 0x7 => f.write_char(b as char)?,
+        for chunk in self.0.as_ref().utf8_chunks() {
+            for c in chunk.valid().chars() {
+                match c {
+                    // Some needed printing chars, and bell
+                    '\n' | '\r' | '\x07' => f.write_char(c)?,
+                    c if c.is_control() => write!(f, "\\x{:02X}", c as u32)?,
+                    c => f.write_char(c)?,
+                }
+            }
+            for &ono in chunk.invalid() {
                 // pad it to a length of 2 with 0s if need be
-                ono => write!(f, "\\x{:02X}", ono)?,
+                write!(f, "\\x{:02X}", ono)?;
             }
         }
         Ok(())
@@ -255,19 +494,28 @@ impl<B: AsRef<[u8]>> Display for BstrFmt<B> {
 
 impl<B: AsRef<[u8]>> Debug for BstrFmt<B> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // This has to read back as the same string
         write!(f, "\"")?;
-        for &b in self.0.as_ref() {
-            match b {
-                b'\t' => write!(f, "\\t")?,
-                b'\r' => write!(f, "\\r")?,
-                b'\n' => write!(f, "\\n")?,
-                b'\"' => write!(f, "\\\"")?,
-                b'\x0b' => write!(f, "\\f")?,
-                b'\x0c' => write!(f, "\\v")?,
-                b'\0' => write!(f, "\\0")?,
-                0x20..=0x7e | 0x7 => f.write_char(b as char)?,
-                // pad it to a length of 2 with 0s if need be
-                ono => write!(f, "\\x{:02X}", ono)?,
+        for chunk in self.0.as_ref().utf8_chunks() {
+            for c in chunk.valid().chars() {
+                match c {
+                    '\t' => write!(f, "\\t")?,
+                    '\r' => write!(f, "\\r")?,
+                    '\n' => write!(f, "\\n")?,
+                    '"' => write!(f, "\\\"")?,
+                    '\\' => write!(f, "\\\\")?,
+                    '\x0b' => write!(f, "\\v")?,
+                    '\x0c' => write!(f, "\\f")?,
+                    '\0' => write!(f, "\\0")?,
+                    '\x07' => write!(f, "\\a")?,
+                    // pad it to a length of 2 with 0s if need be
+                    c if c.is_ascii_control() => write!(f, "\\x{:02X}", c as u32)?,
+                    c if c.is_control() => write!(f, "\\u{{{:X}}}", c as u32)?,
+                    c => f.write_char(c)?,
+                }
+            }
+            for &ono in chunk.invalid() {
+                write!(f, "\\x{:02X}", ono)?;
             }
         }
         write!(f, "\"")
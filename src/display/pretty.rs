@@ -0,0 +1,134 @@
+//! Laying out printed exprs to fit in a width.
+//!
+//! The printer emits a stream of text, line breaks and groups, Oppen-style.
+//! A group is printed flat if it fits in what's left of the line. Otherwise,
+//! its line breaks become newlines: all of them, or for `fill` groups, just the
+//! ones needed to keep the next bit on the line.
+
+/// Forms whose first few arguments stay on the first line, with the rest indented
+/// as a body underneath.
+pub const BODY_FORMS: &[(&str, usize)] = &[
+    ("define", 1),
+    ("defun", 2),
+    ("define-macro", 2),
+    ("defgeneric", 1),
+    ("defmethod", 3),
+    ("define-record", 2),
+    ("lambda", 1),
+    ("\\", 1),
+    ("macro", 1),
+    ("let", 1),
+    ("when", 1),
+    ("unless", 1),
+    ("match", 1),
+    ("switch", 1),
+    ("catch", 0),
+    ("cond", 0),
+    ("do", 0),
+];
+
+/// Symbols longer than this at the head of a call don't get their args hung after them.
+pub const MAX_HANG: usize = 10;
+
+/// How far body forms are indented.
+pub const BODY_INDENT: usize = 2;
+
+#[derive(Debug, Clone)]
+pub enum Token {
+    Text(String),
+    /// A space, or a newline if the group breaks.
+    Line,
+    /// Start a group. Its broken lines are indented `indent` past where it starts.
+    Begin {
+        indent: usize,
+        fill: bool,
+    },
+    End,
+}
+
+/// Lay tokens out to try and fit within `width` columns.
+pub fn layout(tokens: &[Token], width: usize) -> String {
+    let sizes = measure(tokens);
+
+    let mut out = String::new();
+    let mut col = 0;
+    // (indent, broken, fill)
+    let mut groups: Vec<(usize, bool, bool)> = Vec::new();
+    for (tok, size) in tokens.iter().zip(sizes) {
+        match tok {
+            Token::Text(s) => {
+                out.push_str(s);
+                col = match s.rfind('\n') {
+                    Some(nl) => s[nl + 1..].chars().count(),
+                    None => col + s.chars().count(),
+                };
+            }
+            Token::Begin { indent, fill } => {
+                groups.push((col + indent, col + size > width, *fill));
+            }
+            Token::End => {
+                groups.pop();
+            }
+            Token::Line => {
+                let (indent, broken, fill) = groups.last().copied().unwrap_or((0, false, false));
+                let newline = broken && (!fill || col + 1 + size > width);
+                if newline {
+                    out.push('\n');
+                    out.push_str(&" ".repeat(indent));
+                    col = indent;
+                } else {
+                    out.push(' ');
+                    col += 1;
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Index and position of a group's `Begin`, and of the last `Line` in it so far.
+type OpenGroup = (usize, usize, Option<(usize, usize)>);
+
+/// Find how wide each group and each stretch between line breaks is if printed flat,
+/// counting whatever text is stuck to its end.
+fn measure(tokens: &[Token]) -> Vec<usize> {
+    let width = |s: &str| s.chars().count();
+
+    // Text glued onto the end of each `End`, before there's anywhere to break
+    let mut trailing = vec![0; tokens.len()];
+    let mut run = 0;
+    for (idx, tok) in tokens.iter().enumerate().rev() {
+        match tok {
+            Token::Text(s) => run += width(s),
+            Token::End => trailing[idx] = run,
+            Token::Line | Token::Begin { .. } => run = 0,
+        }
+    }
+
+    let mut sizes = vec![0; tokens.len()];
+    let mut pos = 0;
+    let mut open: Vec<OpenGroup> = Vec::new();
+    for (idx, tok) in tokens.iter().enumerate() {
+        match tok {
+            Token::Text(s) => pos += width(s),
+            Token::Begin { .. } => open.push((idx, pos, None)),
+            Token::Line => {
+                if let Some((_, _, pending)) = open.last_mut() {
+                    if let Some((line, line_pos)) = pending.replace((idx, pos + 1)) {
+                        sizes[line] = pos - line_pos;
+                    }
+                }
+                pos += 1;
+            }
+            Token::End => {
+                if let Some((begin, begin_pos, pending)) = open.pop() {
+                    sizes[begin] = pos - begin_pos + trailing[idx];
+                    if let Some((line, line_pos)) = pending {
+                        sizes[line] = pos - line_pos + trailing[idx];
+                    }
+                }
+            }
+        }
+    }
+    sizes
+}
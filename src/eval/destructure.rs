@@ -131,6 +131,36 @@ impl Engine {
 
                     Ok(out)
                 }
+                // #{field spec ...} pulls fields out of a record by name
+                (Expr::Map(spec_map), Expr::Record(ty, fields)) => {
+                    let mut out = HashMap::with_capacity(spec_map.capacity());
+
+                    for (spec_k, spec_v) in spec_map.iter() {
+                        let idx = match &**spec_k {
+                            Expr::Symbol(field) => ty.field_idx(*field),
+                            _ => None,
+                        };
+                        match idx {
+                            Some(idx) => out.extend(recurse(
+                                engine,
+                                env.to_owned(),
+                                spec_v.to_owned(),
+                                fields[idx].to_owned(),
+                            )?),
+                            None => {
+                                let k = engine.write_expr(spec_k.to_owned())?;
+                                let v = engine.write_expr(val.to_owned())?;
+                                return Err(engine.make_err(
+                                    "record/no-field",
+                                    format!("cannot bind field {} of {}", k, v),
+                                    Some(spec_k.to_owned()),
+                                ));
+                            }
+                        }
+                    }
+
+                    Ok(out)
+                }
                 _ if spec == val => {
                     // Well, it matches ... just return an empty namespace
                     // TODO: this probably interacts weirdly with symbols that refer to other symbols
@@ -35,6 +35,21 @@ impl Engine {
             };
         }
     }
+
+    /// Call a function with already-evaluated args and get the result.
+    pub(crate) fn call(
+        &mut self,
+        env: Gc<GcCell<Namespace>>,
+        func: Gc<Expr>,
+        mut args: Vec<Gc<Expr>>,
+    ) -> EvalResult {
+        args.push(Expr::nil());
+        match self.apply_inner(env, func, args)? {
+            TailRec::Exit(it) => Ok(it),
+            TailRec::TailRecur(expr, env) => self.eval_inner(env, expr),
+        }
+    }
+
     /// Helper function that either returns Err(next expr) or Ok(final result).
     fn eval_rec(
         &mut self,
@@ -45,12 +60,16 @@ impl Engine {
             // Passthru literals unchanged
             Expr::Integer(_)
             | Expr::Float(_)
+            | Expr::Rational(..)
             | Expr::Bool(_)
             | Expr::Nil
             | Expr::String(_)
+            | Expr::Char(_)
             | Expr::SpecialForm { .. }
             | Expr::NativeProcedure { .. }
             | Expr::Procedure { .. }
+            | Expr::Record(..)
+            | Expr::RecordType(_)
             | Expr::Transient(_) => Ok(TailRec::Exit(expr)),
             // Lookup the symbol
             &Expr::Symbol(id) => {
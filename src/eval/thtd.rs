@@ -7,6 +7,7 @@ mod eq;
 mod exceptions;
 mod fmt;
 mod funcs;
+mod generics;
 mod io;
 mod math;
 mod misc;
@@ -14,9 +15,13 @@ mod pairs_lists;
 mod peg;
 mod profiling;
 mod quoting;
+mod random;
+mod reader;
+mod records;
 mod strings;
 mod symbols;
 mod transient;
+mod unicode;
 use control::*;
 use env::*;
 use eq::*;
@@ -32,6 +37,8 @@ use quoting::*;
 use strings::*;
 use symbols::*;
 
+pub use math::Num;
+
 use std::{fs, io::Write, path::Path};
 
 use gc::{Gc, GcCell, GcCellRef};
@@ -82,6 +89,23 @@ pub fn add_thtandard_library(engine: &mut Engine) {
         ("**", pow as _),
         ("mod", mod_ as _),
         ("log", log as _),
+        ("sqrt", sqrt as _),
+        ("cbrt", cbrt as _),
+        ("exp", exp as _),
+        ("sin", sin as _),
+        ("cos", cos as _),
+        ("tan", tan as _),
+        ("asin", asin as _),
+        ("acos", acos as _),
+        ("atan", atan as _),
+        ("atan2", atan2 as _),
+        ("hypot", hypot as _),
+        ("nan?", is_nan as _),
+        ("finite?", is_finite as _),
+        ("isqrt", isqrt as _),
+        ("gcd", gcd_ as _),
+        ("lcm", lcm as _),
+        ("modpow", modpow as _),
         ("<", lt as _),
         (">", gt as _),
         ("<=", le as _),
@@ -94,6 +118,10 @@ pub fn add_thtandard_library(engine: &mut Engine) {
         ("floor", floor as _),
         ("ceil", ceil as _),
         ("->inexact", to_inexact as _),
+        ("exact->inexact", to_inexact as _),
+        ("inexact->exact", to_exact as _),
+        ("numerator", numerator as _),
+        ("denominator", denominator as _),
         ("bitand", bitwise_and as _),
         ("bitor", bitwise_or as _),
         ("bitxor", bitwise_xor as _),
@@ -115,14 +143,44 @@ pub fn add_thtandard_library(engine: &mut Engine) {
         ("string/split", string_split as _),
         ("string/chars", string_chars as _),
         ("string/bytes", string_bytes as _),
+        ("string/compact", string_compact as _),
+        ("string/pinning?", string_pinning as _),
+        // unicode
+        ("string/codepoints", unicode::codepoints as _),
+        ("string/graphemes", unicode::graphemes as _),
+        ("string/len-chars", unicode::len_chars as _),
+        ("string/slice-chars", unicode::slice_chars as _),
+        ("string/upper", unicode::upper as _),
+        ("string/lower", unicode::lower as _),
+        ("string/nfc", unicode::nfc as _),
+        ("string/nfd", unicode::nfd as _),
+        ("string/nfkc", unicode::nfkc as _),
+        ("string/nfkd", unicode::nfkd as _),
+        ("string/utf8?", unicode::is_utf8 as _),
+        ("string/utf8-errors", unicode::utf8_error_positions as _),
+        ("char->integer", unicode::char2integer as _),
+        ("integer->char", unicode::integer2char as _),
         // formatting
         ("scanf", scanf as _),
         ("read", read as _),
         ("write", write as _),
         ("native-repr", native_repr as _),
+        ("pprint", pprint as _),
+        ("pprint->string", pprint2string as _),
+        ("print/limits", print_limits as _),
+        ("print/set-limits!", set_print_limits as _),
+        // reader macros
+        ("reader/set-prefix!", reader::set_prefix as _),
+        ("reader/set-dispatch!", reader::set_dispatch as _),
         // peg
         ("peg/compile", peg::compile as _),
         ("peg/match-inner", peg::match_ as _),
+        ("peg/match-verbose-inner", peg::match_verbose as _),
+        ("peg/find-inner", peg::find as _),
+        ("peg/find-all-inner", peg::find_all as _),
+        ("peg/replace-inner", peg::replace as _),
+        ("peg/split-inner", peg::split as _),
+        ("peg/disassemble-inner", peg::disassemble as _),
         // list/pair
         ("cons", cons as _),
         ("car", car as _),
@@ -135,12 +193,16 @@ pub fn add_thtandard_library(engine: &mut Engine) {
         // equality
         ("ptr-equal?", id_equal as _),
         ("equal?", equal as _),
+        // what the `equal?` generic falls back on
+        ("equal?/native", equal_native as _),
         ("pair?", is_pair as _),
         ("number?", is_number as _),
+        ("integer?", is_integer as _),
         ("exact?", is_exact as _),
         ("inexact?", is_inexact as _),
         ("nil?", is_nil as _),
         ("string?", is_string as _),
+        ("char?", is_char as _),
         ("symbol?", is_symbol as _),
         ("bool?", is_bool as _),
         ("map?", is_map as _),
@@ -148,6 +210,7 @@ pub fn add_thtandard_library(engine: &mut Engine) {
         ("procedure?", is_procedure as _),
         ("macro?", is_macro as _),
         ("transient?", is_transient as _),
+        ("record?", is_record as _),
         ("typeof", typeof_ as _),
         // collections
         ("map/new", collections::new_map as _),
@@ -165,6 +228,17 @@ pub fn add_thtandard_library(engine: &mut Engine) {
             "map/remove/clobbered",
             collections::map_remove_clobbered as _,
         ),
+        // generics
+        ("generic/new!", generics::new as _),
+        ("generic/add-method!", generics::add_method as _),
+        // records
+        ("record/type", records::make_type as _),
+        ("record/new", records::new as _),
+        ("record/is?", records::is_a as _),
+        ("record/get", records::get as _),
+        ("record/with", records::with as _),
+        ("record/type-of", records::type_of as _),
+        ("record/fields", records::fields as _),
         // transients
         ("transient/new", transient::new as _),
         ("transient/persist!", transient::persist as _),
@@ -183,12 +257,25 @@ pub fn add_thtandard_library(engine: &mut Engine) {
             transient::map::insert_clobbered as _,
         ),
         ("map/clear!", transient::map::clear as _),
+        ("sb/push!", transient::string::push as _),
+        ("sb/push-bytes!", transient::string::push_bytes as _),
+        ("sb/push-expr!", transient::string::push_expr as _),
+        ("sb/len", transient::string::len as _),
+        ("sb/clear!", transient::string::clear as _),
         // io
         ("io/read-file", read_file as _),
         // profiling
         ("profiling/start", start_profiling as _),
         ("profiling/check", check_profiling as _),
         ("profiling/stop", stop_profiling as _),
+        // random
+        ("random/new", random::new as _),
+        ("random/seed!", random::seed as _),
+        ("random/int", random::int as _),
+        ("random/float", random::float as _),
+        ("random/choice", random::choice as _),
+        ("random/shuffle", random::shuffle as _),
+        ("random/sample", random::sample as _),
         // etc
         ("reload-thtdlib", reload_thtd as _),
         ("timeit", timeit as _),
@@ -202,7 +289,20 @@ pub fn add_thtandard_library(engine: &mut Engine) {
         });
         thtdlib.borrow_mut().insert(symbol, handle);
     }
-    for (name, tail_func) in [("apply", apply as _)] {
+    for (name, constant) in [
+        ("pi", std::f64::consts::PI),
+        ("e", std::f64::consts::E),
+        ("inf", f64::INFINITY),
+        ("nan", f64::NAN),
+    ] {
+        let symbol = engine.intern_symbol(name);
+        thtdlib.borrow_mut().insert(symbol, Expr::float(constant));
+    }
+    for (name, tail_func) in [
+        ("apply", apply as _),
+        ("generic/dispatch", generics::dispatch as _),
+        ("generic/call-next", generics::call_next as _),
+    ] {
         let symbol = engine.intern_symbol(name);
         let handle = Gc::new(Expr::NativeProcedure {
             func: Err(tail_func),
@@ -315,7 +415,7 @@ pub fn bad_arg_type(engine: &mut Engine, arg: Gc<Expr>, idx: usize, want: &str)
     let msg = format!("in argument #{}, expected {}", idx, want);
     let data = Engine::list_to_sexp(&[
         Gc::new(Expr::Integer(idx as _)),
-        Gc::new(Expr::String(want.as_bytes().to_owned())),
+        Expr::string(want.as_bytes().to_owned()),
         arg,
     ]);
     engine.make_err("application/arg-type", msg, Some(data))
@@ -36,7 +36,10 @@ pub fn new_map(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Value]) ->
 
     let mut map = GcMap::new();
     for kv in args.chunks_exact(2) {
-        map.insert(kv[0].to_owned(), kv[1].to_owned());
+        map.insert(
+            Expr::compacted(kv[0].to_owned()),
+            Expr::compacted(kv[1].to_owned()),
+        );
     }
 
     Ok(Gc::new(Expr::Map(map)))
@@ -132,7 +135,10 @@ fn map_insert_inner(
     // step 3: n e v e r stabilize anything using them
     // 2021 "array_chunks" incident
     for kv in args[1..].chunks_exact(2) {
-        let clob = map.insert(kv[0].to_owned(), kv[1].to_owned());
+        let clob = map.insert(
+            Expr::compacted(kv[0].to_owned()),
+            Expr::compacted(kv[1].to_owned()),
+        );
         if let Some(ref mut clobbered) = clobbered {
             clobbered.push(clob.unwrap_or_else(|| engine.make_bool(false)));
         }
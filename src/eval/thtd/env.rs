@@ -14,6 +14,11 @@ pub fn define(
     let spec = args[0].to_owned();
     let val = engine.eval_inner(env.clone(), args[1].to_owned())?;
     let bindings = engine.destructure_assign(env.to_owned(), spec, val.to_owned())?;
+    // Definitions tend to stick around, so don't let them keep big strings alive
+    let bindings = bindings
+        .into_iter()
+        .map(|(sym, val)| (sym, Expr::compacted(val)))
+        .collect();
     env.borrow_mut().merge_from(bindings);
 
     Ok(TailRec::Exit(val))
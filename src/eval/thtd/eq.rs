@@ -52,8 +52,23 @@ where
     })
 }
 
-/// return whether things are equal w/o type juggling
-pub fn equal(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+/// return whether things are equal w/o type juggling,
+/// unless a method for the first thing has been added to the `equal?` generic
+pub fn equal(engine: &mut Engine, env: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_min_argc(engine, args, 1)?;
+    let lhs = &args[0];
+    match generics::dispatch_if_specialized(engine, env.to_owned(), "equal?", lhs, args)? {
+        Some(it) => Ok(it),
+        None => equal_native(engine, env, args),
+    }
+}
+
+/// `equal?` without looking for methods, for the `default` method to fall back on
+pub fn equal_native(
+    engine: &mut Engine,
+    _: Gc<GcCell<Namespace>>,
+    args: &[Gc<Expr>],
+) -> EvalResult {
     check_min_argc(engine, args, 1)?;
     let (lhs, rest) = args.split_first().unwrap();
     for rhs in rest {
@@ -69,8 +84,12 @@ pub fn equal(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -
 pub fn typeof_(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
     check_argc(engine, args, 1, 1)?;
 
-    let sym = args[0].type_name();
-    Ok(Gc::new(Expr::Symbol(engine.intern_symbol(sym))))
+    let sym = match &*args[0] {
+        // Records are known by the name of their type
+        Expr::Record(ty, _) => ty.name,
+        it => engine.intern_symbol(it.type_name()),
+    };
+    Ok(Gc::new(Expr::Symbol(sym)))
 }
 
 macro_rules! predicates {
@@ -89,10 +108,12 @@ macro_rules! predicates {
 predicates! {
     is_pair
     is_number
+    is_integer
     is_exact
     is_inexact
     is_nil
     is_string
+    is_char
     is_bool
     is_symbol
     is_map
@@ -100,4 +121,5 @@ predicates! {
     is_procedure
     is_macro
     is_transient
+    is_record
 }
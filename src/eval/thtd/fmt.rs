@@ -3,18 +3,19 @@
 use itertools::Itertools;
 
 use super::*;
+use crate::PrintLimits;
 
 pub fn scanf(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
     check_argc(engine, args, 2, 2)?;
 
     let mut data = if let Expr::String(s) = &*args[0] {
-        s.as_slice()
+        &s[..]
     } else {
         return Err(bad_arg_type(engine, args[0].to_owned(), 0, "string"));
     };
 
     let mut fmt = if let Expr::String(s) = &*args[1] {
-        s.as_slice()
+        &s[..]
     } else {
         return Err(bad_arg_type(engine, args[1].to_owned(), 1, "string"));
     };
@@ -56,8 +57,8 @@ pub fn scanf(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -
                                 "scanf/int-fail",
                                 format!("could not parse int {}: {}", parsable, e),
                                 Some(Engine::list_to_sexp(&[
-                                    Gc::new(Expr::String(s.to_owned())),
-                                    Gc::new(Expr::String(e.to_string().into_bytes())),
+                                    Expr::string(s.to_owned()),
+                                    Expr::string(e.to_string().into_bytes()),
                                 ])),
                             )
                         })?;
@@ -71,7 +72,7 @@ pub fn scanf(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -
                         let (s, rest) = data.split_at(end);
                         data = rest;
 
-                        datums_read.push(Gc::new(Expr::String(s.to_owned())));
+                        datums_read.push(Expr::string(s.to_owned()));
                     }
                     b'[' => {
                         let negate = fmt.starts_with(b"^");
@@ -102,7 +103,7 @@ pub fn scanf(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -
                             .unwrap_or_else(|| data.len());
                         let (s, rest) = data.split_at(ending_idx);
                         data = rest;
-                        datums_read.push(Gc::new(Expr::String(s.to_owned())));
+                        datums_read.push(Expr::string(s.to_owned()));
                     }
 
                     ono => {
@@ -110,7 +111,7 @@ pub fn scanf(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -
                         return Err(engine.make_err(
                             "scanf/bad-specifier",
                             format!("the format char {:?} is invalid", ono as char),
-                            Some(Gc::new(Expr::String(vec![ono]))),
+                            Some(Expr::string(vec![ono])),
                         ));
                     }
                 }
@@ -142,8 +143,8 @@ pub fn scanf(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -
                             c, next_c
                         ),
                         Some(Engine::list_to_sexp(&[
-                            Gc::new(Expr::String(c.to_string().into_bytes())),
-                            Gc::new(Expr::String(next_c.to_string().into_bytes())),
+                            Expr::string(c.to_string().into_bytes()),
+                            Expr::string(next_c.to_string().into_bytes()),
                         ])),
                     ));
                 } // else data has already been advanced yahoo
@@ -180,7 +181,7 @@ pub fn write(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -
     check_argc(engine, args, 1, 1)?;
     engine
         .write_expr(args[0].to_owned())
-        .map(|s| Gc::new(Expr::String(s.into_bytes())))
+        .map(|s| Expr::string(s.into_bytes()))
 }
 
 pub fn native_repr(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
@@ -188,3 +189,86 @@ pub fn native_repr(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Exp
     let repr = format!("{:?}", &args[0]);
     Ok(Expr::string(repr.into_bytes()))
 }
+
+/// Width to pretty-print to if nobody says otherwise.
+const PPRINT_WIDTH: usize = 80;
+
+fn pprint_args(engine: &mut Engine, args: &[Gc<Expr>]) -> Result<String, Exception> {
+    check_argc(engine, args, 1, 2)?;
+    let width = match args.get(1) {
+        Some(_) => match int_arg(engine, args, 1)? {
+            n if n > 0 => n as usize,
+            _ => {
+                return Err(bad_arg_type(
+                    engine,
+                    args[1].to_owned(),
+                    1,
+                    "positive integer",
+                ))
+            }
+        },
+        None => PPRINT_WIDTH,
+    };
+    engine.pprint_expr(args[0].to_owned(), width)
+}
+
+/// `(pprint expr [width])` writes it to stdout laid out over lines, and returns it.
+pub fn pprint(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    let out = pprint_args(engine, args)?;
+    println!("{}", out);
+    Ok(args[0].to_owned())
+}
+
+pub fn pprint2string(
+    engine: &mut Engine,
+    _: Gc<GcCell<Namespace>>,
+    args: &[Gc<Expr>],
+) -> EvalResult {
+    pprint_args(engine, args).map(Expr::string)
+}
+
+/// `(print/limits)` gets `(length depth)`, with `false` for no limit.
+pub fn print_limits(
+    engine: &mut Engine,
+    _: Gc<GcCell<Namespace>>,
+    args: &[Gc<Expr>],
+) -> EvalResult {
+    check_argc(engine, args, 0, 0)?;
+    let PrintLimits { length, depth } = engine.print_limits();
+    let limit = |it: Option<usize>| match it {
+        Some(it) => Expr::integer(it as i64),
+        None => Expr::bool(false),
+    };
+    Ok(Engine::list_to_sexp(&[limit(length), limit(depth)]))
+}
+
+/// `(print/set-limits! length depth)` sets how much of lazy lists get printed.
+pub fn set_print_limits(
+    engine: &mut Engine,
+    _: Gc<GcCell<Namespace>>,
+    args: &[Gc<Expr>],
+) -> EvalResult {
+    check_argc(engine, args, 2, 2)?;
+    let mut limits = [None; 2];
+    for (idx, limit) in limits.iter_mut().enumerate() {
+        if !engine.is_truthy(args[idx].to_owned()) {
+            continue;
+        }
+        match int_arg(engine, args, idx)? {
+            n if n >= 0 => *limit = Some(n as usize),
+            _ => {
+                return Err(bad_arg_type(
+                    engine,
+                    args[idx].to_owned(),
+                    idx,
+                    "non-negative integer or false",
+                ))
+            }
+        }
+    }
+    engine.set_print_limits(PrintLimits {
+        length: limits[0],
+        depth: limits[1],
+    });
+    Ok(Expr::nil())
+}
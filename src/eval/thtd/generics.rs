@@ -0,0 +1,215 @@
+//! The natives `defgeneric` and `defmethod` expand to.
+
+use crate::{
+    generic::{self, Generic},
+    Symbol, TailRec,
+};
+
+use super::*;
+
+fn name_arg(engine: &mut Engine, args: &[Gc<Expr>]) -> Result<Symbol, Exception> {
+    match &*args[0] {
+        Expr::Symbol(sym) => Ok(*sym),
+        _ => Err(bad_arg_type(engine, args[0].to_owned(), 0, "symbol")),
+    }
+}
+
+fn unknown_generic(engine: &mut Engine, name: Symbol) -> Exception {
+    let msg = format!(
+        "there is no generic function named {}",
+        String::from_utf8_lossy(engine.get_symbol_str(name).unwrap_or_default())
+    );
+    engine.make_err("generic/unknown", msg, Some(Expr::symbol(name)))
+}
+
+/// Call the first method, giving it a `next-method` that calls the rest.
+fn call_methods(
+    engine: &mut Engine,
+    env: Gc<GcCell<Namespace>>,
+    methods: &[Gc<Expr>],
+    args: Vec<Gc<Expr>>,
+) -> Result<TailRec, Exception> {
+    let (method, rest) = match methods.split_first() {
+        Some(it) => it,
+        None => {
+            return Err(engine.make_err(
+                "generic/no-method",
+                "no method applies to these arguments",
+                Some(Engine::list_to_sexp(&args)),
+            ))
+        }
+    };
+
+    // (lambda args (generic/call-next 'rest 'args args))
+    let quote = Expr::symbol(engine.intern_symbol("quote"));
+    let quoted = |expr: Gc<Expr>| Engine::list_to_sexp(&[quote.to_owned(), expr]);
+    let next_args = Expr::symbol(engine.intern_symbol("args"));
+    let body = Engine::list_to_sexp(&[
+        Expr::symbol(engine.intern_symbol("generic/call-next")),
+        quoted(Engine::list_to_sexp(rest)),
+        quoted(Engine::list_to_sexp(&args)),
+        next_args.to_owned(),
+    ]);
+    let next_method = Gc::new(Expr::Procedure {
+        arg_spec: next_args,
+        body: vec![body],
+        env: Some(env.to_owned()),
+        name: Some(engine.intern_symbol("next-method")),
+    });
+
+    let mut full_args = Vec::with_capacity(args.len() + 2);
+    full_args.push(next_method);
+    full_args.extend(args);
+    full_args.push(Expr::nil());
+    engine.apply_inner(env, method.to_owned(), full_args)
+}
+
+/// `(generic/new! name [dispatcher])`
+///
+/// Redefining a generic keeps its methods.
+pub fn new(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 1, 2)?;
+    let name = name_arg(engine, args)?;
+    let dispatcher = match args.get(1) {
+        Some(it) if it.is_callable() => Some(it.to_owned()),
+        Some(it) if !engine.is_truthy(it.to_owned()) => None,
+        Some(it) => return Err(bad_arg_type(engine, it.to_owned(), 1, "callable or falsy")),
+        None => None,
+    };
+
+    engine.generics.entry(name).or_default().dispatcher = dispatcher;
+    Ok(args[0].to_owned())
+}
+
+/// `(generic/add-method! name type method)`
+///
+/// If `type` is a symbol bound to a record type, the method is for that record type.
+/// Otherwise it's for whatever dispatches to `type`.
+pub fn add_method(
+    engine: &mut Engine,
+    env: Gc<GcCell<Namespace>>,
+    args: &[Gc<Expr>],
+) -> EvalResult {
+    check_argc(engine, args, 3, 3)?;
+    let name = name_arg(engine, args)?;
+    if !args[2].is_callable() {
+        return Err(bad_arg_type(engine, args[2].to_owned(), 2, "callable"));
+    }
+
+    let key = match &*args[1] {
+        Expr::Symbol(sym) => match env.borrow().lookup(*sym) {
+            Some(ty) if matches!(&*ty, Expr::RecordType(_)) => ty,
+            _ => args[1].to_owned(),
+        },
+        _ => args[1].to_owned(),
+    };
+    match engine.generics.get_mut(&name) {
+        Some(generic) => generic.add_method(key, args[2].to_owned()),
+        None => return Err(unknown_generic(engine, name)),
+    }
+    Ok(args[0].to_owned())
+}
+
+/// `(generic/dispatch name args)`
+pub fn dispatch(
+    engine: &mut Engine,
+    env: Gc<GcCell<Namespace>>,
+    args: &[Gc<Expr>],
+) -> Result<TailRec, Exception> {
+    check_argc(engine, args, 2, 2)?;
+    let name = name_arg(engine, args)?;
+    let call_args = match engine.sexp_to_list(args[1].to_owned())? {
+        Some(it) => it,
+        None => return Err(bad_arg_type(engine, args[1].to_owned(), 1, "list")),
+    };
+
+    let dispatcher = match engine.generics.get(&name) {
+        Some(generic) => generic.dispatcher.to_owned(),
+        None => return Err(unknown_generic(engine, name)),
+    };
+    let key = match (dispatcher, call_args.first()) {
+        (Some(dispatcher), _) => engine.call(env.to_owned(), dispatcher, call_args.to_owned())?,
+        (None, Some(first)) => generic::type_key(engine, first),
+        (None, None) => {
+            return Err(engine.make_err(
+                "generic/no-args",
+                "need an argument to dispatch on",
+                Some(args[0].to_owned()),
+            ))
+        }
+    };
+
+    // Take it out so we can look up symbols while we're in it
+    let mut generic: Generic = engine.generics.remove(&name).unwrap_or_default();
+    let methods = generic.methods_for(engine, key);
+    engine.generics.insert(name, generic);
+
+    call_methods(engine, env, &methods, call_args)
+}
+
+/// If the generic `name` has a method for `value` more specific than its `default`,
+/// call the generic with these args.
+///
+/// This lets natives like `equal?` stay fast until someone adds a method to them.
+pub fn dispatch_if_specialized(
+    engine: &mut Engine,
+    env: Gc<GcCell<Namespace>>,
+    name: &str,
+    value: &Expr,
+    args: &[Gc<Expr>],
+) -> Result<Option<Gc<Expr>>, Exception> {
+    let name = engine.intern_symbol(name);
+    let mut generic = match engine.generics.remove(&name) {
+        Some(it) => it,
+        None => return Ok(None),
+    };
+    let key = generic::type_key(engine, value);
+    let specialized = generic.specific_method_count(engine, key) > 0;
+    engine.generics.insert(name, generic);
+    if !specialized {
+        return Ok(None);
+    }
+
+    let dispatch_args = [Expr::symbol(name), Engine::list_to_sexp(args)];
+    match dispatch(engine, env, &dispatch_args)? {
+        TailRec::Exit(it) => Ok(Some(it)),
+        TailRec::TailRecur(expr, env) => engine.eval_inner(env, expr).map(Some),
+    }
+}
+
+/// `(generic/call-next methods original-args args)` backs `next-method`.
+///
+/// With no args, the next method gets the same args as this one did.
+pub fn call_next(
+    engine: &mut Engine,
+    env: Gc<GcCell<Namespace>>,
+    args: &[Gc<Expr>],
+) -> Result<TailRec, Exception> {
+    check_argc(engine, args, 3, 3)?;
+    let lists = args
+        .iter()
+        .map(|arg| engine.sexp_to_list(arg.to_owned()))
+        .collect::<Result<Vec<_>, _>>()?;
+    match lists.as_slice() {
+        [Some(methods), Some(original), Some(passed)] => {
+            let call_args = if passed.is_empty() {
+                original.to_owned()
+            } else {
+                passed.to_owned()
+            };
+            if methods.is_empty() {
+                return Err(engine.make_err(
+                    "generic/no-next-method",
+                    "there is no less specific method to call",
+                    Some(Engine::list_to_sexp(&call_args)),
+                ));
+            }
+            call_methods(engine, env, methods, call_args)
+        }
+        _ => Err(engine.make_err(
+            "generic/call-next/bad-args",
+            "expected three lists",
+            Some(Engine::list_to_sexp(args)),
+        )),
+    }
+}
@@ -16,13 +16,13 @@ pub fn read_file(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>
         std::fs::read(path)
     })();
     match res {
-        Ok(s) => EvalResult::Ok(Gc::new(Expr::String(s))),
+        Ok(s) => EvalResult::Ok(Expr::string(s)),
         Err(oh_no) => {
             let msg = oh_no.to_string();
             EvalResult::Err(engine.make_err(
                 "read-file/io-err",
                 format!("os-level error: {}", &msg),
-                Some(Gc::new(Expr::String(msg.into_bytes()))),
+                Some(Expr::string(msg.into_bytes())),
             ))
         }
     }
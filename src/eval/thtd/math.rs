@@ -1,28 +1,38 @@
 //! Mathematics? That's for eggheads!
 //! Also boolean operators.
+use std::{cmp::Ordering, convert::TryFrom};
+
 use crate::{eval::TailRec, Value};
 
 use super::*;
 
 use paste::paste;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub enum Num {
     Int(i64),
+    /// Always in lowest terms, with a denominator greater than 1.
+    Ratio(i64, i64),
     Float(f64),
 }
 
 impl Num {
     pub fn to_expr(self) -> Value {
-        Gc::new(match self {
+        Gc::new(self.to_bare_expr())
+    }
+
+    pub fn to_bare_expr(self) -> Expr {
+        match self {
             Num::Int(int) => Expr::Integer(int),
+            Num::Ratio(numer, denom) => Expr::Rational(numer, denom),
             Num::Float(float) => Expr::Float(float),
-        })
+        }
     }
 
     pub fn from_expr(engine: &mut Engine, expr: Gc<Expr>, idx: usize) -> Result<Num, Exception> {
         Ok(match &*expr {
             Expr::Integer(int) => Num::Int(*int),
+            Expr::Rational(numer, denom) => Num::Ratio(*numer, *denom),
             Expr::Float(float) => Num::Float(*float),
             // bools convert to ints!
             Expr::Bool(b) => Num::Int(*b as _),
@@ -31,31 +41,91 @@ impl Num {
         })
     }
 
+    /// Make an exact number out of a numerator and denominator, putting it in lowest terms.
+    ///
+    /// If the result doesn't fit in an i64 it turns into a float instead.
+    /// The denominator must not be zero.
+    pub fn ratio(numer: i128, denom: i128) -> Num {
+        debug_assert_ne!(denom, 0, "tried to make a ratio with a zero denominator");
+        let divisor = gcd(numer, denom) * denom.signum();
+        let (numer, denom) = (numer / divisor, denom / divisor);
+        match (i64::try_from(numer), i64::try_from(denom)) {
+            (Ok(numer), Ok(1)) => Num::Int(numer),
+            (Ok(numer), Ok(denom)) => Num::Ratio(numer, denom),
+            _ => Num::Float(numer as f64 / denom as f64),
+        }
+    }
+
+    /// Get the numerator and denominator of this, or `None` if it's inexact.
+    pub fn as_exact(self) -> Option<(i128, i128)> {
+        match self {
+            Num::Int(int) => Some((int as _, 1)),
+            Num::Ratio(numer, denom) => Some((numer as _, denom as _)),
+            Num::Float(_) => None,
+        }
+    }
+
     pub fn as_float(self) -> f64 {
         match self {
             Num::Int(int) => int as _,
+            Num::Ratio(numer, denom) => numer as f64 / denom as f64,
             Num::Float(float) => float,
         }
     }
 
+    pub fn is_zero(self) -> bool {
+        self.as_float() == 0.0
+    }
+
+    pub fn abs(self) -> Num {
+        if self < Num::Int(0) {
+            -self
+        } else {
+            self
+        }
+    }
+
     pub fn to_string(self) -> String {
         match self {
             Num::Int(int) => int.to_string(),
+            Num::Ratio(numer, denom) => format!("{}/{}", numer, denom),
             Num::Float(float) => float.to_string(),
         }
     }
 }
 
+/// Greatest common divisor, always non-negative.
+pub fn gcd(a: i128, b: i128) -> i128 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    a
+}
+
+/// Numbers operate on each other like so:
+/// - Two ints stay ints (and wrap on overflow, like they always have).
+/// - Anything with a float in it becomes a float.
+/// - Otherwise, it's done exactly with ratios.
 macro_rules! num_ops {
-    (($trait:path => $name:ident $op:tt)) => {
+    (($trait:path => $name:ident $op:tt |$ln:ident, $ld:ident, $rn:ident, $rd:ident| $exact:expr)) => {
         impl $trait for Num {
             type Output = Self;
             fn $name(self, rhs: Self) -> Self::Output {
                 match (self, rhs) {
                     (Num::Int(l), Num::Int(r)) => paste! { Num::Int(l.[< wrapping_ $name >](r)) },
-                    (Num::Float(l), Num::Int(r)) => Num::Float(l $op r as f64),
-                    (Num::Int(l), Num::Float(r)) => Num::Float(l as f64 $op r),
-                    (Num::Float(l), Num::Float(r)) => Num::Float(l $op r),
+                    (Num::Float(_), _) | (_, Num::Float(_)) => {
+                        Num::Float(self.as_float() $op rhs.as_float())
+                    }
+                    _ => {
+                        // neither side is a float so these are fine to unwrap
+                        let ($ln, $ld) = self.as_exact().unwrap();
+                        let ($rn, $rd) = rhs.as_exact().unwrap();
+                        let (numer, denom) = $exact;
+                        Num::ratio(numer, denom)
+                    }
                 }
             }
         }
@@ -67,11 +137,48 @@ macro_rules! num_ops {
 }
 
 num_ops! {
-    (std::ops::Add => add +)
-    (std::ops::Sub => sub -)
-    (std::ops::Mul => mul *)
-    (std::ops::Div => div /)
-    (std::ops::Rem => rem %)
+    (std::ops::Add => add + |ln, ld, rn, rd| (ln * rd + rn * ld, ld * rd))
+    (std::ops::Sub => sub - |ln, ld, rn, rd| (ln * rd - rn * ld, ld * rd))
+    (std::ops::Mul => mul * |ln, ld, rn, rd| (ln * rn, ld * rd))
+    (std::ops::Rem => rem % |ln, ld, rn, rd| ((ln * rd) % (rn * ld), ld * rd))
+}
+
+/// Dividing two ints gets you an exact ratio instead of truncating.
+impl std::ops::Div for Num {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self::Output {
+        match (self.as_exact(), rhs.as_exact()) {
+            (Some((ln, ld)), Some((rn, rd))) => Num::ratio(ln * rd, ld * rn),
+            _ => Num::Float(self.as_float() / rhs.as_float()),
+        }
+    }
+}
+
+impl std::ops::Neg for Num {
+    type Output = Self;
+    fn neg(self) -> Self::Output {
+        match self {
+            Num::Int(int) => Num::Int(int.wrapping_neg()),
+            Num::Ratio(numer, denom) => Num::ratio(-(numer as i128), denom as _),
+            Num::Float(float) => Num::Float(-float),
+        }
+    }
+}
+
+impl PartialEq for Num {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Num {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self.as_exact(), other.as_exact()) {
+            // denominators are always positive so this is ok
+            (Some((ln, ld)), Some((rn, rd))) => Some((ln * rd).cmp(&(rn * ld))),
+            _ => self.as_float().partial_cmp(&other.as_float()),
+        }
+    }
 }
 
 pub fn add(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
@@ -88,11 +195,7 @@ pub fn sub(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) ->
     let mut difference = Num::from_expr(engine, args[0].to_owned(), 0)?;
 
     if args.len() == 1 {
-        return Ok(match difference {
-            Num::Int(int) => Num::Int(-int),
-            Num::Float(float) => Num::Float(-float),
-        }
-        .to_expr());
+        return Ok((-difference).to_expr());
     }
 
     for (idx, arg) in args.iter().enumerate().skip(1) {
@@ -116,24 +219,20 @@ pub fn div(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) ->
     let mut quotient = Num::from_expr(engine, args[0].to_owned(), 0)?;
 
     if args.len() == 1 {
-        let q = match quotient {
-            Num::Int(i) => i as f64,
-            Num::Float(f) => f,
-        };
-        return if q == 0.0 {
+        return if quotient.is_zero() {
             Err(engine.make_err(
                 "arithmetic/div-by-zero",
-                format!("cannot divide {} by zero", q),
-                Some(Gc::new(Expr::Float(q))),
+                "cannot divide 1 by zero",
+                Some(quotient.to_expr()),
             ))
         } else {
-            Ok(Num::Float(q.recip()).to_expr())
+            Ok((Num::Int(1) / quotient).to_expr())
         };
     }
 
     for (idx, arg) in args.iter().enumerate().skip(1) {
         let rhs = Num::from_expr(engine, arg.to_owned(), idx)?;
-        if rhs.as_float() == 0.0 {
+        if rhs.is_zero() {
             return Err(engine.make_err(
                 "arithmetic/div-by-zero",
                 format!("cannot divide {} by zero", rhs.to_string()),
@@ -152,7 +251,7 @@ pub fn rem(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) ->
     let dividend = Num::from_expr(engine, args[0].to_owned(), 0)?;
     let divisor = Num::from_expr(engine, args[1].to_owned(), 1)?;
 
-    if divisor.as_float() == 0.0 {
+    if divisor.is_zero() {
         return Err(engine.make_err(
             "arithmetic/div-by-zero",
             format!("cannot divide {} by zero", divisor.to_string()),
@@ -173,20 +272,30 @@ pub fn pow(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) ->
     }
     let rhs = Num::from_expr(engine, args[1].to_owned(), 0)?;
     let res = match (lhs, rhs) {
-        (Num::Int(l), Num::Int(r)) => {
-            if r > 0 {
-                Num::Int(l.pow(r as u32))
-            } else {
-                Num::Float((l as f64).powi(r as i32))
-            }
-        }
+        (Num::Int(_) | Num::Ratio(..), Num::Int(r)) => exact_pow(lhs, r),
         (Num::Float(l), Num::Int(r)) => Num::Float(l.powi(r as i32)),
-        (Num::Int(l), Num::Float(r)) => Num::Float((l as f64).powf(r)),
-        (Num::Float(l), Num::Float(r)) => Num::Float(l.powf(r)),
+        _ => Num::Float(lhs.as_float().powf(rhs.as_float())),
     };
     Ok(res.to_expr())
 }
 
+/// Raise an exact number to an integer power, falling back to floats if it gets too big
+/// (or if it's 0 to a negative power).
+fn exact_pow(base: Num, exp: i64) -> Num {
+    // ok to unwrap because the caller only passes exact numbers
+    let (numer, denom) = base.as_exact().unwrap();
+    let pow = |x: i128| {
+        u32::try_from(exp.unsigned_abs())
+            .ok()
+            .and_then(|exp| x.checked_pow(exp))
+    };
+    match (pow(numer), pow(denom)) {
+        (Some(numer), Some(denom)) if exp >= 0 => Num::ratio(numer, denom),
+        (Some(numer), Some(denom)) if numer != 0 => Num::ratio(denom, numer),
+        _ => Num::Float(base.as_float().powf(exp as f64)),
+    }
+}
+
 pub fn log(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
     check_argc(engine, args, 1, 2)?;
 
@@ -199,13 +308,181 @@ pub fn log(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) ->
     Ok(Num::Float(rhs.as_float().log(lhs.as_float())).to_expr())
 }
 
+macro_rules! float_fns {
+    ($name:ident) => {
+        pub fn $name(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+            check_argc(engine, args, 1, 1)?;
+
+            let x = Num::from_expr(engine, args[0].to_owned(), 0)?;
+            Ok(Num::Float(x.as_float().$name()).to_expr())
+        }
+    };
+    ($head:tt $($tail:tt)*) => {
+        float_fns! { $head }
+        float_fns! { $($tail)* }
+    };
+}
+
+float_fns! {
+    exp sin cos tan asin acos atan cbrt
+}
+
+/// Exact if given a perfect square (or a ratio of them), inexact otherwise.
+pub fn sqrt(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+
+    let x = Num::from_expr(engine, args[0].to_owned(), 0)?;
+    if let Some((numer, denom)) = x.as_exact() {
+        if numer >= 0 {
+            let (numer_root, denom_root) = (isqrt_inner(numer), isqrt_inner(denom));
+            if numer_root * numer_root == numer && denom_root * denom_root == denom {
+                return Ok(Num::ratio(numer_root, denom_root).to_expr());
+            }
+        }
+    }
+    Ok(Num::Float(x.as_float().sqrt()).to_expr())
+}
+
+pub fn atan2(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 2, 2)?;
+
+    let y = Num::from_expr(engine, args[0].to_owned(), 0)?;
+    let x = Num::from_expr(engine, args[1].to_owned(), 1)?;
+    Ok(Num::Float(y.as_float().atan2(x.as_float())).to_expr())
+}
+
+/// Length of the vector with all the arguments as components.
+pub fn hypot(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_min_argc(engine, args, 1)?;
+
+    let mut len = 0.0f64;
+    for (idx, arg) in args.iter().enumerate() {
+        let x = Num::from_expr(engine, arg.to_owned(), idx)?;
+        len = len.hypot(x.as_float());
+    }
+    Ok(Num::Float(len).to_expr())
+}
+
+pub fn is_nan(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+
+    let x = Num::from_expr(engine, args[0].to_owned(), 0)?;
+    Ok(engine.make_bool(x.as_float().is_nan()))
+}
+
+pub fn is_finite(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+
+    let x = Num::from_expr(engine, args[0].to_owned(), 0)?;
+    Ok(engine.make_bool(x.as_float().is_finite()))
+}
+
+/// Read an integer argument, with the same coercions as any other number.
+pub fn int_arg(engine: &mut Engine, args: &[Gc<Expr>], idx: usize) -> Result<i64, Exception> {
+    match Num::from_expr(engine, args[idx].to_owned(), idx)? {
+        Num::Int(int) => Ok(int),
+        _ => Err(bad_arg_type(engine, args[idx].to_owned(), idx, "integer")),
+    }
+}
+
+/// Floor of the square root of a non-negative number.
+fn isqrt_inner(x: i128) -> i128 {
+    if x < 2 {
+        return x;
+    }
+    // Newton's method, starting from above so it only goes down
+    let mut guess = x / 2 + 1;
+    loop {
+        let next = (guess + x / guess) / 2;
+        if next >= guess {
+            return guess;
+        }
+        guess = next;
+    }
+}
+
+pub fn isqrt(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+
+    let x = int_arg(engine, args, 0)?;
+    if x < 0 {
+        return Err(bad_arg_type(
+            engine,
+            args[0].to_owned(),
+            0,
+            "non-negative integer",
+        ));
+    }
+    Ok(Num::Int(isqrt_inner(x as _) as _).to_expr())
+}
+
+pub fn gcd_(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    let mut out = 0;
+    for idx in 0..args.len() {
+        out = gcd(out, int_arg(engine, args, idx)? as _);
+    }
+    Ok(Num::ratio(out, 1).to_expr())
+}
+
+pub fn lcm(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    let mut out = 1i128;
+    for idx in 0..args.len() {
+        let x = int_arg(engine, args, idx)? as i128;
+        if x == 0 {
+            return Ok(Num::Int(0).to_expr());
+        }
+        // this can only overflow if it's already too big for an i64, so just make it a float
+        out = match (out / gcd(out, x)).checked_mul(x.abs()) {
+            Some(it) => it,
+            None => return Ok(Num::Float(out as f64 * x.abs() as f64).to_expr()),
+        };
+    }
+    Ok(Num::ratio(out, 1).to_expr())
+}
+
+/// `(modpow base exp modulus)`, or `base^exp mod modulus` without the big intermediate number.
+pub fn modpow(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 3, 3)?;
+
+    let base = int_arg(engine, args, 0)? as i128;
+    let exp = int_arg(engine, args, 1)?;
+    let modulus = int_arg(engine, args, 2)? as i128;
+    if exp < 0 {
+        return Err(bad_arg_type(
+            engine,
+            args[1].to_owned(),
+            1,
+            "non-negative integer",
+        ));
+    }
+    if modulus == 0 {
+        return Err(engine.make_err(
+            "arithmetic/div-by-zero",
+            "cannot take a modulus of zero",
+            Some(args[2].to_owned()),
+        ));
+    }
+
+    let mut out = 1i128.rem_euclid(modulus);
+    let mut base = base.rem_euclid(modulus);
+    let mut exp = exp;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            out = (out * base).rem_euclid(modulus);
+        }
+        base = (base * base).rem_euclid(modulus);
+        exp >>= 1;
+    }
+    Ok(Num::ratio(out, 1).to_expr())
+}
+
 pub fn mod_(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
     check_argc(engine, args, 2, 2)?;
 
     let dividend = Num::from_expr(engine, args[0].to_owned(), 0)?;
     let divisor = Num::from_expr(engine, args[1].to_owned(), 1)?;
 
-    if divisor.as_float() == 0.0 {
+    if divisor.is_zero() {
         return Err(engine.make_err(
             "arithmetic/div-by-zero",
             format!("cannot divide {} by zero", divisor.to_string()),
@@ -215,9 +492,17 @@ pub fn mod_(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) ->
 
     let rem = match (dividend, divisor) {
         (Num::Int(l), Num::Int(r)) => Num::Int(l.rem_euclid(r)),
-        (Num::Float(l), Num::Int(r)) => Num::Float(l.rem_euclid(r as _)),
-        (Num::Int(l), Num::Float(r)) => Num::Float((l as f64).rem_euclid(r)),
-        (Num::Float(l), Num::Float(r)) => Num::Float(l.rem_euclid(r)),
+        (Num::Float(_), _) | (_, Num::Float(_)) => {
+            Num::Float(dividend.as_float().rem_euclid(divisor.as_float()))
+        }
+        _ => {
+            let rem = dividend % divisor;
+            if rem < Num::Int(0) {
+                rem + divisor.abs()
+            } else {
+                rem
+            }
+        }
     };
     Ok(rem.to_expr())
 }
@@ -228,10 +513,12 @@ macro_rules! rounders {
             check_argc(engine, args, 1, 1)?;
 
             let roundee = Num::from_expr(engine, args[0].to_owned(), 0)?;
-            Ok(if let Num::Float(f) = roundee {
-                Num::Int(f.$name() as i64)
-            } else {
-                roundee // keep the int
+            Ok(match roundee {
+                Num::Float(f) => Num::Int(f.$name() as i64),
+                Num::Ratio(numer, denom) => paste! {
+                    Num::ratio([< ratio_ $name >](numer as _, denom as _), 1)
+                },
+                Num::Int(_) => roundee, // keep the int
             }.to_expr())
         }
     };
@@ -245,6 +532,26 @@ rounders! {
     round trunc floor ceil
 }
 
+// These all assume the denominator is positive, which it always is for a `Num::Ratio`.
+fn ratio_trunc(numer: i128, denom: i128) -> i128 {
+    numer / denom
+}
+fn ratio_floor(numer: i128, denom: i128) -> i128 {
+    numer.div_euclid(denom)
+}
+fn ratio_ceil(numer: i128, denom: i128) -> i128 {
+    -(-numer).div_euclid(denom)
+}
+/// Round half away from zero, same as `f64::round`.
+fn ratio_round(numer: i128, denom: i128) -> i128 {
+    let trunc = numer / denom;
+    if 2 * (numer % denom).abs() >= denom {
+        trunc + numer.signum()
+    } else {
+        trunc
+    }
+}
+
 pub fn to_inexact(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
     check_argc(engine, args, 1, 1)?;
 
@@ -252,6 +559,109 @@ pub fn to_inexact(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr
     Ok(Gc::new(Expr::Float(it.as_float())))
 }
 
+pub fn to_exact(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+
+    let it = Num::from_expr(engine, args[0].to_owned(), 0)?;
+    match it {
+        Num::Float(f) => match float_to_exact(f) {
+            Some(exact) => Ok(exact.to_expr()),
+            None => Err(engine.make_err(
+                "arithmetic/no-exact",
+                format!("{} has no exact representation", f),
+                Some(args[0].to_owned()),
+            )),
+        },
+        exact => Ok(exact.to_expr()),
+    }
+}
+
+/// Find the exact value of a float, if it's finite and fits into an int or ratio.
+fn float_to_exact(f: f64) -> Option<Num> {
+    if !f.is_finite() {
+        return None;
+    }
+
+    // f = sign * mantissa * 2^exponent
+    let bits = f.to_bits();
+    let sign = if bits >> 63 == 0 { 1 } else { -1 };
+    let raw_exponent = ((bits >> 52) & 0x7ff) as i32;
+    let raw_mantissa = (bits & 0xf_ffff_ffff_ffff) as i128;
+    let (mut mantissa, mut exponent) = if raw_exponent == 0 {
+        // subnormal
+        (raw_mantissa, -1074)
+    } else {
+        (raw_mantissa | 1 << 52, raw_exponent - 1075)
+    };
+    if mantissa == 0 {
+        return Some(Num::Int(0));
+    }
+    while exponent < 0 && mantissa % 2 == 0 {
+        mantissa /= 2;
+        exponent += 1;
+    }
+
+    let exact = if exponent >= 0 {
+        if exponent > 62 {
+            return None;
+        }
+        Num::ratio(sign * (mantissa << exponent), 1)
+    } else {
+        if exponent < -62 {
+            return None;
+        }
+        Num::ratio(sign * mantissa, 1 << -exponent)
+    };
+    // it might have turned into a float if it didn't fit
+    match exact {
+        Num::Float(_) => None,
+        exact => Some(exact),
+    }
+}
+
+/// Get the exact numerator and denominator of some number.
+/// The bool is whether the number was inexact.
+fn exact_parts(
+    engine: &mut Engine,
+    expr: Gc<Expr>,
+    idx: usize,
+) -> Result<(i64, i64, bool), Exception> {
+    let num = Num::from_expr(engine, expr.to_owned(), idx)?;
+    let (exact, was_inexact) = match num {
+        Num::Float(f) => (float_to_exact(f), true),
+        exact => (Some(exact), false),
+    };
+    match exact {
+        Some(Num::Int(int)) => Ok((int, 1, was_inexact)),
+        Some(Num::Ratio(numer, denom)) => Ok((numer, denom, was_inexact)),
+        _ => Err(bad_arg_type(engine, expr, idx, "finite number")),
+    }
+}
+
+pub fn numerator(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+
+    let (numer, _, inexact) = exact_parts(engine, args[0].to_owned(), 0)?;
+    Ok(if inexact {
+        Num::Float(numer as _)
+    } else {
+        Num::Int(numer)
+    }
+    .to_expr())
+}
+
+pub fn denominator(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+
+    let (_, denom, inexact) = exact_parts(engine, args[0].to_owned(), 0)?;
+    Ok(if inexact {
+        Num::Float(denom as _)
+    } else {
+        Num::Int(denom)
+    }
+    .to_expr())
+}
+
 macro_rules! bitwise {
     (($name:ident $op:tt)) => {
         pub fn $name(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
@@ -401,13 +811,7 @@ macro_rules! comparisons {
             check_argc(engine, args, 2, 2)?;
             let lhs = Num::from_expr(engine, args[0].to_owned(), 0)?;
             let rhs = Num::from_expr(engine, args[1].to_owned(), 1)?;
-
-            let cmp = if let (Num::Int(l), Num::Int(r)) = (lhs, rhs) {
-                l $op r
-            } else {
-                lhs.as_float() $op rhs.as_float()
-            };
-            Ok(engine.make_bool(cmp))
+            Ok(engine.make_bool(lhs $op rhs))
         }
     };
     ($head:tt $($tail:tt)*) => {
@@ -432,11 +836,7 @@ pub fn num_eq(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>])
     for (idx, rhs) in rest.iter().enumerate() {
         let rhs = Num::from_expr(engine, rhs.to_owned(), idx + 1)?;
 
-        let cmp = if let (Num::Int(l), Num::Int(r)) = (lhs, rhs) {
-            l == r
-        } else {
-            (lhs.as_float() - rhs.as_float()).abs() < 1e10
-        };
+        let cmp = lhs == rhs;
         all_eq = cmp;
         if !cmp {
             break;
@@ -461,8 +861,8 @@ pub fn num2rounded_str(
     let s = match num {
         // precision is ignored on ints
         Num::Int(i) => i.to_string(),
-        Num::Float(f) => format!("{:.*}", precision as usize, f),
+        _ => format!("{:.*}", precision as usize, num.as_float()),
     };
 
-    Ok(Gc::new(Expr::String(s.into_bytes())))
+    Ok(Expr::string(s.into_bytes()))
 }
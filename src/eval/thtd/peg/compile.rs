@@ -3,13 +3,13 @@ use std::{borrow::Cow, collections::HashMap, convert::TryInto};
 use gc::{Gc, GcCell};
 use itertools::Itertools;
 
-use super::{
-    Opcode, BYTE, BYTE_SIZE, EXPR, EXPR_SIZE, OPCODE_SIZE, RULEPTR, RULEPTR_SIZE, STR_SIZE, U,
-    U_SIZE,
-};
+use super::{Format, Opcode, BYTE, BYTE_SIZE, HEADER_SIZE, OPCODE_SIZE};
 use crate::{
     display::BstrFmt,
-    eval::thtd::{check_argc, peg::compression},
+    eval::thtd::{
+        check_argc,
+        peg::{compression, left_recursion},
+    },
     hash::GcMap,
     Engine, EvalResult, Exception, Expr, Namespace,
 };
@@ -22,57 +22,53 @@ pub fn compile(engine: &mut Engine, env: Gc<GcCell<Namespace>>, args: &[Gc<Expr>
     check_argc(engine, args, 1, 1)?;
 
     let peg = args[0].to_owned();
-    let mut builder = Builder {
-        bytecode: Vec::new(),
-        exprs: Vec::new(),
-        lookup: None,
-        prev_symbols: HashMap::new(),
-        string_slots: HashMap::new(),
-        engine,
-        env,
-    };
-    let mut cursor = 0;
-    builder.write_rule(&mut cursor, peg)?;
-
-    // Wow, we actually made it, somehow.
-    // Deconstruct it back into its parts, and fill in the strings.
-    let mut bytecode = builder.bytecode;
-
-    let (buffer, slots) = COMPRESSOR(builder.string_slots);
-    for (slot, bufptr) in slots {
-        let ptr = bufptr + bytecode.len();
-
-        let uptr: U = if let Ok(u) = ptr.try_into() {
-            u
-        } else {
-            return Err(engine.make_err("peg/compile/too-long/strptr", format!("the bytecode got longer than {} when writing a string and the strptr could not be created", U::MAX), None));
+    // Narrow pointers are smaller, so only go wide if they don't fit
+    let mut replacers = Vec::new();
+    for format in [Format::NARROW, Format::WIDE] {
+        let mut builder = Builder {
+            bytecode: format.header().to_vec(),
+            exprs: Vec::new(),
+            format,
+            overflowed: false,
+            replacers,
+            next_replacer: 0,
+            lookup: None,
+            prev_symbols: HashMap::new(),
+            rule_names: HashMap::new(),
+            string_slots: HashMap::new(),
+            engine,
+            env: env.to_owned(),
         };
-        let dest = &mut bytecode[slot..slot + U_SIZE];
-        debug_assert_eq!(
-            dest,
-            &[0xff, 0xff],
-            "tried to overwrite a non-0xFF string slot at {} in the string buffer: {:x?}",
-            slot,
-            buffer
-        );
-        dest.copy_from_slice(&uptr.to_be_bytes());
+        let mut cursor = HEADER_SIZE;
+        builder.write_rule(&mut cursor, peg.to_owned())?;
+
+        // Wow, we actually made it, somehow.
+        if let Some(bytecode) = builder.finish()? {
+            builder.check_left_recursion(&bytecode)?;
+            let exprs = Engine::list_to_sexp(&builder.exprs);
+            let peg_sym = engine.intern_symbol("peg");
+            return Ok(Engine::list_to_improper_sexp(
+                &[Expr::symbol(peg_sym), Expr::string(bytecode)],
+                exprs,
+            ));
+        }
+        replacers = builder.replacers;
     }
-
-    bytecode.extend(buffer);
-    let exprs = Engine::list_to_sexp(&builder.exprs);
-
-    let peg_sym = engine.intern_symbol("peg");
-
-    Ok(Engine::list_to_improper_sexp(
-        &[Expr::symbol(peg_sym), Expr::string(bytecode)],
-        exprs,
-    ))
+    unreachable!("wide bytecode errors instead of overflowing")
 }
 
 struct Builder<'engine, 'map> {
     bytecode: Vec<u8>,
     exprs: Vec<Gc<Expr>>,
 
+    format: Format,
+    /// Set when a number didn't fit in a narrow `u`, so we need to start over with wide ones.
+    overflowed: bool,
+    /// The evaluated replacers of `replace`, in the order they were written,
+    /// so starting over doesn't evaluate them twice.
+    replacers: Vec<Gc<Expr>>,
+    next_replacer: usize,
+
     lookup: Option<&'map GcMap>,
     /// Maps can be used to create recursive rules.
     ///
@@ -82,6 +78,8 @@ struct Builder<'engine, 'map> {
     ///
     /// This is *not* passed down to further maps, because lower-leveled maps can't call into higher-leveled ones.
     prev_symbols: HashMap<u64, usize>,
+    /// The symbols rules were written for, by where they were written, for error messages.
+    rule_names: HashMap<usize, u64>,
 
     /// Indices of byte idxes we need to write strings to mapped to the expected string.
     string_slots: HashMap<usize, Vec<u8>>,
@@ -95,12 +93,12 @@ impl Builder<'_, '_> {
     fn write_rule(&mut self, cursor: &mut usize, peg: Gc<Expr>) -> Result<(), Exception> {
         match &*peg {
             &Expr::Integer(count) => {
-                self.reserve(OPCODE_SIZE + U_SIZE);
+                self.reserve(OPCODE_SIZE + self.format.u_size);
                 self.write_opcode(cursor, Opcode::CharCount);
                 self.write_i64(cursor, count)?;
             }
             Expr::String(s) => {
-                self.reserve(OPCODE_SIZE + STR_SIZE);
+                self.reserve(OPCODE_SIZE + self.format.str_size());
                 self.write_opcode(cursor, Opcode::Literal);
                 self.write_str(cursor, s)?;
             }
@@ -130,7 +128,7 @@ impl Builder<'_, '_> {
                             ));
                         };
 
-                        self.reserve(OPCODE_SIZE + RULEPTR_SIZE + U_SIZE);
+                        self.reserve(OPCODE_SIZE + 2 * self.format.u_size);
                         self.write_opcode(cursor, Opcode::Count);
                         self.write_ruleptr(cursor, subpeg)?;
                         self.write_i64(cursor, i)?;
@@ -164,26 +162,34 @@ impl Builder<'_, '_> {
                 let mut builder_with_lookup = Builder {
                     bytecode: std::mem::take(&mut self.bytecode),
                     exprs: std::mem::take(&mut self.exprs),
+                    format: self.format,
+                    overflowed: self.overflowed,
+                    replacers: std::mem::take(&mut self.replacers),
+                    next_replacer: self.next_replacer,
                     lookup: Some(new_lookup),
                     prev_symbols,
+                    rule_names: std::mem::take(&mut self.rule_names),
                     string_slots: HashMap::new(),
 
                     engine: self.engine,
                     env: self.env.to_owned(),
                 };
                 // yes we do actually write the rule here
-                builder_with_lookup.write_rule(cursor, main_peg)?;
+                builder_with_lookup.write_named(cursor, main_sym, main_peg)?;
                 // then undestructure it
                 self.bytecode = builder_with_lookup.bytecode;
                 self.exprs = builder_with_lookup.exprs;
+                self.overflowed = builder_with_lookup.overflowed;
+                self.replacers = builder_with_lookup.replacers;
+                self.next_replacer = builder_with_lookup.next_replacer;
+                self.rule_names = builder_with_lookup.rule_names;
                 self.string_slots.extend(builder_with_lookup.string_slots);
             }
             Expr::Symbol(sym) => {
                 if let Some(&prev_idx) = self.prev_symbols.get(sym) {
                     // hey we've already used this symbol! let's just jump to it with a `do` opcode.
-                    self.reserve(OPCODE_SIZE + RULEPTR_SIZE);
+                    self.reserve(OPCODE_SIZE + self.format.u_size);
                     self.write_opcode(cursor, Opcode::Jump);
-                    // U has the same size as a rule-idx ... we must make sure that always holds.
                     self.write_usize(cursor, prev_idx)?;
                 } else {
                     let local_map = self.lookup.map(Cow::Borrowed);
@@ -205,7 +211,7 @@ impl Builder<'_, '_> {
                             // Write the index of *this* into the map first, in case that rule calls this.
                             self.prev_symbols.insert(*sym, *cursor);
                             // seamlessly replace the symbol with the rule.
-                            return self.write_rule(cursor, subpeg.to_owned());
+                            return self.write_named(cursor, *sym, subpeg.to_owned());
                         }
                     }
 
@@ -230,6 +236,49 @@ impl Builder<'_, '_> {
         Ok(())
     }
 
+    /// Write a rule that was looked up by name, so failures can say which rule they were in.
+    fn write_named(
+        &mut self,
+        cursor: &mut usize,
+        sym: u64,
+        peg: Gc<Expr>,
+    ) -> Result<(), Exception> {
+        self.rule_names.insert(*cursor, sym);
+        let name = self.engine.get_symbol_str(sym).unwrap().to_owned();
+
+        self.reserve(OPCODE_SIZE + self.format.u_size + self.format.str_size());
+        self.write_opcode(cursor, Opcode::Named);
+        self.write_ruleptr(cursor, peg)?;
+        self.write_str(cursor, &name)
+    }
+
+    /// Write a rule that tags the last thing the PEG captures.
+    fn write_tag(
+        &mut self,
+        cursor: &mut usize,
+        peg: Gc<Expr>,
+        tag: Gc<Expr>,
+    ) -> Result<(), Exception> {
+        self.reserve(OPCODE_SIZE + self.format.u_size + self.format.expr_size);
+        self.write_opcode(cursor, Opcode::Tag);
+        self.write_ruleptr(cursor, peg)?;
+        self.write_expr(cursor, tag)
+    }
+
+    /// Evaluate the expression a `replace` or `cmt` calls, or get it from the last try.
+    fn eval_replacer(&mut self, replacer: Gc<Expr>) -> Result<Gc<Expr>, Exception> {
+        let replacer = match self.replacers.get(self.next_replacer) {
+            Some(already) => already.to_owned(),
+            None => {
+                let replacer = self.engine.eval_inner(self.env.to_owned(), replacer)?;
+                self.replacers.push(replacer.to_owned());
+                replacer
+            }
+        };
+        self.next_replacer += 1;
+        Ok(replacer)
+    }
+
     /// Write a rule given an opcode string and body of the expression.
     ///
     /// Yes this is a dumb name but I call it *once* and I can't think of a better one
@@ -242,15 +291,17 @@ impl Builder<'_, '_> {
     ) -> Result<(), Exception> {
         match op {
             // Things taking 0 arguments
-            b"position" | b"$" => {
-                let opc = match op {
-                    b"position" | b"$" => Opcode::Position,
+            b"position" | b"$" | b"line" | b"column" => {
+                let (opc, real_name) = match op {
+                    b"position" | b"$" => (Opcode::Position, b"position".as_ref()),
+                    b"line" => (Opcode::Line, b"line".as_ref()),
+                    b"column" => (Opcode::Column, b"column".as_ref()),
                     ono => panic!("i forgor to impl {}", BstrFmt(ono)),
                 };
                 if !body.is_empty() {
                     return Err(self.engine.make_err(
-                        "peg/position/argc",
-                        "position takes 0 arguments".to_string(),
+                        format!("peg/{}/argc", BstrFmt(real_name)),
+                        format!("{} takes 0 arguments", BstrFmt(real_name)),
                         Some(tail),
                     ));
                 }
@@ -258,9 +309,59 @@ impl Builder<'_, '_> {
                 self.reserve(OPCODE_SIZE);
                 self.write_opcode(cursor, opc);
             }
+            // Things that tag what they capture, when given a tag
+            b"capture" | b"<-" | b"quote" | b"constant" if body.len() == 2 => {
+                let untagged = Engine::list_to_sexp(&[
+                    Expr::symbol(self.engine.intern_symbol(op)),
+                    body[0].to_owned(),
+                ]);
+                self.write_tag(cursor, untagged, body[1].to_owned())?;
+            }
+            b"cmt" if body.len() == 3 => {
+                let untagged = Engine::list_to_sexp(&[
+                    Expr::symbol(self.engine.intern_symbol(op)),
+                    body[0].to_owned(),
+                    body[1].to_owned(),
+                ]);
+                self.write_tag(cursor, untagged, body[2].to_owned())?;
+            }
+            b"tag" => {
+                let (subpeg, tag) = if let [subpeg, tag] = body.as_slice() {
+                    (subpeg.to_owned(), tag.to_owned())
+                } else {
+                    return Err(self.engine.make_err(
+                        "peg/compile/tag/argc",
+                        "tag takes 1 PEG and 1 tag argument",
+                        Some(tail),
+                    ));
+                };
+                self.write_tag(cursor, subpeg, tag)?;
+            }
+            // Things taking 1 expression argument
+            b"constant" | b"backref" | b"->" => {
+                let (opc, real_name) = match op {
+                    b"constant" => (Opcode::Constant, b"constant".as_ref()),
+                    b"backref" | b"->" => (Opcode::Backref, b"backref".as_ref()),
+                    ono => panic!("i forgor to impl {}", BstrFmt(ono)),
+                };
+                let expr = if let [expr] = body.as_slice() {
+                    expr.to_owned()
+                } else {
+                    return Err(self.engine.make_err(
+                        format!("peg/compile/{}/argc", BstrFmt(real_name)),
+                        format!("{} requires 1 argument", BstrFmt(real_name)),
+                        Some(tail),
+                    ));
+                };
+
+                self.reserve(OPCODE_SIZE + self.format.expr_size);
+                self.write_opcode(cursor, opc);
+                self.write_expr(cursor, expr)?;
+            }
             // Things taking 1 subpeg argument
             b"any" | b"some" | b"opt" | b"?" | b"capture" | b"<-" | b"quote" | b"group"
-            | b"quasiquote" | b"not" | b"!" | b"all" | b"do" => {
+            | b"quasiquote" | b"not" | b"!" | b"all" | b"do" | b"to" | b"thru" | b"accumulate"
+            | b"%" => {
                 let (opc, real_name) = match op {
                     b"any" => (Opcode::Any, b"any".as_ref()),
                     b"some" => (Opcode::Some, b"some".as_ref()),
@@ -270,6 +371,9 @@ impl Builder<'_, '_> {
                     b"not" | b"!" => (Opcode::Not, b"not".as_ref()),
                     b"all" => (Opcode::All, b"all".as_ref()),
                     b"do" => (Opcode::Jump, b"do".as_ref()),
+                    b"to" => (Opcode::To, b"to".as_ref()),
+                    b"thru" => (Opcode::Thru, b"thru".as_ref()),
+                    b"accumulate" | b"%" => (Opcode::Accumulate, b"accumulate".as_ref()),
                     ono => panic!("i forgor to impl {}", BstrFmt(ono)),
                 };
                 let subpeg = if let [subpeg] = body.as_slice() {
@@ -282,7 +386,7 @@ impl Builder<'_, '_> {
                     ));
                 };
 
-                self.reserve(OPCODE_SIZE + RULEPTR_SIZE);
+                self.reserve(OPCODE_SIZE + self.format.u_size);
                 self.write_opcode(cursor, opc);
                 self.write_ruleptr(cursor, subpeg)?;
             }
@@ -305,7 +409,7 @@ impl Builder<'_, '_> {
                     ));
                 };
 
-                self.reserve(OPCODE_SIZE + 2 * RULEPTR_SIZE);
+                self.reserve(OPCODE_SIZE + 2 * self.format.u_size);
                 self.write_opcode(cursor, opc);
                 self.write_ruleptr(cursor, speg1)?;
                 self.write_ruleptr(cursor, speg2)?;
@@ -337,7 +441,7 @@ impl Builder<'_, '_> {
                     concated.extend(s.iter().copied());
                 }
 
-                self.reserve(OPCODE_SIZE + STR_SIZE);
+                self.reserve(OPCODE_SIZE + self.format.str_size());
                 self.write_opcode(cursor, Opcode::Range);
                 self.write_str(cursor, &concated)?;
             }
@@ -352,7 +456,7 @@ impl Builder<'_, '_> {
                     ));
                 };
                 let mut set = match &**set {
-                    Expr::String(s) => s.clone(),
+                    Expr::String(s) => s.to_vec(),
                     _ => {
                         return Err(self.engine.make_err(
                             "peg/compile/set/non-string",
@@ -377,7 +481,7 @@ impl Builder<'_, '_> {
                     (Opcode::Set, set)
                 };
 
-                self.reserve(OPCODE_SIZE + STR_SIZE);
+                self.reserve(OPCODE_SIZE + self.format.str_size());
                 self.write_opcode(cursor, opc);
                 self.write_str(cursor, &arg)?
             }
@@ -400,7 +504,7 @@ impl Builder<'_, '_> {
                     Opcode::Sequence
                 };
 
-                self.reserve(OPCODE_SIZE + BYTE_SIZE + count * RULEPTR_SIZE);
+                self.reserve(OPCODE_SIZE + BYTE_SIZE + count * self.format.u_size);
                 self.write_opcode(cursor, opc);
                 self.write_byte(cursor, count_b);
                 for subrule in body {
@@ -446,7 +550,7 @@ impl Builder<'_, '_> {
                     ono => panic!("i forgor to impl {}", BstrFmt(ono)),
                 };
 
-                self.reserve(OPCODE_SIZE + RULEPTR_SIZE + U_SIZE);
+                self.reserve(OPCODE_SIZE + 2 * self.format.u_size);
                 self.write_opcode(cursor, opc);
                 self.write_ruleptr(cursor, subpeg)?;
                 self.write_i64(cursor, n)?;
@@ -502,7 +606,7 @@ impl Builder<'_, '_> {
                     }
                 };
 
-                self.reserve(OPCODE_SIZE + RULEPTR_SIZE + 2 * U_SIZE);
+                self.reserve(OPCODE_SIZE + self.format.u_size + 2 * self.format.u_size);
                 self.write_opcode(cursor, Opcode::Between);
                 self.write_ruleptr(cursor, subpeg)?;
                 self.write_i64(cursor, min)?;
@@ -519,19 +623,64 @@ impl Builder<'_, '_> {
                     ));
                 };
 
-                let replacer = self.engine.eval_inner(self.env.to_owned(), replacer)?;
+                let replacer = self.eval_replacer(replacer)?;
 
-                self.reserve(OPCODE_SIZE + RULEPTR_SIZE + EXPR_SIZE);
+                self.reserve(OPCODE_SIZE + self.format.u_size + self.format.expr_size);
                 self.write_opcode(cursor, Opcode::Replace);
                 self.write_ruleptr(cursor, subpeg)?;
                 self.write_expr(cursor, replacer)?;
             }
+            b"cmt" => {
+                let (subpeg, fun) = if let [subpeg, fun] = body.as_slice() {
+                    (subpeg.to_owned(), fun.to_owned())
+                } else {
+                    return Err(self.engine.make_err(
+                        "peg/compile/cmt/argc",
+                        "cmt takes 1 PEG, 1 procedure and an optional tag",
+                        Some(tail),
+                    ));
+                };
+
+                let fun = self.eval_replacer(fun)?;
+                if !fun.is_callable() {
+                    return Err(self.engine.make_err(
+                        "peg/compile/cmt/not-callable",
+                        format!("cmt needs a procedure, not a {}", fun.type_name()),
+                        Some(fun),
+                    ));
+                }
+
+                self.reserve(OPCODE_SIZE + self.format.u_size + self.format.expr_size);
+                self.write_opcode(cursor, Opcode::MatchTime);
+                self.write_ruleptr(cursor, subpeg)?;
+                self.write_expr(cursor, fun)?;
+            }
+            b"look" | b">" => {
+                let (offset, subpeg) = match body.as_slice() {
+                    [subpeg] => (Expr::integer(0), subpeg.to_owned()),
+                    [offset, subpeg] if matches!(**offset, Expr::Integer(_)) => {
+                        (offset.to_owned(), subpeg.to_owned())
+                    }
+                    _ => {
+                        return Err(self.engine.make_err(
+                            "peg/compile/look/argc",
+                            "look takes an optional int offset and 1 PEG",
+                            Some(tail),
+                        ))
+                    }
+                };
+
+                self.reserve(OPCODE_SIZE + self.format.u_size + self.format.expr_size);
+                self.write_opcode(cursor, Opcode::Look);
+                self.write_ruleptr(cursor, subpeg)?;
+                self.write_expr(cursor, offset)?;
+            }
 
             _ => {
                 return Err(self.engine.make_err(
                     "peg/compile/bad-op",
                     format!("did not recognize the operator '{}", BstrFmt(op)),
-                    Some(Gc::new(Expr::String(op.to_owned()))),
+                    Some(Expr::string(op.to_owned())),
                 ));
             }
         }
@@ -539,15 +688,82 @@ impl Builder<'_, '_> {
         Ok(())
     }
 
+    /// Fill in the strings and take the finished bytecode.
+    ///
+    /// Returns `None` if something didn't fit in narrow `u`s and it has to be built again with wide ones.
+    fn finish(&mut self) -> Result<Option<Vec<u8>>, Exception> {
+        if self.overflowed {
+            return Ok(None);
+        }
+        let mut bytecode = std::mem::take(&mut self.bytecode);
+
+        let (buffer, slots) = COMPRESSOR(std::mem::take(&mut self.string_slots));
+        for (slot, bufptr) in slots {
+            let ptr = (bufptr + bytecode.len()) as u64;
+            if ptr > self.format.u_max() {
+                if self.format != Format::WIDE {
+                    return Ok(None);
+                }
+                return Err(self.engine.make_err("peg/compile/too-long/strptr", format!("the bytecode got longer than {} when writing a string and the strptr could not be created", self.format.u_max()), None));
+            }
+            let u_size = self.format.u_size;
+            let dest = &mut bytecode[slot..slot + u_size];
+            debug_assert!(
+                dest.iter().all(|&b| b == 0xff),
+                "tried to overwrite a non-0xFF string slot at {} in the string buffer: {:x?}",
+                slot,
+                buffer
+            );
+            dest.copy_from_slice(&ptr.to_be_bytes()[8 - u_size..]);
+        }
+
+        bytecode.extend(buffer);
+        Ok(Some(bytecode))
+    }
+
+    /// Complain if a rule can call itself without matching anything first,
+    /// because that would recurse forever.
+    fn check_left_recursion(&mut self, bytecode: &[u8]) -> Result<(), Exception> {
+        let cycle = left_recursion::find_cycle(bytecode, self.format, HEADER_SIZE, &self.exprs)
+            .expect("the compiler wrote bytecode it can't read");
+        let cycle = match cycle {
+            Some(it) => it,
+            None => return Ok(()),
+        };
+
+        let mut names = cycle
+            .iter()
+            .filter_map(|ptr| self.rule_names.get(ptr).copied())
+            .collect_vec();
+        names.dedup();
+        if names.len() == 1 {
+            // Name it on both ends, like the rest
+            names.push(names[0]);
+        }
+        let shown = names
+            .iter()
+            .map(|&sym| BstrFmt(self.engine.get_symbol_str(sym).unwrap()).to_string())
+            .join(" -> ");
+        let data = names.into_iter().map(Expr::symbol).collect_vec();
+        Err(self.engine.make_err(
+            "peg/compile/left-recursion",
+            format!(
+                "rules would call themselves forever without matching anything: {}",
+                shown
+            ),
+            Some(Engine::list_to_sexp(&data)),
+        ))
+    }
+
     /// Push this many 0 bytes onto the end of the stack.
     fn reserve(&mut self, size: usize) {
         let len = self.bytecode.len();
         self.bytecode.resize(len + size, 0);
     }
 
-    /// Write a U and move the cursor forward.
-    fn write_u(&mut self, cursor: &mut usize, u: U) {
-        let end_cursor = *cursor + U_SIZE;
+    /// Write a `u` and move the cursor forward.
+    fn write_u(&mut self, cursor: &mut usize, u: u64) {
+        let end_cursor = *cursor + self.format.u_size;
         let slice = &mut self.bytecode[*cursor..end_cursor];
         debug_assert!(
             all_zero(slice),
@@ -556,7 +772,7 @@ impl Builder<'_, '_> {
             &self.bytecode,
             u,
         );
-        slice.copy_from_slice(&u.to_be_bytes());
+        slice.copy_from_slice(&u.to_be_bytes()[8 - self.format.u_size..]);
         *cursor = end_cursor;
     }
 
@@ -580,44 +796,51 @@ impl Builder<'_, '_> {
         self.write_byte(cursor, opc.into());
     }
 
-    /// Write a usize as a U and move the cursor forward
+    /// Write a usize as a `u` and move the cursor forward.
+    ///
+    /// If it doesn't fit in a narrow `u`, this skips it and marks the builder as overflowed.
     fn write_usize(&mut self, cursor: &mut usize, usz: usize) -> Result<(), Exception> {
-        let u: U = match usz.try_into() {
-            Ok(u) => u,
-            Err(_) => {
-                return Err(self.engine.make_err(
-                    "peg/compile/overlarge-usize",
-                    format!(
-                        "the usize {} was too big to put into a {}-byte uint",
-                        usz, U_SIZE
-                    ),
-                    Some(Expr::integer(usz as _)),
-                ))
-            }
-        };
-        self.write_u(cursor, u);
+        if usz as u64 <= self.format.u_max() {
+            self.write_u(cursor, usz as u64);
+        } else if self.format != Format::WIDE {
+            self.overflowed = true;
+            *cursor += self.format.u_size;
+        } else {
+            return Err(self.engine.make_err(
+                "peg/compile/overlarge-usize",
+                format!(
+                    "the usize {} was too big to put into a {}-byte uint",
+                    usz, self.format.u_size
+                ),
+                Some(Expr::integer(usz as _)),
+            ));
+        }
         Ok(())
     }
 
-    /// Write an i64 as a U and move the cursor forward
+    /// Write an i64 as a `u` and move the cursor forward
     fn write_i64(&mut self, cursor: &mut usize, i: i64) -> Result<(), Exception> {
-        let u: U = match i.try_into() {
-            Ok(u) => u,
-            Err(_) => {
-                return Err(self.engine.make_err(
-                    "peg/compile/overlarge-i64",
-                    format!("the i64 {} cannot be put into a {}-byte uint", i, U_SIZE),
-                    Some(Expr::integer(i)),
-                ))
-            }
-        };
-        self.write_u(cursor, u);
-        Ok(())
+        let fits = i
+            .try_into()
+            .ok()
+            .filter(|&u: &u64| u <= Format::WIDE.u_max());
+        match fits {
+            Some(u) => self.write_usize(cursor, u as usize),
+            None => Err(self.engine.make_err(
+                "peg/compile/overlarge-i64",
+                format!(
+                    "the i64 {} cannot be put into a {}-byte uint",
+                    i,
+                    Format::WIDE.u_size
+                ),
+                Some(Expr::integer(i)),
+            )),
+        }
     }
 
-    /// Pretend to write a string. Save the string to `string_slots`, write the length, and write `0xffff` as the pointer.
+    /// Pretend to write a string. Save the string to `string_slots`, write the length, and write all `0xff`s as the pointer.
     fn write_str(&mut self, cursor: &mut usize, string: &[u8]) -> Result<(), Exception> {
-        let end_cursor = *cursor + STR_SIZE;
+        let end_cursor = *cursor + self.format.str_size();
         let slice = &mut self.bytecode[*cursor..end_cursor];
         debug_assert!(all_zero(slice), "tried to overwrite bytes at {}", cursor);
 
@@ -626,14 +849,14 @@ impl Builder<'_, '_> {
         debug_assert!(_prev.is_none());
 
         // Now tell the caller about the "ptr" and len
-        self.write_u(cursor, U::MAX);
+        self.write_u(cursor, self.format.u_max());
         self.write_usize(cursor, str_len).map_err(|_| {
             self.engine.make_err(
                 "peg/compile/too-long/string",
                 format!(
                     "the string had a length of {}, longer than the allowed {}",
                     string.len(),
-                    RULEPTR::MAX
+                    self.format.u_max()
                 ),
                 Some(Expr::integer(string.len() as _)),
             )
@@ -653,7 +876,7 @@ impl Builder<'_, '_> {
                 "peg/compile/too-long/ruleptr",
                 format!(
                     "bytecode got longer than {} and the ruleptr could not be created",
-                    RULEPTR::MAX
+                    self.format.u_max()
                 ),
                 Some(rule),
             )
@@ -671,17 +894,13 @@ impl Builder<'_, '_> {
             self.exprs.push(expr);
             ptr
         };
-        let b = if let Ok(b) = expr_ptr.try_into() {
-            b
-        } else {
-            return Err(self.engine.make_err(
+        self.write_usize(cursor, expr_ptr).map_err(|_| {
+            self.engine.make_err(
                 "peg/compile/too-long/exprs",
-                format!("cannot have more than {} exprs", EXPR::MAX),
+                format!("cannot have more than {} exprs", Format::WIDE.u_max()),
                 Some(Expr::integer(expr_ptr as _)),
-            ));
-        };
-        self.write_byte(cursor, b);
-        Ok(())
+            )
+        })
     }
 }
 
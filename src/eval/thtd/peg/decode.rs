@@ -0,0 +1,203 @@
+//! Reading rules back out of bytecode, for looking over it without running it.
+
+use std::{
+    collections::{hash_map::Entry, BTreeSet, HashMap},
+    convert::TryFrom,
+    fmt::Write,
+};
+
+use gc::{Gc, GcCell};
+
+use super::{execute::Peg, Format, Opcode, BYTE_SIZE, OPCODE_SIZE};
+use crate::{display::BstrFmt, eval::thtd::check_argc, Engine, EvalResult, Expr, Namespace, Value};
+
+/// One argument of a rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arg {
+    U(usize),
+    Byte(u8),
+    Str { ptr: usize, len: usize },
+    Rule(usize),
+    Expr(usize),
+}
+
+/// A rule read out of the bytecode.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub opcode: Opcode,
+    pub args: Vec<Arg>,
+}
+
+impl Rule {
+    /// Pointers to the rules this one runs, in order.
+    pub fn subrules(&self) -> impl Iterator<Item = usize> + '_ {
+        self.args.iter().filter_map(|arg| match *arg {
+            Arg::Rule(ptr) => Some(ptr),
+            _ => None,
+        })
+    }
+
+    /// The `u` arguments, like counts.
+    pub fn us(&self) -> impl Iterator<Item = usize> + '_ {
+        self.args.iter().filter_map(|arg| match *arg {
+            Arg::U(u) => Some(u),
+            _ => None,
+        })
+    }
+}
+
+/// Read the rule at `at`.
+///
+/// Fails with a message saying what's wrong if there isn't a whole rule there.
+pub fn decode(bytecode: &[u8], format: Format, at: usize) -> Result<Rule, String> {
+    let read = |cursor: &mut usize, size: usize, what: &str| {
+        let data = bytecode
+            .get(*cursor..*cursor + size)
+            .ok_or_else(|| format!("{} at {} is out of bounds", what, cursor))?;
+        *cursor += size;
+        Ok::<_, String>(data.iter().fold(0, |acc, &b| acc << 8 | b as usize))
+    };
+
+    let byte = *bytecode
+        .get(at)
+        .ok_or_else(|| format!("rule at {} is out of bounds", at))?;
+    let opcode =
+        Opcode::try_from(byte).map_err(|_| format!("0x{:X} at {} is not an opcode", byte, at))?;
+    let mut cursor = at + OPCODE_SIZE;
+
+    let u = format.u_size;
+    let mut args = Vec::new();
+    match opcode {
+        Opcode::Position => {}
+        Opcode::CharCount => args.push(Arg::U(read(&mut cursor, u, "u")?)),
+        Opcode::Literal | Opcode::Range | Opcode::Set => {
+            let ptr = read(&mut cursor, u, "string pointer")?;
+            let len = read(&mut cursor, u, "string length")?;
+            args.push(Arg::Str { ptr, len });
+        }
+        Opcode::Choice | Opcode::Sequence => {
+            let count = read(&mut cursor, BYTE_SIZE, "subrule count")?;
+            args.push(Arg::Byte(count as u8));
+            for _ in 0..count {
+                args.push(Arg::Rule(read(&mut cursor, u, "rule pointer")?));
+            }
+        }
+        Opcode::Split | Opcode::If | Opcode::IfNot => {
+            args.push(Arg::Rule(read(&mut cursor, u, "rule pointer")?));
+            args.push(Arg::Rule(read(&mut cursor, u, "rule pointer")?));
+        }
+        Opcode::Any
+        | Opcode::Some
+        | Opcode::Opt
+        | Opcode::Capture
+        | Opcode::Group
+        | Opcode::Not
+        | Opcode::All
+        | Opcode::Jump => args.push(Arg::Rule(read(&mut cursor, u, "rule pointer")?)),
+        Opcode::AtLeast | Opcode::AtMost | Opcode::Count => {
+            args.push(Arg::Rule(read(&mut cursor, u, "rule pointer")?));
+            args.push(Arg::U(read(&mut cursor, u, "u")?));
+        }
+        Opcode::Between => {
+            args.push(Arg::Rule(read(&mut cursor, u, "rule pointer")?));
+            args.push(Arg::U(read(&mut cursor, u, "u")?));
+            args.push(Arg::U(read(&mut cursor, u, "u")?));
+        }
+        Opcode::Named => {
+            args.push(Arg::Rule(read(&mut cursor, u, "rule pointer")?));
+            let ptr = read(&mut cursor, u, "string pointer")?;
+            let len = read(&mut cursor, u, "string length")?;
+            args.push(Arg::Str { ptr, len });
+        }
+        Opcode::Line | Opcode::Column => {}
+        Opcode::Constant | Opcode::Backref => {
+            args.push(Arg::Expr(read(&mut cursor, format.expr_size, "expr")?));
+        }
+        Opcode::Tag | Opcode::Look | Opcode::MatchTime => {
+            args.push(Arg::Rule(read(&mut cursor, u, "rule pointer")?));
+            args.push(Arg::Expr(read(&mut cursor, format.expr_size, "expr")?));
+        }
+        Opcode::To | Opcode::Thru | Opcode::Accumulate => {
+            args.push(Arg::Rule(read(&mut cursor, u, "rule pointer")?));
+        }
+        Opcode::Replace => {
+            args.push(Arg::Rule(read(&mut cursor, u, "rule pointer")?));
+            args.push(Arg::Expr(read(&mut cursor, format.expr_size, "expr")?));
+        }
+    }
+
+    Ok(Rule { opcode, args })
+}
+
+/// Read every rule that can be run from the rule at `start`, by where they are.
+pub fn reachable(
+    bytecode: &[u8],
+    format: Format,
+    start: usize,
+) -> Result<HashMap<usize, Rule>, String> {
+    let mut rules = HashMap::new();
+    let mut todo = vec![start];
+    while let Some(ptr) = todo.pop() {
+        if let Entry::Vacant(slot) = rules.entry(ptr) {
+            let rule = decode(bytecode, format, ptr)?;
+            todo.extend(rule.subrules());
+            slot.insert(rule);
+        }
+    }
+    Ok(rules)
+}
+
+/// `(peg/disassemble-inner peg)`
+///
+/// Every rule the PEG can run, one per line, like `9: sequence @14 @22`.
+/// `@n` is the rule at `n`, and exprs are written out in full.
+pub fn disassemble(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Value]) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+    let peg = Peg::from_arg(engine, args)?;
+    let bad = |engine: &mut Engine, problem: String| {
+        engine.make_err("peg/invalid-bytecode", problem, Some(args[0].to_owned()))
+    };
+
+    let mut rules = BTreeSet::new();
+    let mut todo = vec![peg.first_rule];
+    while let Some(ptr) = todo.pop() {
+        if rules.insert(ptr) {
+            let rule = decode(&peg.bytecode, peg.format, ptr).map_err(|e| bad(engine, e))?;
+            todo.extend(rule.subrules());
+        }
+    }
+
+    let mut out = format!(
+        "; {}-byte pointers, {}-byte exprs, starting at {}\n",
+        peg.format.u_size, peg.format.expr_size, peg.first_rule
+    );
+    for ptr in rules {
+        let rule = decode(&peg.bytecode, peg.format, ptr).map_err(|e| bad(engine, e))?;
+        write!(out, "{}: {}", ptr, opcode_name(rule.opcode)).unwrap();
+        for arg in rule.args {
+            let arg = match arg {
+                // Already obvious from how many rules there are
+                Arg::Byte(_) => continue,
+                Arg::U(u) => u.to_string(),
+                Arg::Str { ptr, len } => format!("{:?}", BstrFmt(&peg.bytecode[ptr..ptr + len])),
+                Arg::Rule(ptr) => format!("@{}", ptr),
+                Arg::Expr(idx) => engine.write_expr(peg.exprs[idx].to_owned())?,
+            };
+            write!(out, " {}", arg).unwrap();
+        }
+        out.push('\n');
+    }
+    Ok(Expr::string(out.into_bytes()))
+}
+
+/// `CharCount` as `char-count`.
+fn opcode_name(opcode: Opcode) -> String {
+    let mut name = String::new();
+    for c in format!("{:?}", opcode).chars() {
+        if c.is_ascii_uppercase() && !name.is_empty() {
+            name.push('-');
+        }
+        name.push(c.to_ascii_lowercase());
+    }
+    name
+}
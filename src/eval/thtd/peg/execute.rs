@@ -1,55 +1,43 @@
-use std::convert::TryInto;
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryInto,
+};
 
 use gc::{Gc, GcCell};
 
+use ariadne::{Label, Report, ReportKind};
+
 use crate::{
-    eval::{bad_arg_type, thtd::check_argc},
-    Engine, EvalResult, Exception, Expr, Namespace, Value,
+    display::BstrFmt,
+    eval::{
+        bad_arg_type,
+        thtd::{check_argc, strings::push_printed},
+    },
+    hash::GcMap,
+    parse::report_config,
+    ByteStr, Engine, EvalResult, Exception, Expr, Namespace, Value,
 };
 
-use super::{Opcode, BYTE, BYTE_SIZE, U, U_SIZE};
+use super::{decode::reachable, verify::verify, Format, Opcode, BYTE, BYTE_SIZE};
 
-pub fn match_(engine: &mut Engine, env: Gc<GcCell<Namespace>>, args: &[Value]) -> EvalResult {
-    check_argc(engine, args, 2, 4)?;
+/// How many checked PEGs to remember, so matching with them again doesn't check them again.
+const VERIFIED_PEGS_KEPT: usize = 64;
 
-    let peg = match engine.sexp_to_list(args[0].to_owned())? {
-        Some(it) => it,
-        None => return Err(bad_arg_type(engine, args[0].to_owned(), 0, "PEG")),
-    };
-    let (peg_sym, bytecode, exprs) = if let [peg_sym, bytecode, exprs @ ..] = peg.as_slice() {
-        (peg_sym, bytecode, exprs)
-    } else {
-        return Err(bad_arg_type(engine, args[0].to_owned(), 0, "PEG"));
-    };
-    let reference_sym = engine.intern_symbol("peg");
-    if peg_sym != &Expr::symbol(reference_sym) {
-        return Err(bad_arg_type(engine, args[0].to_owned(), 0, "PEG"));
-    }
-    let bytecode = match &**bytecode {
-        Expr::String(s) => s,
-        _ => return Err(bad_arg_type(engine, args[0].to_owned(), 0, "PEG")),
-    };
+/// How many results the memo table keeps when `memo` is just `true`.
+const DEFAULT_MEMO_CAPACITY: usize = 1 << 16;
 
-    let text = match &*args[1] {
-        Expr::String(s) => s,
-        _ => return Err(bad_arg_type(engine, args[1].to_owned(), 1, "string")),
-    };
+/// `(peg/match-inner peg text [start] [debug] [memo])`
+///
+/// If `memo` is truthy, the results of each rule at each position are saved so backtracking
+/// doesn't run them again, at the cost of `replace`rs only being called once per position.
+/// Rules that can get to a `backref` aren't saved, since what they match depends on the tags.
+/// An integer says how many results to keep at most.
+pub fn match_(engine: &mut Engine, env: Gc<GcCell<Namespace>>, args: &[Value]) -> EvalResult {
+    check_argc(engine, args, 2, 5)?;
 
-    let start_cursor = if let Some(cursor) = args.get(2) {
-        match **cursor {
-            Expr::Integer(cursor) if cursor >= 0 => cursor,
-            _ => {
-                return Err(bad_arg_type(
-                    engine,
-                    args[2].to_owned(),
-                    2,
-                    "optional positive integer",
-                ))
-            }
-        }
-    } else {
-        0
-    };
+    let peg = Peg::from_arg(engine, args)?;
+    let text = text_arg(engine, args)?;
+    let start_cursor = start_arg(engine, args, &text)?;
 
     let debug = if let Some(dbg) = args.get(3) {
         engine.is_truthy(dbg.clone())
@@ -57,42 +45,431 @@ pub fn match_(engine: &mut Engine, env: Gc<GcCell<Namespace>>, args: &[Value]) -
         false
     };
 
+    let capacity = match args.get(4).map(|memo| &**memo) {
+        Some(&Expr::Integer(capacity)) if capacity >= 0 => Some(capacity as usize),
+        Some(Expr::Integer(_)) => {
+            return Err(bad_arg_type(
+                engine,
+                args[4].to_owned(),
+                4,
+                "optional bool or positive integer",
+            ))
+        }
+        Some(_) if engine.is_truthy(args[4].to_owned()) => Some(DEFAULT_MEMO_CAPACITY),
+        _ => None,
+    };
+    let memo = match capacity
+        .map(|capacity| Memo::new(capacity, &peg))
+        .transpose()
+    {
+        Ok(it) => it,
+        Err(problem) => {
+            return Err(engine.make_err("peg/invalid-bytecode", problem, Some(args[0].to_owned())))
+        }
+    };
+
     if debug {
         println!("{}", engine.write_expr(args[0].to_owned()).unwrap());
     }
 
-    let mut executor = Executor {
-        bytecode,
-        exprs: exprs.to_owned(),
-        engine,
-        env,
-        debug,
-    };
-    let matched = executor.execute_rule(0, text, start_cursor as usize)?;
+    let mut executor = Executor::new(&peg, &text, engine, env);
+    executor.debug = debug;
+    executor.memo = memo;
+    let matched = executor.execute_rule(peg.first_rule, &text, start_cursor)?;
     Ok(match matched {
         Some((_, stack)) => Engine::list_to_sexp(&stack),
         None => Expr::bool(false),
     })
 }
 
+/// `(peg/match-verbose-inner peg text [start] [report])`
+///
+/// Like `peg/match-inner`, but when it doesn't match, returns a map of the farthest `position`
+/// it got to, its `line` and `column`, what it `expected` there, and the `stack` of rules it was in.
+///
+/// If `report` is truthy, the map also has a `report` string showing all that on the text.
+/// If it's a string, that's the name the text goes by.
+pub fn match_verbose(
+    engine: &mut Engine,
+    env: Gc<GcCell<Namespace>>,
+    args: &[Value],
+) -> EvalResult {
+    check_argc(engine, args, 2, 4)?;
+
+    let peg = Peg::from_arg(engine, args)?;
+    let text = text_arg(engine, args)?;
+    let start_cursor = start_arg(engine, args, &text)?;
+    let report_name = match args.get(3).map(|report| &**report) {
+        Some(Expr::String(name)) => Some(String::from_utf8_lossy(name).into_owned()),
+        Some(_) if engine.is_truthy(args[3].to_owned()) => Some("<text>".to_owned()),
+        _ => None,
+    };
+
+    let mut executor = Executor::new(&peg, &text, engine, env);
+    executor.farthest = Some(Farthest {
+        position: start_cursor,
+        expected: Vec::new(),
+        stack: Vec::new(),
+    });
+    let matched = executor.execute_rule(peg.first_rule, &text, start_cursor)?;
+    let farthest = executor.farthest.take().unwrap();
+    if let Some((_, stack)) = matched {
+        return Ok(Engine::list_to_sexp(&stack));
+    }
+
+    let (line, column) = line_column(&text, farthest.position);
+    let stack = farthest
+        .stack
+        .iter()
+        .map(|name| Expr::symbol(engine.intern_symbol(name)))
+        .collect::<Vec<_>>();
+
+    let mut map = GcMap::new();
+    let mut entry = |engine: &mut Engine, key: &str, value: Value| {
+        map.insert(Expr::symbol(engine.intern_symbol(key)), value);
+    };
+    entry(engine, "position", Expr::integer(farthest.position as _));
+    entry(engine, "line", Expr::integer(line as _));
+    entry(engine, "column", Expr::integer(column as _));
+    let expected = farthest
+        .expected
+        .iter()
+        .map(|what| Expr::string(what.as_bytes().to_vec()))
+        .collect::<Vec<_>>();
+    entry(engine, "expected", Engine::list_to_sexp(&expected));
+    entry(engine, "stack", Engine::list_to_sexp(&stack));
+    if let Some(name) = report_name {
+        let report = farthest.report(&text, name);
+        entry(engine, "report", Expr::string(report.into_bytes()));
+    }
+    Ok(Gc::new(Expr::Map(map)))
+}
+
+/// The line and column of a byte position, both starting at 1. Columns are counted in chars.
+fn line_column(text: &[u8], position: usize) -> (usize, usize) {
+    let before = &text[..position];
+    let line = before.iter().filter(|&&b| b == b'\n').count() + 1;
+    let line_start = before
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |nl| nl + 1);
+    let column = String::from_utf8_lossy(&before[line_start..])
+        .chars()
+        .count()
+        + 1;
+    (line, column)
+}
+
+/// The parts of a `(peg "<bytecode>" . exprs)` list.
+pub struct Peg {
+    pub(super) bytecode: ByteStr,
+    pub(super) format: Format,
+    pub(super) first_rule: usize,
+    pub(super) exprs: Vec<Value>,
+}
+
+impl Peg {
+    /// Pull apart the PEG in the first argument.
+    pub fn from_arg(engine: &mut Engine, args: &[Value]) -> Result<Peg, Exception> {
+        let bad = |engine: &mut Engine| bad_arg_type(engine, args[0].to_owned(), 0, "PEG");
+        let peg = match engine.sexp_to_list(args[0].to_owned())? {
+            Some(it) => it,
+            None => return Err(bad(engine)),
+        };
+        let (peg_sym, bytecode, exprs) = if let [peg_sym, bytecode, exprs @ ..] = peg.as_slice() {
+            (peg_sym, bytecode, exprs)
+        } else {
+            return Err(bad(engine));
+        };
+        let reference_sym = engine.intern_symbol("peg");
+        if peg_sym != &Expr::symbol(reference_sym) {
+            return Err(bad(engine));
+        }
+        let bytecode = match &**bytecode {
+            Expr::String(s) => s.clone(),
+            _ => return Err(bad(engine)),
+        };
+        let (format, first_rule) = match Format::read(&bytecode) {
+            Some(it) => it,
+            None => {
+                return Err(engine.make_err(
+                    "peg/invalid-bytecode",
+                    "the bytecode's header is not a version this can run",
+                    Some(args[0].to_owned()),
+                ))
+            }
+        };
+
+        // Bytecode can come from anywhere, so check it before trusting it.
+        // PEGs can't be changed, so one that's been checked doesn't need checking again.
+        if !engine
+            .verified_pegs
+            .iter()
+            .any(|peg| Gc::ptr_eq(peg, &args[0]))
+        {
+            if let Err(problem) = verify(&bytecode, format, first_rule, exprs) {
+                return Err(engine.make_err(
+                    "peg/invalid-bytecode",
+                    problem,
+                    Some(args[0].to_owned()),
+                ));
+            }
+            let verified = &mut engine.verified_pegs;
+            if verified.len() >= VERIFIED_PEGS_KEPT {
+                verified.pop_front();
+            }
+            verified.push_back(args[0].to_owned());
+        }
+
+        Ok(Peg {
+            bytecode,
+            format,
+            first_rule,
+            exprs: exprs.to_owned(),
+        })
+    }
+}
+
+/// The text to match against, in the second argument.
+pub fn text_arg(engine: &mut Engine, args: &[Value]) -> Result<ByteStr, Exception> {
+    match &*args[1] {
+        Expr::String(s) => Ok(s.clone()),
+        _ => Err(bad_arg_type(engine, args[1].to_owned(), 1, "string")),
+    }
+}
+
+/// The optional byte index to start matching at, in the third argument.
+pub fn start_arg(engine: &mut Engine, args: &[Value], text: &[u8]) -> Result<usize, Exception> {
+    match args.get(2).map(|cursor| &**cursor) {
+        Some(&Expr::Integer(cursor)) if cursor >= 0 && cursor as usize <= text.len() => {
+            Ok(cursor as usize)
+        }
+        Some(_) => Err(bad_arg_type(
+            engine,
+            args[2].to_owned(),
+            2,
+            "optional position in the text",
+        )),
+        None => Ok(0),
+    }
+}
+
 /// eeeexxecutor!
-struct Executor<'code, 'engine> {
+pub struct Executor<'code, 'engine> {
+    /// The whole text we're matching against, so captures can share it.
+    text: ByteStr,
     bytecode: &'code [u8],
+    format: Format,
     exprs: Vec<Value>,
 
     engine: &'engine mut Engine,
     env: Gc<GcCell<Namespace>>,
 
     debug: bool,
+    memo: Option<Memo>,
+    /// Only kept track of for `peg/match-verbose`.
+    farthest: Option<Farthest<'code>>,
+    /// Names of the rules we're in right now.
+    rule_stack: Vec<&'code [u8]>,
+    /// Tagged captures, most recent last.
+    tagged: Tagged,
+}
+
+pub type MatchResult = Option<(usize, Vec<Value>)>;
+/// Tagged captures, as `(tag, capture)`.
+type Tagged = Vec<(Value, Value)>;
+
+/// Packrat memo table of the results of rules at text positions.
+struct Memo {
+    /// Maps `(rule pointer, text cursor)` to what that rule matched there,
+    /// and the tags it added so they can be added again.
+    table: HashMap<(usize, usize), (MatchResult, Tagged)>,
+    /// Once the table has this many results it stops saving new ones.
+    capacity: usize,
+    /// Rules that can get to a `backref`, which are never saved.
+    sees_tags: HashSet<usize>,
+}
+
+impl Memo {
+    fn new(capacity: usize, peg: &Peg) -> Result<Self, String> {
+        let rules = reachable(&peg.bytecode, peg.format, peg.first_rule)?;
+        let mut sees_tags = HashSet::new();
+        loop {
+            let mut changed = false;
+            for (&ptr, rule) in &rules {
+                let sees = matches!(rule.opcode, Opcode::Backref)
+                    || rule.subrules().any(|sub| sees_tags.contains(&sub));
+                if sees && sees_tags.insert(ptr) {
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        Ok(Self {
+            table: HashMap::new(),
+            capacity,
+            sees_tags,
+        })
+    }
+}
+
+/// The farthest a match got before failing, and what it wanted there.
+struct Farthest<'code> {
+    position: usize,
+    /// What would have matched there, without repeats.
+    expected: Vec<String>,
+    /// Names of the rules it was in when it first got there.
+    stack: Vec<&'code [u8]>,
+}
+
+impl Farthest<'_> {
+    /// Show where it failed on the text, the same way syntax errors are shown.
+    fn report(&self, text: &[u8], name: String) -> String {
+        // ariadne doesn't like carriage returns, and counts in chars
+        let crs = text[..self.position]
+            .iter()
+            .filter(|&&b| b == b'\r')
+            .count();
+        let text = text
+            .iter()
+            .copied()
+            .filter(|&b| b != b'\r')
+            .collect::<Vec<_>>();
+        let mut source = String::from_utf8_lossy(&text).into_owned();
+        let offset = String::from_utf8_lossy(&text[..self.position - crs])
+            .chars()
+            .count();
+        // Failing at the end of the text still needs a char to point at
+        if offset >= source.chars().count() {
+            source.push(' ');
+        }
+
+        let expected = match &self.expected[..] {
+            [] => "expected something else".to_owned(),
+            [one] => format!("expected {}", one),
+            many => format!("expected one of {}", many.join(", ")),
+        };
+        let mut report = Report::build(ReportKind::Error, &name, offset)
+            .with_config(report_config().with_color(false))
+            .with_message("the PEG didn't match")
+            .with_label(Label::new((name.clone(), offset..offset + 1)).with_message(expected));
+        if !self.stack.is_empty() {
+            let stack = self.stack.iter().map(|name| BstrFmt(name).to_string());
+            report = report.with_note(format!("in rule {}", stack.collect::<Vec<_>>().join(" > ")));
+        }
+
+        let mut out = Vec::new();
+        report
+            .finish()
+            .write(ariadne::sources(std::iter::once((name, source))), &mut out)
+            .unwrap();
+        String::from_utf8_lossy(&out).into_owned()
+    }
+}
+
+impl<'code, 'engine> Executor<'code, 'engine> {
+    pub fn new(
+        peg: &'code Peg,
+        text: &ByteStr,
+        engine: &'engine mut Engine,
+        env: Gc<GcCell<Namespace>>,
+    ) -> Self {
+        Executor {
+            text: text.clone(),
+            bytecode: &peg.bytecode,
+            format: peg.format,
+            exprs: peg.exprs.clone(),
+            engine,
+            env,
+            debug: false,
+            memo: None,
+            farthest: None,
+            rule_stack: Vec::new(),
+            tagged: Vec::new(),
+        }
+    }
 }
 
 impl<'code> Executor<'code, '_> {
+    pub fn engine(&mut self) -> &mut Engine {
+        self.engine
+    }
+
+    /// Try matching the whole PEG starting at this position, forgetting tags from before.
+    pub fn match_at(
+        &mut self,
+        peg: &Peg,
+        text: &[u8],
+        position: usize,
+    ) -> Result<MatchResult, Exception> {
+        self.tagged.clear();
+        self.execute_rule(peg.first_rule, text, position)
+    }
+
+    /// Note that something else was wanted at this position, for `peg/match-verbose`.
+    fn expected(&mut self, position: usize, what: impl FnOnce() -> String) {
+        let farthest = match &mut self.farthest {
+            Some(it) => it,
+            None => return,
+        };
+        let further = position > farthest.position;
+        if further || (position == farthest.position && farthest.expected.is_empty()) {
+            farthest.position = position;
+            farthest.expected.clear();
+            farthest.stack = self.rule_stack.clone();
+        }
+        if position == farthest.position {
+            let what = what();
+            if !farthest.expected.contains(&what) {
+                farthest.expected.push(what);
+            }
+        }
+    }
+
+    /// Match the rule at the bytecode cursor, or look up what it matched last time.
     fn execute_rule(
+        &mut self,
+        bytecode_cursor: usize,
+        full_text: &[u8],
+        text_cursor: usize,
+    ) -> Result<MatchResult, Exception> {
+        let key = (bytecode_cursor, text_cursor);
+        if let Some(memo) = &self.memo {
+            if let Some((matched, tagged)) = memo.table.get(&key) {
+                if self.debug {
+                    println!(
+                        "^^^ memoized rule at {} at textidx {}",
+                        bytecode_cursor, text_cursor
+                    );
+                }
+                self.tagged.extend(tagged.iter().cloned());
+                return Ok(matched.clone());
+            }
+        }
+
+        let tagged_before = self.tagged.len();
+        let matched = self.run_rule(bytecode_cursor, full_text, text_cursor)?;
+        if matched.is_none() {
+            // Tags from a failed match don't count
+            self.tagged.truncate(tagged_before);
+        }
+        if let Some(memo) = &mut self.memo {
+            if memo.table.len() < memo.capacity && !memo.sees_tags.contains(&bytecode_cursor) {
+                let tagged = self.tagged[tagged_before..].to_vec();
+                memo.table.insert(key, (matched.clone(), tagged));
+            }
+        }
+        Ok(matched)
+    }
+
+    fn run_rule(
         &mut self,
         mut bytecode_cursor: usize,
         full_text: &[u8],
         text_cursor: usize,
-    ) -> Result<Option<(usize, Vec<Value>)>, Exception> {
+    ) -> Result<MatchResult, Exception> {
         let text = &full_text[text_cursor..];
         let opc = self.read_opcode(&mut bytecode_cursor)?;
 
@@ -105,10 +482,14 @@ impl<'code> Executor<'code, '_> {
 
         Ok(match opc {
             Opcode::CharCount => {
-                let len = self.read_u(&mut bytecode_cursor)? as usize;
+                let len = self.read_u(&mut bytecode_cursor)?;
                 if text.len() >= len {
                     Some((len, vec![]))
                 } else {
+                    self.expected(text_cursor, || match len {
+                        1 => "any char".to_owned(),
+                        _ => format!("{} chars", len),
+                    });
                     None
                 }
             }
@@ -117,16 +498,18 @@ impl<'code> Executor<'code, '_> {
                 if text.starts_with(string) {
                     Some((string.len(), vec![]))
                 } else {
+                    self.expected(text_cursor, || format!("{:?}", BstrFmt(string)));
                     None
                 }
             }
             Opcode::Range => {
+                let string = self.read_string(&mut bytecode_cursor)?;
                 let chr = if let Some(c) = text.first() {
                     *c
                 } else {
+                    self.expected(text_cursor, || format!("(range {:?})", BstrFmt(string)));
                     return Ok(None);
                 };
-                let string = self.read_string(&mut bytecode_cursor)?;
                 if string.len() % 2 != 0 {
                     return Err(self.engine.make_err(
                         "peg/match/range/bad-len",
@@ -141,27 +524,27 @@ impl<'code> Executor<'code, '_> {
                 if matches {
                     Some((1, vec![]))
                 } else {
+                    self.expected(text_cursor, || {
+                        let ranges = string.chunks_exact(2).map(|r| format!(" {:?}", BstrFmt(r)));
+                        format!("(range{})", ranges.collect::<String>())
+                    });
                     None
                 }
             }
             Opcode::Set => {
-                let chr = if let Some(c) = text.first() {
-                    *c
-                } else {
-                    return Ok(None);
-                };
                 let string = self.read_string(&mut bytecode_cursor)?;
-                let matches = string.contains(&chr);
+                let matches = text.first().is_some_and(|chr| string.contains(chr));
                 if matches {
                     Some((1, vec![]))
                 } else {
+                    self.expected(text_cursor, || format!("(set {:?})", BstrFmt(string)));
                     None
                 }
             }
             Opcode::Choice => {
                 let subrule_count = self.read_byte(&mut bytecode_cursor)?;
                 for _ in 0..subrule_count {
-                    let ruleptr = self.read_u(&mut bytecode_cursor)? as usize;
+                    let ruleptr = self.read_u(&mut bytecode_cursor)?;
                     let matched = self.execute_rule(ruleptr, full_text, text_cursor)?;
                     if matched.is_some() {
                         // yay!
@@ -176,7 +559,7 @@ impl<'code> Executor<'code, '_> {
                 let mut subcursor = 0;
                 let mut stack = vec![];
                 for _ in 0..subrule_count {
-                    let ruleptr = self.read_u(&mut bytecode_cursor)? as usize;
+                    let ruleptr = self.read_u(&mut bytecode_cursor)?;
                     let matched = self.execute_rule(ruleptr, full_text, text_cursor + subcursor)?;
                     let (len, substack) = if let Some(it) = matched {
                         it
@@ -189,8 +572,8 @@ impl<'code> Executor<'code, '_> {
                 Some((subcursor, stack))
             }
             Opcode::Split => {
-                let mainptr = self.read_u(&mut bytecode_cursor)? as _;
-                let sepptr = self.read_u(&mut bytecode_cursor)? as _;
+                let mainptr = self.read_u(&mut bytecode_cursor)?;
+                let sepptr = self.read_u(&mut bytecode_cursor)?;
 
                 // (split w ",")
                 // "a,b,c" should match all
@@ -247,13 +630,13 @@ impl<'code> Executor<'code, '_> {
             | Opcode::AtMost
             | Opcode::Count
             | Opcode::Between => {
-                let ruleptr = self.read_u(&mut bytecode_cursor)? as usize;
+                let ruleptr = self.read_u(&mut bytecode_cursor)?;
                 let (min, max) = match opc {
                     Opcode::Any => (0, usize::MAX),
                     Opcode::Some => (1, usize::MAX),
                     Opcode::Opt => (0, 1),
                     Opcode::AtLeast | Opcode::AtMost | Opcode::Count => {
-                        let n = self.read_u(&mut bytecode_cursor)? as usize;
+                        let n = self.read_u(&mut bytecode_cursor)?;
                         match opc {
                             Opcode::AtLeast => (n, usize::MAX),
                             Opcode::AtMost => (0, n),
@@ -264,7 +647,7 @@ impl<'code> Executor<'code, '_> {
                     Opcode::Between => {
                         let min = self.read_u(&mut bytecode_cursor)?;
                         let max = self.read_u(&mut bytecode_cursor)?;
-                        (min as _, max as _)
+                        (min, max)
                     }
                     _ => unreachable!(),
                 };
@@ -299,18 +682,18 @@ impl<'code> Executor<'code, '_> {
                 unreachable!()
             }
             Opcode::Capture => {
-                let ruleptr = self.read_u(&mut bytecode_cursor)? as usize;
+                let ruleptr = self.read_u(&mut bytecode_cursor)?;
                 match self.execute_rule(ruleptr, full_text, text_cursor) {
                     Ok(Some((len, mut stack))) => {
-                        let matched = &text[..len];
-                        stack.push(Expr::string(matched.to_vec()));
+                        let matched = self.text.slice(text_cursor..text_cursor + len);
+                        stack.push(Gc::new(Expr::String(matched)));
                         Some((len, stack))
                     }
                     ono => return ono,
                 }
             }
             Opcode::Replace => {
-                let ruleptr = self.read_u(&mut bytecode_cursor)? as usize;
+                let ruleptr = self.read_u(&mut bytecode_cursor)?;
                 let replacer = self.read_expr(&mut bytecode_cursor)?.to_owned();
 
                 let matched = self.execute_rule(ruleptr, full_text, text_cursor)?;
@@ -329,7 +712,7 @@ impl<'code> Executor<'code, '_> {
                 }
             }
             Opcode::Group => {
-                let ruleptr = self.read_u(&mut bytecode_cursor)? as usize;
+                let ruleptr = self.read_u(&mut bytecode_cursor)?;
                 match self.execute_rule(ruleptr, full_text, text_cursor) {
                     Ok(Some((len, stack))) => {
                         let stack_list = Engine::list_to_sexp(&stack);
@@ -339,8 +722,8 @@ impl<'code> Executor<'code, '_> {
                 }
             }
             Opcode::If | Opcode::IfNot => {
-                let rule_if = self.read_u(&mut bytecode_cursor)? as usize;
-                let rule_then = self.read_u(&mut bytecode_cursor)? as usize;
+                let rule_if = self.read_u(&mut bytecode_cursor)?;
+                let rule_then = self.read_u(&mut bytecode_cursor)?;
                 let matched = self.execute_rule(rule_if, full_text, text_cursor)?;
                 match (opc, matched) {
                     (Opcode::If, Some(_)) | (Opcode::IfNot, None) => {
@@ -350,7 +733,7 @@ impl<'code> Executor<'code, '_> {
                 }
             }
             Opcode::Not => {
-                let ruleptr = self.read_u(&mut bytecode_cursor)? as usize;
+                let ruleptr = self.read_u(&mut bytecode_cursor)?;
                 match self.execute_rule(ruleptr, full_text, text_cursor)? {
                     Some(_) => None,
                     None => Some((0, vec![])),
@@ -358,39 +741,152 @@ impl<'code> Executor<'code, '_> {
             }
             Opcode::Position => Some((0, vec![Expr::integer(text_cursor as _)])),
             Opcode::All => {
-                let ruleptr = self.read_u(&mut bytecode_cursor)? as usize;
+                let ruleptr = self.read_u(&mut bytecode_cursor)?;
                 match self.execute_rule(ruleptr, full_text, text_cursor)? {
                     Some((len, stack)) if len == text.len() => Some((len, stack)),
-                    _ => None,
+                    Some((len, _)) => {
+                        self.expected(text_cursor + len, || "the end of the text".to_owned());
+                        None
+                    }
+                    None => None,
                 }
             }
             Opcode::Jump => {
-                let ruleptr = self.read_u(&mut bytecode_cursor)? as usize;
+                let ruleptr = self.read_u(&mut bytecode_cursor)?;
                 self.execute_rule(ruleptr, full_text, text_cursor)?
             }
+            Opcode::Constant => {
+                let constant = self.read_expr(&mut bytecode_cursor)?.to_owned();
+                Some((0, vec![constant]))
+            }
+            Opcode::Tag => {
+                let ruleptr = self.read_u(&mut bytecode_cursor)?;
+                let tag = self.read_expr(&mut bytecode_cursor)?.to_owned();
+                let matched = self.execute_rule(ruleptr, full_text, text_cursor)?;
+                if let Some(last) = matched.as_ref().and_then(|(_, stack)| stack.last()) {
+                    self.tagged.push((tag, last.to_owned()));
+                }
+                matched
+            }
+            Opcode::Backref => {
+                let tag = self.read_expr(&mut bytecode_cursor)?.to_owned();
+                let found = self.tagged.iter().rev().find(|(other, _)| *other == tag);
+                found.map(|(_, capture)| (0, vec![capture.to_owned()]))
+            }
+            Opcode::Line | Opcode::Column => {
+                let (line, column) = line_column(full_text, text_cursor);
+                let n = if let Opcode::Line = opc { line } else { column };
+                Some((0, vec![Expr::integer(n as _)]))
+            }
+            Opcode::To | Opcode::Thru => {
+                let ruleptr = self.read_u(&mut bytecode_cursor)?;
+                let mut found = None;
+                for skipped in 0..=text.len() {
+                    let matched = self.execute_rule(ruleptr, full_text, text_cursor + skipped)?;
+                    if let Some((len, stack)) = matched {
+                        let len = if let Opcode::To = opc {
+                            skipped
+                        } else {
+                            skipped + len
+                        };
+                        found = Some((len, stack));
+                        break;
+                    }
+                }
+                found
+            }
+            Opcode::Look => {
+                let ruleptr = self.read_u(&mut bytecode_cursor)?;
+                let offset = match **self.read_expr(&mut bytecode_cursor)? {
+                    Expr::Integer(offset) => offset,
+                    _ => {
+                        return Err(self.engine.make_err(
+                            "peg/match/look/offset",
+                            "look needs an integer offset",
+                            None,
+                        ))
+                    }
+                };
+                match (text_cursor as i64).checked_add(offset) {
+                    Some(at) if (0..=full_text.len() as i64).contains(&at) => self
+                        .execute_rule(ruleptr, full_text, at as usize)?
+                        .map(|(_, stack)| (0, stack)),
+                    _ => None,
+                }
+            }
+            Opcode::MatchTime => {
+                let ruleptr = self.read_u(&mut bytecode_cursor)?;
+                let fun = self.read_expr(&mut bytecode_cursor)?.to_owned();
+                match self.execute_rule(ruleptr, full_text, text_cursor)? {
+                    Some((len, stack)) => {
+                        let result = self.engine.call(self.env.to_owned(), fun, stack)?;
+                        if self.engine.is_truthy(result.to_owned()) {
+                            Some((len, vec![result]))
+                        } else {
+                            None
+                        }
+                    }
+                    None => None,
+                }
+            }
+            Opcode::Accumulate => {
+                let ruleptr = self.read_u(&mut bytecode_cursor)?;
+                match self.execute_rule(ruleptr, full_text, text_cursor)? {
+                    Some((len, stack)) => {
+                        let mut out = Vec::new();
+                        for capture in stack {
+                            push_printed(self.engine, &mut out, capture)?;
+                        }
+                        Some((len, vec![Expr::string(out)]))
+                    }
+                    None => None,
+                }
+            }
+            Opcode::Named => {
+                let ruleptr = self.read_u(&mut bytecode_cursor)?;
+                let name = self.read_string(&mut bytecode_cursor)?;
+                self.rule_stack.push(name);
+                let matched = self.execute_rule(ruleptr, full_text, text_cursor);
+                self.rule_stack.pop();
+                let matched = matched?;
+                if matched.is_none() {
+                    self.expected(text_cursor, || BstrFmt(name).to_string());
+                }
+                matched
+            }
         })
     }
 
-    fn read_u(&mut self, cursor: &mut usize) -> Result<U, Exception> {
-        let next_cursor = *cursor + U_SIZE;
+    fn read_u(&mut self, cursor: &mut usize) -> Result<usize, Exception> {
+        let u = self.read_uint(cursor, self.format.u_size, "peg/match/oob/u", "a u")?;
+
+        if self.debug {
+            println!("read u {} at {}", u, cursor);
+        }
+        Ok(u)
+    }
+
+    /// Read a big-endian number this many bytes wide.
+    fn read_uint(
+        &mut self,
+        cursor: &mut usize,
+        size: usize,
+        err_name: &str,
+        what: &str,
+    ) -> Result<usize, Exception> {
+        let next_cursor = *cursor + size;
         let data = match self.bytecode.get(*cursor..next_cursor) {
             Some(it) => it,
             None => {
                 return Err(self.engine.make_err(
-                    "peg/match/oob/u",
-                    "went out of bounds when reading a u".to_string(),
+                    err_name,
+                    format!("went out of bounds when reading {}", what),
                     None,
                 ))
             }
         };
-        let data = data.try_into().unwrap();
-        let u = U::from_be_bytes(data);
-
-        if self.debug {
-            println!("read u {} at {}", u, cursor);
-        }
-
-        *cursor += U_SIZE;
+        let u = data.iter().fold(0, |acc, &b| acc << 8 | b as usize);
+        *cursor = next_cursor;
         Ok(u)
     }
 
@@ -439,8 +935,8 @@ impl<'code> Executor<'code, '_> {
     }
 
     fn read_string(&mut self, cursor: &mut usize) -> Result<&'code [u8], Exception> {
-        let ptr = self.read_u(cursor)? as usize;
-        let len = self.read_u(cursor)? as usize;
+        let ptr = self.read_u(cursor)?;
+        let len = self.read_u(cursor)?;
 
         if let Some(s) = self.bytecode.get(ptr..ptr + len) {
             if self.debug {
@@ -467,7 +963,12 @@ impl<'code> Executor<'code, '_> {
     }
 
     fn read_expr<'a>(&'a mut self, cursor: &mut usize) -> Result<&'a Value, Exception> {
-        let idx = self.read_byte(cursor)? as usize;
+        let idx = self.read_uint(
+            cursor,
+            self.format.expr_size,
+            "peg/match/oob/expr",
+            "an expr",
+        )?;
         if let Some(expr) = self.exprs.get(idx) {
             Ok(expr)
         } else {
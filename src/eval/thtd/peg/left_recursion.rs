@@ -0,0 +1,158 @@
+//! Finding rules that call themselves without matching anything first,
+//! which would recurse forever when run.
+
+use std::collections::{HashMap, HashSet};
+
+use super::{
+    decode::{reachable, Arg, Rule},
+    Format, Opcode,
+};
+use crate::{Expr, Value};
+
+/// Find a cycle of rules that can call each other at the same text position,
+/// starting from the rule at `start`. `exprs` are the PEG's exprs, for `look` offsets.
+///
+/// Returns the pointers of the rules in the cycle, with the first one repeated at the end.
+pub fn find_cycle(
+    bytecode: &[u8],
+    format: Format,
+    start: usize,
+    exprs: &[Value],
+) -> Result<Option<Vec<usize>>, String> {
+    let rules = reachable(bytecode, format, start)?;
+
+    // Work out which can match nothing, assuming none can until shown otherwise
+    let mut nullable = HashSet::new();
+    loop {
+        let mut changed = false;
+        for (&ptr, rule) in &rules {
+            if !nullable.contains(&ptr) && is_nullable(rule, &nullable) {
+                nullable.insert(ptr);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let mut finder = CycleFinder {
+        rules: &rules,
+        nullable: &nullable,
+        exprs,
+        path: Vec::new(),
+        done: HashSet::new(),
+    };
+    Ok(finder.visit(start))
+}
+
+/// Can this rule match without taking up any text?
+fn is_nullable(rule: &Rule, nullable: &HashSet<usize>) -> bool {
+    let sub = |ptr: usize| nullable.contains(&ptr);
+    let first = rule.subrules().next();
+    let n = rule.us().next().unwrap_or(0);
+    match rule.opcode {
+        Opcode::CharCount => n == 0,
+        Opcode::Literal => matches!(rule.args[..], [Arg::Str { len: 0, .. }]),
+        Opcode::Range | Opcode::Set => false,
+        Opcode::Choice => rule.subrules().any(sub),
+        Opcode::Sequence => rule.subrules().all(sub),
+        Opcode::Any
+        | Opcode::Opt
+        | Opcode::AtMost
+        | Opcode::Not
+        | Opcode::Position
+        | Opcode::Constant
+        | Opcode::Backref
+        | Opcode::Line
+        | Opcode::Column
+        | Opcode::To
+        | Opcode::Look => true,
+        Opcode::AtLeast | Opcode::Count | Opcode::Between => n == 0 || first.is_some_and(sub),
+        // The first rule of these only looks ahead
+        Opcode::If | Opcode::IfNot => rule.subrules().nth(1).is_some_and(sub),
+        Opcode::Split
+        | Opcode::Some
+        | Opcode::Capture
+        | Opcode::Replace
+        | Opcode::Group
+        | Opcode::All
+        | Opcode::Jump
+        | Opcode::Named
+        | Opcode::Tag
+        | Opcode::Thru
+        | Opcode::MatchTime
+        | Opcode::Accumulate => first.is_some_and(sub),
+    }
+}
+
+struct CycleFinder<'a> {
+    rules: &'a HashMap<usize, Rule>,
+    /// Rules that can match without taking up any text.
+    nullable: &'a HashSet<usize>,
+    exprs: &'a [Value],
+    /// The rules we're inside of right now.
+    path: Vec<usize>,
+    /// Rules we've already looked through everything under.
+    done: HashSet<usize>,
+}
+
+impl CycleFinder<'_> {
+    fn visit(&mut self, ptr: usize) -> Option<Vec<usize>> {
+        if let Some(idx) = self.path.iter().position(|&p| p == ptr) {
+            let mut cycle = self.path[idx..].to_vec();
+            cycle.push(ptr);
+            return Some(cycle);
+        }
+        if self.done.contains(&ptr) {
+            return None;
+        }
+
+        self.path.push(ptr);
+        for next in self.at_same_position(ptr) {
+            if let Some(cycle) = self.visit(next) {
+                return Some(cycle);
+            }
+        }
+        self.path.pop();
+        self.done.insert(ptr);
+        None
+    }
+
+    /// The rules that can be run at the same text position as this one starts at.
+    fn at_same_position(&self, ptr: usize) -> Vec<usize> {
+        let rule = &self.rules[&ptr];
+        match rule.opcode {
+            // Only up to the first one that has to match something
+            Opcode::Sequence => {
+                let mut out = Vec::new();
+                for sub in rule.subrules() {
+                    out.push(sub);
+                    if !self.nullable.contains(&sub) {
+                        break;
+                    }
+                }
+                out
+            }
+            // The separator only runs after something
+            Opcode::Split => {
+                let subrules = rule.subrules().collect::<Vec<_>>();
+                if self.nullable.contains(&subrules[0]) {
+                    subrules
+                } else {
+                    subrules[..1].to_vec()
+                }
+            }
+            // Looking somewhere else runs the rule at a different position
+            Opcode::Look => match rule.args[..] {
+                [Arg::Rule(sub), Arg::Expr(idx)]
+                    if matches!(self.exprs.get(idx).map(|it| &**it), Some(Expr::Integer(0))) =>
+                {
+                    vec![sub]
+                }
+                _ => Vec::new(),
+            },
+            _ => rule.subrules().collect(),
+        }
+    }
+}
@@ -9,17 +9,23 @@
 //! A full PEG is a list, with the first element being the symbol `'peg`, the second element
 //! a string for the *bytecode* and the remainder being a list of *Value*s.
 //!
-//! **Bytecode** is a list of one or more *rules*, possibly interspersed with *string*s.
+//! **Bytecode** is a *header* followed by one or more *rules*, possibly interspersed with *string*s.
+//! The first rule is the one that gets matched.
+//!
+//! The **header** is a `0` byte (which can't be an opcode), the version of the format
+//! (currently 1), and how many bytes wide a `u` is: 2, or 4 if something didn't fit in 2.
+//! Bytecode without a header is from before there were headers, and has 2-byte `u`s
+//! and 1-byte `expr`s.
 //!
 //! **Rules** are a single byte *opcode*, followed by 0 or more *arguments*.
 //!
 //! **Arguments** are all typed. Each argument type is the same length.
 //!
-//! - `u`: A 2-byte signed integer
+//! - `u`: An unsigned integer, 2 or 4 bytes wide depending on the header
 //! - `byte`: A 1-byte unsigned int
 //! - `str`: A string. `u` for the absolute position in the bytecode of the string, and `u` for its len.
 //! - `rule`: A `u` for the absolute position in the bytecode of *another* rule.
-//! - `expr`: 1 byte representing the index of the expression in the cdr of the top-level list.
+//! - `expr`: A `u` for the index of the expression in the cdr of the top-level list.
 //!
 //! **Strings** are just bags of bytes. The length is stored with the ptr, and there is no terminating \0
 //! or similar.
@@ -37,27 +43,91 @@
 //!
 //! Maps write the `main` rule. Then, when we recursively write *that* rule's subrules,
 //! we know to look up symbols in that context map.
+//!
+//! Once it's all written, we go back over the bytecode looking for rules that can call themselves
+//! without matching anything first. Those would recurse forever, so they're a compile error.
+//!
+//! ## Running Bytecode
+//!
+//! A PEG is just a list, so its bytecode could have come from anywhere. Before a PEG is matched with,
+//! its header and every rule it can reach are checked over (see `verify`), and bad bytecode is a
+//! `peg/invalid-bytecode` error instead of going wrong halfway through a match. The engine remembers
+//! the last few PEG values it checked, so matching with the same one again is cheap.
 mod compile;
 mod compression;
+mod decode;
 mod execute;
+mod left_recursion;
+mod search;
+mod verify;
 
 pub use compile::compile;
-pub use execute::match_;
+pub use decode::disassemble;
+pub use execute::{match_, match_verbose};
+pub use search::{find, find_all, replace, split};
 
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
-type U = u16;
-type RULEPTR = U;
-type EXPR = u8;
 type BYTE = u8;
 
-const U_SIZE: usize = std::mem::size_of::<U>();
-const RULEPTR_SIZE: usize = std::mem::size_of::<RULEPTR>();
-const EXPR_SIZE: usize = std::mem::size_of::<EXPR>();
 const BYTE_SIZE: usize = std::mem::size_of::<BYTE>();
 
-/// One `u` for the pointer, one `u` for the len.
-const STR_SIZE: usize = 2 * U_SIZE;
+/// Version of the bytecode format, written in the header.
+const VERSION: u8 = 1;
+/// A 0 byte, the version, and the width of a `u`.
+const HEADER_SIZE: usize = 3;
+
+/// How wide the numbers in some bytecode are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Format {
+    /// Bytes in a `u`, which rule and string pointers are too.
+    u_size: usize,
+    /// Bytes in an `expr`.
+    expr_size: usize,
+}
+
+impl Format {
+    /// What gets written unless something doesn't fit.
+    const NARROW: Format = Format {
+        u_size: 2,
+        expr_size: 2,
+    };
+    const WIDE: Format = Format {
+        u_size: 4,
+        expr_size: 4,
+    };
+    /// Bytecode from before there was a header.
+    const LEGACY: Format = Format {
+        u_size: 2,
+        expr_size: 1,
+    };
+
+    /// One `u` for the pointer, one `u` for the len.
+    fn str_size(self) -> usize {
+        2 * self.u_size
+    }
+
+    /// The biggest number a `u` can hold.
+    fn u_max(self) -> u64 {
+        u64::MAX >> (64 - 8 * self.u_size)
+    }
+
+    fn header(self) -> [u8; HEADER_SIZE] {
+        [0, VERSION, self.u_size as u8]
+    }
+
+    /// Work out the format of some bytecode, and where its first rule is.
+    ///
+    /// Returns `None` if the header isn't one we know.
+    fn read(bytecode: &[u8]) -> Option<(Format, usize)> {
+        match bytecode {
+            [0, VERSION, 2, ..] => Some((Format::NARROW, HEADER_SIZE)),
+            [0, VERSION, 4, ..] => Some((Format::WIDE, HEADER_SIZE)),
+            [0, ..] => None,
+            _ => Some((Format::LEGACY, 0)),
+        }
+    }
+}
 
 /// Size of the opcode
 const OPCODE_SIZE: usize = std::mem::size_of::<Opcode>();
@@ -176,4 +246,55 @@ enum Opcode {
     ///
     /// `rule`
     Jump,
+    /// Match the rule, which was looked up by this name. This is the same as a `Jump`,
+    /// except failures can say which rule they were in.
+    ///
+    /// `rule str`
+    Named,
+
+    // === More captures ===
+    // These come after the rest so older bytecode keeps its opcodes.
+    /// Match 0 chars and push the expression onto the capture stack.
+    ///
+    /// `expr`
+    Constant,
+    /// Run the rule, and tag its last capture with the expression so `Backref` can find it.
+    ///
+    /// `rule expr`
+    Tag,
+    /// Match 0 chars and push the latest capture tagged with the expression again.
+    /// Doesn't match if nothing has that tag.
+    ///
+    /// `expr`
+    Backref,
+    /// Match 0 chars and push the line number, starting at 1.
+    ///
+    /// `<no args>`
+    Line,
+    /// Match 0 chars and push the column number in chars, starting at 1.
+    ///
+    /// `<no args>`
+    Column,
+    /// Match everything up to where the rule next matches, not including it.
+    ///
+    /// `rule`
+    To,
+    /// Match everything up to where the rule next matches, including it.
+    ///
+    /// `rule`
+    Thru,
+    /// Match 0 chars if the rule matches this many bytes away, which can be negative.
+    /// Keeps the rule's captures.
+    ///
+    /// `rule expr`
+    Look,
+    /// Run the rule, then call the expression with its captures. If that returns
+    /// something truthy, that's the capture; otherwise this doesn't match.
+    ///
+    /// `rule expr`
+    MatchTime,
+    /// Run the rule, and put all its captures together into one string.
+    ///
+    /// `rule`
+    Accumulate,
 }
@@ -0,0 +1,169 @@
+//! Looking for a PEG anywhere in a text, instead of only at one position.
+
+use gc::{Gc, GcCell};
+
+use super::execute::{start_arg, text_arg, Executor, Peg};
+use crate::{
+    eval::{
+        bad_arg_type,
+        thtd::{check_argc, strings::push_printed},
+    },
+    ByteStr, Engine, EvalResult, Exception, Expr, Namespace, Value,
+};
+
+/// Where a match was, how long it was, and what it captured.
+type Found = (usize, usize, Vec<Value>);
+
+/// Find the first match at or after `start`.
+fn find_from(
+    executor: &mut Executor,
+    peg: &Peg,
+    text: &[u8],
+    start: usize,
+) -> Result<Option<Found>, Exception> {
+    for position in start..=text.len() {
+        if let Some((len, captures)) = executor.match_at(peg, text, position)? {
+            return Ok(Some((position, len, captures)));
+        }
+    }
+    Ok(None)
+}
+
+/// Where to look for the next match after this one, so empty matches don't repeat forever.
+fn after((position, len, _): &Found) -> usize {
+    position + (*len).max(1)
+}
+
+/// `(position . captures)`
+fn found_to_expr((position, _, captures): Found) -> Value {
+    Engine::list_to_improper_sexp(
+        &[Expr::integer(position as _)],
+        Engine::list_to_sexp(&captures),
+    )
+}
+
+/// `(peg/find-inner peg text [start])`
+///
+/// The first match at or after `start`, as `(position . captures)`, or false.
+pub fn find(engine: &mut Engine, env: Gc<GcCell<Namespace>>, args: &[Value]) -> EvalResult {
+    check_argc(engine, args, 2, 3)?;
+    let peg = Peg::from_arg(engine, args)?;
+    let text = text_arg(engine, args)?;
+    let start = start_arg(engine, args, &text)?;
+
+    let mut executor = Executor::new(&peg, &text, engine, env);
+    Ok(match find_from(&mut executor, &peg, &text, start)? {
+        Some(found) => found_to_expr(found),
+        None => Expr::bool(false),
+    })
+}
+
+/// `(peg/find-all-inner peg text [start])`
+///
+/// A lazy list of every match that doesn't overlap the one before, each as `(position . captures)`.
+pub fn find_all(engine: &mut Engine, env: Gc<GcCell<Namespace>>, args: &[Value]) -> EvalResult {
+    check_argc(engine, args, 2, 3)?;
+    let peg = Peg::from_arg(engine, args)?;
+    let text = text_arg(engine, args)?;
+    let start = start_arg(engine, args, &text)?;
+
+    let mut executor = Executor::new(&peg, &text, engine, env.to_owned());
+    let found = match find_from(&mut executor, &peg, &text, start)? {
+        Some(it) => it,
+        None => return Ok(Expr::nil()),
+    };
+
+    let next = after(&found);
+    // The rest is found by calling this again, when someone looks
+    let rest = if next <= text.len() {
+        let quote = Expr::symbol(engine.intern_symbol("quote"));
+        let this = Gc::new(Expr::NativeProcedure {
+            func: Ok(find_all as _),
+            name: engine.intern_symbol("peg/find-all-inner"),
+        });
+        let call = Engine::list_to_sexp(&[
+            this,
+            Engine::list_to_sexp(&[quote, args[0].to_owned()]),
+            args[1].to_owned(),
+            Expr::integer(next as _),
+        ]);
+        (call, false)
+    } else {
+        (Expr::nil(), true)
+    };
+    Ok(Gc::new(Expr::LazyPair(
+        GcCell::new((found_to_expr(found), true)),
+        GcCell::new(rest),
+        env,
+    )))
+}
+
+/// `(peg/replace-inner peg text start replacement all?)`
+///
+/// Replace the first match at or after `start`, or every match if `all?`.
+/// The replacement is a string, or a procedure called with the captures
+/// that returns what to put there.
+pub fn replace(engine: &mut Engine, env: Gc<GcCell<Namespace>>, args: &[Value]) -> EvalResult {
+    check_argc(engine, args, 5, 5)?;
+    let peg = Peg::from_arg(engine, args)?;
+    let text = text_arg(engine, args)?;
+    let start = start_arg(engine, args, &text)?;
+    let replacement = args[3].to_owned();
+    if !matches!(&*replacement, Expr::String(_)) && !replacement.is_callable() {
+        return Err(bad_arg_type(engine, replacement, 3, "string or procedure"));
+    }
+    let all = engine.is_truthy(args[4].to_owned());
+
+    let mut out = Vec::new();
+    let mut copied = 0;
+    let mut position = start;
+    let mut executor = Executor::new(&peg, &text, engine, env.to_owned());
+    while let Some(found) = find_from(&mut executor, &peg, &text, position)? {
+        let (at, len, captures) = &found;
+        out.extend_from_slice(&text[copied..*at]);
+        match &*replacement {
+            Expr::String(s) => out.extend_from_slice(s),
+            _ => {
+                let engine = executor.engine();
+                let replaced =
+                    engine.call(env.to_owned(), replacement.to_owned(), captures.to_owned())?;
+                push_printed(engine, &mut out, replaced)?;
+            }
+        }
+        copied = at + len;
+        position = after(&found);
+        if !all {
+            break;
+        }
+    }
+    out.extend_from_slice(&text[copied.min(text.len())..]);
+    Ok(Expr::string(out))
+}
+
+/// `(peg/split-inner peg text)`
+///
+/// The pieces of the text between matches. Empty matches don't split anything.
+pub fn split(engine: &mut Engine, env: Gc<GcCell<Namespace>>, args: &[Value]) -> EvalResult {
+    check_argc(engine, args, 2, 2)?;
+    let peg = Peg::from_arg(engine, args)?;
+    let text = text_arg(engine, args)?;
+
+    let mut pieces = Vec::new();
+    let mut piece_start = 0;
+    let mut position = 0;
+    let mut executor = Executor::new(&peg, &text, engine, env);
+    while let Some(found) = find_from(&mut executor, &peg, &text, position)? {
+        position = after(&found);
+        let (at, len, _) = found;
+        if len > 0 {
+            pieces.push(piece(&text, piece_start, at));
+            piece_start = at + len;
+        }
+    }
+    pieces.push(piece(&text, piece_start, text.len()));
+    Ok(Engine::list_to_sexp(&pieces))
+}
+
+fn piece(text: &ByteStr, start: usize, end: usize) -> Value {
+    Gc::new(Expr::String(text.slice(start..end)))
+}
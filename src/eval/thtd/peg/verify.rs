@@ -0,0 +1,78 @@
+//! Checking bytecode that might not have come from the compiler before running it,
+//! so that bad bytecode is an error up front instead of a crash halfway through a match.
+
+use std::collections::HashSet;
+
+use super::{
+    decode::{decode, Arg},
+    left_recursion::find_cycle,
+    Format, Opcode,
+};
+use crate::{Expr, Value};
+
+/// Check every rule that can be reached from `first_rule`.
+///
+/// Fails with a message saying what's wrong if any opcode is unknown, any argument runs off the end,
+/// any pointer or expr index points nowhere, a `look` or `cmt` has the wrong kind of expr,
+/// or any rules call each other without matching anything.
+pub fn verify(
+    bytecode: &[u8],
+    format: Format,
+    first_rule: usize,
+    exprs: &[Value],
+) -> Result<(), String> {
+    let expr_count = exprs.len();
+    let mut seen = HashSet::new();
+    let mut todo = vec![first_rule];
+    while let Some(ptr) = todo.pop() {
+        if !seen.insert(ptr) {
+            continue;
+        }
+        let rule = decode(bytecode, format, ptr)?;
+        for arg in &rule.args {
+            match *arg {
+                Arg::Str { ptr: str_ptr, len } => {
+                    if !matches!(str_ptr.checked_add(len), Some(end) if end <= bytecode.len()) {
+                        return Err(format!(
+                            "string at {} with length {} in the rule at {} is out of bounds",
+                            str_ptr, len, ptr
+                        ));
+                    }
+                    if matches!(rule.opcode, Opcode::Range) && len % 2 == 1 {
+                        return Err(format!(
+                            "range at {} needs a string of even length but got {}",
+                            ptr, len
+                        ));
+                    }
+                }
+                Arg::Expr(idx) if idx >= expr_count => {
+                    return Err(format!(
+                        "rule at {} uses expression {} but there are only {}",
+                        ptr, idx, expr_count
+                    ));
+                }
+                Arg::Rule(sub) => todo.push(sub),
+                _ => {}
+            }
+        }
+        // Some exprs have to be a certain kind of thing
+        match (rule.opcode, &rule.args[..]) {
+            (Opcode::Look, [_, Arg::Expr(idx)]) if !matches!(*exprs[*idx], Expr::Integer(_)) => {
+                return Err(format!("look at {} needs an integer offset", ptr));
+            }
+            (Opcode::MatchTime, [_, Arg::Expr(idx)]) if !exprs[*idx].is_callable() => {
+                return Err(format!("cmt at {} needs something callable", ptr));
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(cycle) = find_cycle(bytecode, format, first_rule, exprs)? {
+        let path = cycle.iter().map(|ptr| ptr.to_string()).collect::<Vec<_>>();
+        return Err(format!(
+            "rules would call themselves forever without matching anything: {}",
+            path.join(" -> ")
+        ));
+    }
+    Ok(())
+}
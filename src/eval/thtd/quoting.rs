@@ -1,6 +1,6 @@
 //! Quotes, unquotes, and quasiquotes.
 use super::*;
-use crate::eval::TailRec;
+use crate::{eval::TailRec, hash::GcMap};
 
 /// Note this is the "function" quote which should just return its argument.
 pub fn quote(
@@ -20,73 +20,174 @@ pub fn quasiquote(
     check_argc(engine, args, 1, 1)?;
 
     let arg = args[0].to_owned();
-    Ok(TailRec::Exit(quasi_helper(engine, env, arg, None)?))
+    Ok(TailRec::Exit(quasi_helper(engine, env, arg, 0)?))
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum QuoteForm {
+    Quasiquote,
+    Unquote,
+    UnquoteSplicing,
+}
+
+/// If this is `(quasiquote x)`, `(unquote x)` or `(unquote-splicing x)`,
+/// which one it is and the `x`.
+fn quote_form(
+    engine: &mut Engine,
+    expr: &Gc<Expr>,
+) -> Result<Option<(QuoteForm, Gc<Expr>)>, Exception> {
+    let (car, cdr) = match engine.split_cons_verb(expr)? {
+        Some(it) => it,
+        None => return Ok(None),
+    };
+    let form = match &*car {
+        Expr::Symbol(sym) => match engine.get_symbol_str(*sym) {
+            Some(b"quasiquote") => QuoteForm::Quasiquote,
+            Some(b"unquote") => QuoteForm::Unquote,
+            Some(b"unquote-splicing") => QuoteForm::UnquoteSplicing,
+            _ => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    match engine.split_cons_verb(&cdr)? {
+        Some((x, rest)) if matches!(&*rest, Expr::Nil) => Ok(Some((form, x))),
+        // Just a list with that symbol in it
+        _ if form == QuoteForm::Quasiquote => Ok(None),
+        _ => Err(bad_arg_type(engine, cdr, 1, "1-list")),
+    }
+}
+
+fn bad_splice(engine: &mut Engine) -> Exception {
+    engine.make_err(
+        "quote/bad-splice",
+        "used unquote-splice in illegal position".to_string(),
+        None,
+    )
+}
+
+/// Fill in the unquotes in something quasiquoted.
+///
+/// `depth` is how many quasiquotes it's nested in past the outermost one.
+/// Like in R7RS, only unquotes at depth 0 are evaluated; the others just
+/// take one off the depth of what's in them.
 fn quasi_helper(
     engine: &mut Engine,
     env: Gc<GcCell<Namespace>>,
     arg: Gc<Expr>,
-    pcdr: Option<Gc<Expr>>,
+    depth: usize,
+) -> EvalResult {
+    match &*arg {
+        Expr::Pair(..) | Expr::LazyPair(..) => quasi_list(engine, env, arg, depth),
+        Expr::Map(map) => quasi_map(engine, env, map, depth),
+        _ => Ok(arg),
+    }
+}
+
+fn quasi_list(
+    engine: &mut Engine,
+    env: Gc<GcCell<Namespace>>,
+    list: Gc<Expr>,
+    depth: usize,
 ) -> EvalResult {
-    let unquote = engine.find_symbol("unquote").unwrap();
-    let unquote_splice = engine.find_symbol("unquote-splicing").unwrap();
-    let arg = match &*arg {
-        Expr::Pair(..) | Expr::LazyPair(..) => {
-            let (car, cdr) = engine.split_cons(arg)?;
-            match &*car {
-                Expr::Symbol(sym) if *sym == unquote => {
-                    let actual_cdr = match &*cdr {
-                        Expr::Pair(..) | Expr::LazyPair(..) => {
-                            let (car, cdr) = engine.split_cons(cdr.clone())?;
-                            if let Expr::Nil = &*cdr {
-                                car
-                            } else {
-                                return Err(bad_arg_type(engine, cdr, 1, "1-list"));
-                            }
-                        }
-                        _ => return Err(bad_arg_type(engine, cdr, 1, "1-list")),
-                    };
-                    engine.eval_inner(env.clone(), actual_cdr)?
-                }
-                Expr::Symbol(sym) if *sym == unquote_splice => return match pcdr {
-                    Some(exp) => {
-                        let actual_cdr = match &*cdr {
-                            Expr::Pair(..) | Expr::LazyPair(..) => {
-                                let (car, cdr) = engine.split_cons(cdr.clone())?;
-                                if let Expr::Nil = &*cdr {
-                                    car
-                                } else {
-                                    return Err(bad_arg_type(engine, cdr, 1, "1-list"));
-                                }
-                            }
-                            _ => return Err(bad_arg_type(engine, cdr, 1, "1-list")),
-                        };
-                        let sexp = engine.eval_inner(env.clone(), actual_cdr.clone())?;
-                        let list = engine
-                            .sexp_to_list(sexp)?
-                            .ok_or_else(|| bad_arg_type(engine, actual_cdr, 1, "list"))?;
-                        let last = quasi_helper(engine, env, exp, None)?;
-                        Ok(Engine::list_to_improper_sexp(&list[..], last))
-                    }
-                    None => Err(engine.make_err(
-                        "quote/bad-splice",
-                        "used unquote-splice in illegal position".to_string(),
-                        None,
-                    )),
-                },
-                _ => {
-                    quasi_helper(engine, env.clone(), car, Some(cdr))?
-                }
+    match quote_form(engine, &list)? {
+        Some((QuoteForm::Unquote, x)) if depth == 0 => return engine.eval_inner(env, x),
+        Some((QuoteForm::UnquoteSplicing, _)) if depth == 0 => return Err(bad_splice(engine)),
+        Some((form, x)) => {
+            let depth = match form {
+                QuoteForm::Quasiquote => depth + 1,
+                _ => depth - 1,
+            };
+            let head = engine.split_cons(list)?.0;
+            let x = quasi_helper(engine, env, x, depth)?;
+            return Ok(Engine::list_to_sexp(&[head, x]));
+        }
+        None => {}
+    }
+
+    let mut items = Vec::new();
+    let mut rest = list;
+    let tail = loop {
+        let (car, cdr) = match engine.split_cons_verb(&rest)? {
+            Some(it) => it,
+            None => break rest,
+        };
+        match quote_form(engine, &car)? {
+            Some((QuoteForm::UnquoteSplicing, x)) if depth == 0 => {
+                let spliced = engine.eval_inner(env.clone(), x)?;
+                let list = engine
+                    .sexp_to_list(spliced.clone())?
+                    .ok_or_else(|| bad_arg_type(engine, spliced, 1, "list"))?;
+                items.extend(list);
             }
+            _ => items.push(quasi_helper(engine, env.clone(), car, depth)?),
+        }
+        // `(a . ,b)` is really `(a unquote b)`
+        if quote_form(engine, &cdr)?.is_some() {
+            break quasi_helper(engine, env.clone(), cdr, depth)?;
         }
-        _ => arg,
+        rest = cdr;
     };
+    Ok(Engine::list_to_improper_sexp(&items, tail))
+}
 
-    Ok(match pcdr.clone() {
-        Some(cdr) => Gc::new(Expr::Pair(arg, quasi_helper(engine, env.clone(), cdr, None)?)),
-        None => arg,
-    })
+/// Keys and values both get filled in. `,@` as a key splices in a map,
+/// or a list of `(key value)` lists; entries written out win over those.
+fn quasi_map(
+    engine: &mut Engine,
+    env: Gc<GcCell<Namespace>>,
+    map: &GcMap,
+    depth: usize,
+) -> EvalResult {
+    let mut out = GcMap::new();
+    let mut written = Vec::new();
+    for (k, v) in map.iter() {
+        match quote_form(engine, k)? {
+            Some((QuoteForm::UnquoteSplicing, x)) if depth == 0 => {
+                let spliced = engine.eval_inner(env.clone(), x)?;
+                splice_entries(engine, spliced, &mut out)?;
+            }
+            _ => written.push((k.to_owned(), v.to_owned())),
+        }
+    }
+    for (k, v) in written {
+        let k = quasi_helper(engine, env.clone(), k, depth)?;
+        let v = quasi_helper(engine, env.clone(), v, depth)?;
+        out.insert(k, v);
+    }
+    Ok(Gc::new(Expr::Map(out)))
+}
+
+fn splice_entries(
+    engine: &mut Engine,
+    spliced: Gc<Expr>,
+    out: &mut GcMap,
+) -> Result<(), Exception> {
+    if let Expr::Map(map) = &*spliced {
+        for (k, v) in map.iter() {
+            out.insert(k.to_owned(), v.to_owned());
+        }
+        return Ok(());
+    }
+    let bad = |engine: &mut Engine| {
+        bad_arg_type(
+            engine,
+            spliced.clone(),
+            1,
+            "map or list of (key value) lists",
+        )
+    };
+    let entries = engine
+        .sexp_to_list(spliced.clone())?
+        .ok_or_else(|| bad(engine))?;
+    for entry in entries {
+        match engine.sexp_to_list(entry)?.as_deref() {
+            Some([k, v]) => {
+                out.insert(k.to_owned(), v.to_owned());
+            }
+            _ => return Err(bad(engine)),
+        }
+    }
+    Ok(())
 }
 
 pub fn unquote(
@@ -0,0 +1,207 @@
+//! Random numbers.
+//!
+//! Every function takes an optional generator as its last argument, made with `random/new`.
+//! It's a transient holding the generator's state; without one we use the engine's.
+
+use std::convert::TryFrom;
+
+use crate::random::Rng;
+
+use super::*;
+
+/// A generator passed in by the user, and which argument it was.
+type RngArg = Option<(Gc<Expr>, usize)>;
+
+/// Split a generator off the end of the args, if there is one.
+fn split_rng(args: &[Gc<Expr>]) -> (&[Gc<Expr>], RngArg) {
+    match args.split_last() {
+        Some((last, rest)) if matches!(&**last, Expr::Transient(_)) => {
+            (rest, Some((last.to_owned(), rest.len())))
+        }
+        _ => (args, None),
+    }
+}
+
+/// Run something with the given generator, or the engine's if there isn't one.
+fn with_rng<T>(
+    engine: &mut Engine,
+    rng: RngArg,
+    f: impl FnOnce(&mut Rng) -> T,
+) -> Result<T, Exception> {
+    let (expr, idx) = match rng {
+        Some(it) => it,
+        None => return Ok(f(&mut engine.rng)),
+    };
+    let trans = match &*expr {
+        Expr::Transient(t) => t,
+        _ => unreachable!(),
+    };
+
+    let state = {
+        let lock = borrow_transient(engine, trans)?;
+        Gc::new((**lock.as_ref().unwrap()).clone())
+    };
+    let state = engine.sexp_to_list(state)?.and_then(|list| {
+        let ints = list
+            .iter()
+            .map(|x| match &**x {
+                Expr::Integer(int) => Some(*int as u64),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let state = <[u64; 4]>::try_from(ints).ok()?;
+        Rng::from_state(state)
+    });
+    let mut state = match state {
+        Some(it) => it,
+        None => return Err(bad_arg_type(engine, expr, idx, "random state")),
+    };
+
+    let out = f(&mut state);
+
+    let state = state
+        .state()
+        .iter()
+        .map(|x| Gc::new(Expr::Integer(*x as i64)))
+        .collect::<Vec<_>>();
+    trans
+        .borrow_mut()
+        .replace(Box::new((*Engine::list_to_sexp(&state)).clone()));
+    Ok(out)
+}
+
+fn list_arg(
+    engine: &mut Engine,
+    args: &[Gc<Expr>],
+    idx: usize,
+) -> Result<Vec<Gc<Expr>>, Exception> {
+    match engine.sexp_to_list(args[idx].to_owned())? {
+        Some(list) => Ok(list),
+        None => Err(bad_arg_type(engine, args[idx].to_owned(), idx, "list")),
+    }
+}
+
+/// `(random/new [seed])`. Without a seed, the new generator is seeded from the engine's.
+pub fn new(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 0, 1)?;
+    let seed = match args.first() {
+        Some(seed) => match &**seed {
+            Expr::Integer(int) => *int as u64,
+            _ => return Err(bad_arg_type(engine, seed.to_owned(), 0, "integer")),
+        },
+        None => engine.rng.next_u64(),
+    };
+
+    let state = Rng::from_seed(seed)
+        .state()
+        .iter()
+        .map(|x| Gc::new(Expr::Integer(*x as i64)))
+        .collect::<Vec<_>>();
+    Ok(Gc::new(Expr::Transient(GcCell::new(Some(Box::new(
+        (*Engine::list_to_sexp(&state)).clone(),
+    ))))))
+}
+
+/// `(random/seed! seed)` reseeds the engine's generator.
+pub fn seed(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+    match &*args[0] {
+        Expr::Integer(int) => engine.seed_rng(*int as u64),
+        _ => return Err(bad_arg_type(engine, args[0].to_owned(), 0, "integer")),
+    }
+    Ok(Gc::new(Expr::Nil))
+}
+
+/// `(random/int [lo] hi [rng])` picks an integer in `[lo, hi)`; `lo` defaults to 0.
+pub fn int(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    let (args, rng) = split_rng(args);
+    check_argc(engine, args, 1, 2)?;
+
+    let (lo, hi) = if args.len() == 1 {
+        (0, int_arg(engine, args, 0)?)
+    } else {
+        (int_arg(engine, args, 0)?, int_arg(engine, args, 1)?)
+    };
+    if lo >= hi {
+        return Err(engine.make_err(
+            "random/empty-range",
+            format!("cannot pick an integer from {} up to {}", lo, hi),
+            Some(Engine::list_to_sexp(&[
+                Gc::new(Expr::Integer(lo)),
+                Gc::new(Expr::Integer(hi)),
+            ])),
+        ));
+    }
+
+    let span = (hi as i128 - lo as i128) as u64;
+    let offset = with_rng(engine, rng, |rng| rng.below(span))?;
+    Ok(Gc::new(Expr::Integer((lo as i128 + offset as i128) as i64)))
+}
+
+/// `(random/float [lo hi] [rng])` picks a float in `[lo, hi)`, or `[0, 1)`.
+pub fn float(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    let (args, rng) = split_rng(args);
+    if args.len() == 1 {
+        // there's no sensible way to have just one bound
+        check_argc(engine, args, 2, 2)?;
+    }
+    check_argc(engine, args, 0, 2)?;
+
+    let (lo, hi) = if args.is_empty() {
+        (0.0, 1.0)
+    } else {
+        (
+            Num::from_expr(engine, args[0].to_owned(), 0)?.as_float(),
+            Num::from_expr(engine, args[1].to_owned(), 1)?.as_float(),
+        )
+    };
+    let unit = with_rng(engine, rng, |rng| rng.next_f64())?;
+    Ok(Expr::float(lo + (hi - lo) * unit))
+}
+
+/// `(random/choice list [rng])` picks one element.
+pub fn choice(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    let (args, rng) = split_rng(args);
+    check_argc(engine, args, 1, 1)?;
+
+    let list = list_arg(engine, args, 0)?;
+    if list.is_empty() {
+        return Err(engine.make_err("random/empty", "cannot choose from an empty list", None));
+    }
+    let idx = with_rng(engine, rng, |rng| rng.below(list.len() as u64))?;
+    Ok(list[idx as usize].to_owned())
+}
+
+/// `(random/shuffle list [rng])` returns the list in a random order.
+pub fn shuffle(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    let (args, rng) = split_rng(args);
+    check_argc(engine, args, 1, 1)?;
+
+    let mut list = list_arg(engine, args, 0)?;
+    let len = list.len();
+    with_rng(engine, rng, |rng| rng.partial_shuffle(&mut list, len))?;
+    Ok(Engine::list_to_sexp(&list))
+}
+
+/// `(random/sample list k [rng])` picks `k` elements without replacement, in a random order.
+pub fn sample(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    let (args, rng) = split_rng(args);
+    check_argc(engine, args, 2, 2)?;
+
+    let mut list = list_arg(engine, args, 0)?;
+    let count = int_arg(engine, args, 1)?;
+    if count < 0 || count as usize > list.len() {
+        return Err(engine.make_err(
+            "random/sample/too-many",
+            format!(
+                "cannot sample {} elements from a list of {}",
+                count,
+                list.len()
+            ),
+            Some(Gc::new(Expr::Integer(count))),
+        ));
+    }
+    let count = count as usize;
+    with_rng(engine, rng, |rng| rng.partial_shuffle(&mut list, count))?;
+    Ok(Engine::list_to_sexp(&list[..count]))
+}
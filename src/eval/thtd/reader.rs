@@ -0,0 +1,92 @@
+//! Reader macros, so scripts can add their own syntax.
+
+use super::*;
+
+fn proc_arg(engine: &mut Engine, args: &[Gc<Expr>], idx: usize) -> Result<Gc<Expr>, Exception> {
+    if args[idx].is_callable() {
+        Ok(args[idx].to_owned())
+    } else {
+        Err(bad_arg_type(engine, args[idx].to_owned(), idx, "procedure"))
+    }
+}
+
+/// `(reader/set-prefix! char proc)` makes `char datum` read as whatever
+/// `(proc 'datum)` returns.
+///
+/// `char` can be a char or a string with one char in it.
+pub fn set_prefix(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 2, 2)?;
+    let c = match &*args[0] {
+        &Expr::Char(c) => Some(c),
+        Expr::String(s) => std::str::from_utf8(s)
+            .ok()
+            .filter(|s| s.chars().count() == 1)
+            .and_then(|s| s.chars().next()),
+        _ => None,
+    };
+    let c = match c {
+        Some(it) => it,
+        None => {
+            return Err(bad_arg_type(
+                engine,
+                args[0].to_owned(),
+                0,
+                "char or string of one char",
+            ))
+        }
+    };
+    let proc = proc_arg(engine, args, 1)?;
+
+    // These would make the reader unable to read normal code
+    if c.is_whitespace() || c.is_ascii_digit() || "()[]{}\";'`,#".contains(c) {
+        return Err(engine.make_err(
+            "reader/bad-prefix",
+            format!("{:?} can't be a reader macro", c),
+            Some(args[0].to_owned()),
+        ));
+    }
+    engine.reader_macros.set_prefix(c, proc);
+    Ok(Expr::nil())
+}
+
+/// `(reader/set-dispatch! name proc)` makes `#name datum` read as whatever
+/// `(proc 'datum)` returns, and `#name"text"` read as `(proc "text")`
+/// with the text exactly as it was written.
+///
+/// `name` can be a symbol or a string.
+pub fn set_dispatch(
+    engine: &mut Engine,
+    _: Gc<GcCell<Namespace>>,
+    args: &[Gc<Expr>],
+) -> EvalResult {
+    check_argc(engine, args, 2, 2)?;
+    let name = match &*args[0] {
+        &Expr::Symbol(sym) => engine.get_symbol_str(sym).unwrap_or_default().to_vec(),
+        Expr::String(s) => s.to_vec(),
+        _ => {
+            return Err(bad_arg_type(
+                engine,
+                args[0].to_owned(),
+                0,
+                "symbol or string",
+            ))
+        }
+    };
+    let proc = proc_arg(engine, args, 1)?;
+
+    let bad = |b: &u8| b.is_ascii_whitespace() || b"()[]{}\"".contains(b);
+    // `#;` and `#\` already mean something
+    let taken = name.starts_with(b";") || name.starts_with(b"\\");
+    if name.is_empty() || name.iter().any(bad) || taken {
+        return Err(engine.make_err(
+            "reader/bad-dispatch",
+            format!(
+                "#{} can't be a reader macro",
+                String::from_utf8_lossy(&name)
+            ),
+            Some(args[0].to_owned()),
+        ));
+    }
+    engine.reader_macros.set_dispatch(name, proc);
+    Ok(Expr::nil())
+}
@@ -0,0 +1,188 @@
+//! Records, and the natives `define-record` expands to.
+
+use std::collections::HashSet;
+
+use crate::{RecordType, Symbol};
+
+use super::*;
+
+fn type_arg(
+    engine: &mut Engine,
+    args: &[Gc<Expr>],
+    idx: usize,
+) -> Result<Gc<RecordType>, Exception> {
+    match &*args[idx] {
+        Expr::RecordType(ty) => Ok(ty.to_owned()),
+        _ => Err(bad_arg_type(
+            engine,
+            args[idx].to_owned(),
+            idx,
+            "record type",
+        )),
+    }
+}
+
+fn symbol_arg(engine: &mut Engine, args: &[Gc<Expr>], idx: usize) -> Result<Symbol, Exception> {
+    match &*args[idx] {
+        Expr::Symbol(sym) => Ok(*sym),
+        _ => Err(bad_arg_type(engine, args[idx].to_owned(), idx, "symbol")),
+    }
+}
+
+/// Get the fields of a record at `args[rec_idx]`, making sure it's of the given type.
+fn fields_of<'a>(
+    engine: &mut Engine,
+    ty: &Gc<RecordType>,
+    args: &'a [Gc<Expr>],
+    rec_idx: usize,
+) -> Result<&'a [Gc<Expr>], Exception> {
+    match &*args[rec_idx] {
+        Expr::Record(rec_ty, fields) if Gc::ptr_eq(rec_ty, ty) => Ok(fields),
+        _ => {
+            let want = engine
+                .get_symbol_str(ty.name)
+                .map(|name| format!("record of type {}", String::from_utf8_lossy(name)))
+                .unwrap_or_else(|| "record".to_owned());
+            Err(bad_arg_type(
+                engine,
+                args[rec_idx].to_owned(),
+                rec_idx,
+                &want,
+            ))
+        }
+    }
+}
+
+fn field_idx(engine: &mut Engine, ty: &RecordType, field: Symbol) -> Result<usize, Exception> {
+    ty.field_idx(field).ok_or_else(|| {
+        let name = engine.get_symbol_str(ty.name).unwrap_or_default();
+        let field_name = engine.get_symbol_str(field).unwrap_or_default();
+        let msg = format!(
+            "record type {} has no field {}",
+            String::from_utf8_lossy(name),
+            String::from_utf8_lossy(field_name)
+        );
+        engine.make_err("record/no-field", msg, Some(Expr::symbol(field)))
+    })
+}
+
+/// `(record/type name (fields ...))`
+pub fn make_type(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 2, 2)?;
+
+    let name = symbol_arg(engine, args, 0)?;
+    let field_exprs = match engine.sexp_to_list(args[1].to_owned())? {
+        Some(it) => it,
+        None => {
+            return Err(bad_arg_type(
+                engine,
+                args[1].to_owned(),
+                1,
+                "list of symbols",
+            ))
+        }
+    };
+    let mut fields = Vec::with_capacity(field_exprs.len());
+    let mut seen = HashSet::new();
+    for field in field_exprs {
+        let sym = match &*field {
+            Expr::Symbol(sym) => *sym,
+            _ => {
+                return Err(bad_arg_type(
+                    engine,
+                    args[1].to_owned(),
+                    1,
+                    "list of symbols",
+                ))
+            }
+        };
+        if !seen.insert(sym) {
+            return Err(engine.make_err(
+                "record/duplicate-field",
+                "a record can't have the same field twice",
+                Some(field),
+            ));
+        }
+        fields.push(sym);
+    }
+
+    Ok(Gc::new(Expr::RecordType(Gc::new(RecordType {
+        name,
+        fields,
+    }))))
+}
+
+/// `(record/new type vals ...)`, with the values in field order.
+pub fn new(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_min_argc(engine, args, 1)?;
+    let ty = type_arg(engine, args, 0)?;
+    let argc = ty.fields.len() + 1;
+    check_argc(engine, args, argc, argc)?;
+
+    Ok(Gc::new(Expr::Record(ty, args[1..].to_vec())))
+}
+
+/// `(record/is? type x)`
+pub fn is_a(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 2, 2)?;
+    let ty = type_arg(engine, args, 0)?;
+
+    Ok(Expr::bool(
+        matches!(&*args[1], Expr::Record(rec_ty, _) if Gc::ptr_eq(rec_ty, &ty)),
+    ))
+}
+
+/// `(record/get type record field)`
+pub fn get(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 3, 3)?;
+    let ty = type_arg(engine, args, 0)?;
+    let fields = fields_of(engine, &ty, args, 1)?;
+    let field = symbol_arg(engine, args, 2)?;
+    let idx = field_idx(engine, &ty, field)?;
+
+    Ok(fields[idx].to_owned())
+}
+
+/// `(record/with type record field val)` makes a copy with that field changed.
+pub fn with(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 4, 4)?;
+    let ty = type_arg(engine, args, 0)?;
+    let fields = fields_of(engine, &ty, args, 1)?;
+    let field = symbol_arg(engine, args, 2)?;
+    let idx = field_idx(engine, &ty, field)?;
+
+    let mut fields = fields.to_vec();
+    fields[idx] = args[3].to_owned();
+    Ok(Gc::new(Expr::Record(ty, fields)))
+}
+
+/// `(record/type-of record)`
+pub fn type_of(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+    match &*args[0] {
+        Expr::Record(ty, _) => Ok(Gc::new(Expr::RecordType(ty.to_owned()))),
+        _ => Err(bad_arg_type(engine, args[0].to_owned(), 0, "record")),
+    }
+}
+
+/// `(record/fields record-or-type)` lists the field names.
+pub fn fields(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+    let ty = match &*args[0] {
+        Expr::Record(ty, _) | Expr::RecordType(ty) => ty,
+        _ => {
+            return Err(bad_arg_type(
+                engine,
+                args[0].to_owned(),
+                0,
+                "record or record type",
+            ))
+        }
+    };
+    let fields = ty
+        .fields
+        .iter()
+        .map(|f| Expr::symbol(*f))
+        .collect::<Vec<_>>();
+    Ok(Engine::list_to_sexp(&fields))
+}
@@ -5,11 +5,25 @@ use itertools::Itertools;
 use super::*;
 
 pub fn to_string(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
-    let mut out = String::new();
+    let mut out = Vec::new();
     for expr in args {
-        out.push_str(&engine.print_expr(expr.to_owned())?);
+        push_printed(engine, &mut out, expr.to_owned())?;
+    }
+    Ok(Expr::string(out))
+}
+
+/// Append the expr like `string` would.
+pub fn push_printed(
+    engine: &mut Engine,
+    out: &mut Vec<u8>,
+    expr: Gc<Expr>,
+) -> Result<(), Exception> {
+    match &*expr {
+        // Copy these verbatim so bytes that aren't UTF-8 survive
+        Expr::String(s) => out.extend_from_slice(s),
+        _ => out.extend_from_slice(engine.print_expr(expr)?.as_bytes()),
     }
-    Ok(Gc::new(Expr::String(out.into_bytes())))
+    Ok(())
 }
 
 pub fn string_len(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
@@ -99,23 +113,7 @@ pub fn string_slice(
     }
 
     // finally
-    let slice = string.get(start..end);
-    if let Some(slice) = slice {
-        Ok(Gc::new(Expr::String(slice.to_owned())))
-    } else {
-        Err(engine.make_err(
-            "string/slice-unknown",
-            format!(
-                "some other error happened when slicing {:?} at {}..{}",
-                &string, start, end
-            ),
-            Some(Engine::list_to_sexp(&[
-                args[0].to_owned(),
-                Gc::new(Expr::Integer(start as _)),
-                Gc::new(Expr::Integer(end as _)),
-            ])),
-        ))
-    }
+    Ok(Gc::new(Expr::String(string.slice(start..end))))
 }
 
 pub fn string_find(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
@@ -132,7 +130,7 @@ pub fn string_find(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Exp
 
     let found = haystack
         .windows(needle.len())
-        .position(|slice| slice == needle);
+        .position(|slice| slice == &needle[..]);
     Ok(match found {
         Some(it) => Gc::new(Expr::Integer(it as _)),
         None => engine.make_bool(false),
@@ -155,7 +153,7 @@ pub fn string_replace(
         _ => return Err(bad_arg_type(engine, args[1].to_owned(), 1, "string")),
     };
     let mut src = match &*args[2] {
-        Expr::String(s) => s.to_owned(),
+        Expr::String(s) => s.to_vec(),
         _ => return Err(bad_arg_type(engine, args[2].to_owned(), 2, "string")),
     };
     let maxcount = match args.get(3) {
@@ -176,7 +174,7 @@ pub fn string_replace(
     let mut positions = src
         .windows(from.len())
         .enumerate()
-        .filter_map(|(idx, slice)| (slice == from).then(|| idx))
+        .filter_map(|(idx, slice)| (slice == &from[..]).then(|| idx))
         .collect_vec();
     if let Some(max) = maxcount {
         positions.truncate(max);
@@ -184,7 +182,7 @@ pub fn string_replace(
     for pos in positions.into_iter().rev() {
         src.splice(pos..pos + from.len(), to.iter().copied());
     }
-    Ok(Gc::new(Expr::String(src)))
+    Ok(Expr::string(src))
 }
 
 pub fn string_lines(
@@ -198,18 +196,18 @@ pub fn string_lines(
         Expr::String(s) => s,
         _ => return Err(bad_arg_type(engine, args[0].to_owned(), 0, "string")),
     };
-    let mut lines = s.split(|byte| *byte == b'\n').collect_vec();
-    // ah yes, ergonomic rust syntax
-    if lines.last() == Some(&&[][..]) {
-        lines.pop();
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for (idx, _) in s.iter().enumerate().filter(|(_, byte)| **byte == b'\n') {
+        lines.push(Gc::new(Expr::String(s.slice(start..idx))));
+        start = idx + 1;
+    }
+    // No empty line after a trailing newline
+    if start < s.len() {
+        lines.push(Gc::new(Expr::String(s.slice(start..))));
     }
 
-    Ok(Engine::list_to_sexp(
-        &lines
-            .into_iter()
-            .map(|line| Gc::new(Expr::String(line.to_owned())))
-            .collect_vec(),
-    ))
+    Ok(Engine::list_to_sexp(&lines))
 }
 
 pub fn string_split(
@@ -231,19 +229,19 @@ pub fn string_split(
     let split_poses = s
         .windows(divider.len())
         .enumerate()
-        .filter_map(|(idx, slice)| (slice == divider).then(|| idx))
+        .filter_map(|(idx, slice)| (slice == &divider[..]).then(|| idx))
         .collect_vec();
 
     let mut out = Vec::new();
     if !split_poses.is_empty() {
-        let span = &s[..split_poses[0]];
-        out.push(Gc::new(Expr::String(span.to_owned())));
+        let span = s.slice(..split_poses[0]);
+        out.push(Gc::new(Expr::String(span)));
         for poses in split_poses.windows(2) {
-            let span = &s[poses[0] + divider.len()..poses[1]];
-            out.push(Gc::new(Expr::String(span.to_owned())));
+            let span = s.slice(poses[0] + divider.len()..poses[1]);
+            out.push(Gc::new(Expr::String(span)));
         }
-        let span = &s[split_poses.last().unwrap() + divider.len()..];
-        out.push(Gc::new(Expr::String(span.to_owned())));
+        let span = s.slice(split_poses.last().unwrap() + divider.len()..);
+        out.push(Gc::new(Expr::String(span)));
     }
     Ok(Engine::list_to_sexp(&out))
 }
@@ -259,9 +257,8 @@ pub fn string_chars(
         Expr::String(s) => s,
         _ => return Err(bad_arg_type(engine, args[0].to_owned(), 0, "string")),
     };
-    let chars = s
-        .iter()
-        .map(|s| Gc::new(Expr::String(vec![*s])))
+    let chars = (0..s.len())
+        .map(|idx| Gc::new(Expr::String(s.slice(idx..idx + 1))))
         .collect_vec();
     Ok(Engine::list_to_sexp(&chars))
 }
@@ -281,6 +278,34 @@ pub fn string_bytes(
     Ok(Engine::list_to_sexp(&bytes))
 }
 
+/// Copy a string out of the buffer it shares if it's keeping a much bigger one alive.
+pub fn string_compact(
+    engine: &mut Engine,
+    _: Gc<GcCell<Namespace>>,
+    args: &[Gc<Expr>],
+) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+
+    match &*args[0] {
+        Expr::String(_) => Ok(Expr::compacted(args[0].to_owned())),
+        _ => Err(bad_arg_type(engine, args[0].to_owned(), 0, "string")),
+    }
+}
+
+/// Is this string keeping a much bigger one alive, so `string/compact` would copy it?
+pub fn string_pinning(
+    engine: &mut Engine,
+    _: Gc<GcCell<Namespace>>,
+    args: &[Gc<Expr>],
+) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+
+    match &*args[0] {
+        Expr::String(s) => Ok(Expr::bool(s.is_pinning())),
+        _ => Err(bad_arg_type(engine, args[0].to_owned(), 0, "string")),
+    }
+}
+
 pub fn prn(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
     check_argc(engine, args, 1, 2)?;
 
@@ -15,7 +15,7 @@ pub fn symbol2string(
         return Err(bad_arg_type(engine, arg, 0, "symbol"));
     };
     let string = engine.get_symbol_str(sym).unwrap_or(b"<unknown>");
-    Ok(Gc::new(Expr::String(string.to_vec())))
+    Ok(Expr::string(string.to_vec()))
 }
 
 pub fn string2symbol(
@@ -49,7 +49,10 @@ fn map_insert_inner(
     // step 3: n e v e r stabilize anything using them
     // 2021 "array_chunks" incident
     for kv in args[1..].chunks_exact(2) {
-        let clob = map.insert(kv[0].to_owned(), kv[1].to_owned());
+        let clob = map.insert(
+            Expr::compacted(kv[0].to_owned()),
+            Expr::compacted(kv[1].to_owned()),
+        );
         if let Some(ref mut clobbered) = clobbered {
             clobbered.push(clob.unwrap_or_else(|| engine.make_bool(false)));
         }
@@ -1,6 +1,7 @@
 use super::*;
 
 pub mod map;
+pub mod string;
 
 pub fn new(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
     check_argc(engine, args, 1, 1)?;
@@ -13,7 +14,7 @@ pub fn new(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) ->
 pub fn persist(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
     check_argc(engine, args, 1, 1)?;
     match &*args[0] {
-        Expr::Transient(t) => take_transient(engine, t).map(Gc::new),
+        Expr::Transient(t) => take_transient(engine, t).map(|expr| Gc::new(persisted(expr))),
         _ => Err(bad_arg_type(engine, args[0].to_owned(), 0, "transient")),
     }
 }
@@ -75,8 +76,19 @@ pub fn clone(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -
         Expr::Transient(t) => borrow_transient(engine, t).map(|expr| {
             // as per contract this is safe to unwrap
             let expr = expr.as_ref().unwrap();
-            Gc::new((**expr).clone())
+            Gc::new(persisted((**expr).clone()))
         }),
         _ => Err(bad_arg_type(engine, args[0].to_owned(), 0, "transient")),
     }
 }
+
+/// Get a value ready to leave its transient.
+///
+/// String builders own their buffer so they can append to it, but once they're out
+/// they should share it so slicing them is cheap.
+fn persisted(mut expr: Expr) -> Expr {
+    if let Expr::String(s) = &mut expr {
+        *s = std::mem::take(s).share();
+    }
+    expr
+}
@@ -0,0 +1,139 @@
+//! String builders: a transient string that gets appended to in place.
+//!
+//! Make one with `(transient/new "")` and get the string out with `transient/persist!`.
+
+use crate::ByteStr;
+
+use super::super::*;
+
+/// Take the bytes out of a transient string builder to work on.
+fn take_builder(engine: &mut Engine, args: &[Value]) -> Result<Vec<u8>, Exception> {
+    let trans = match &*args[0] {
+        Expr::Transient(t) => t,
+        _ => {
+            return Err(bad_arg_type(
+                engine,
+                args[0].to_owned(),
+                0,
+                "transient string",
+            ))
+        }
+    };
+    match &mut take_transient(engine, trans)? {
+        Expr::String(s) => Ok(std::mem::take(s).into_vec()),
+        ono => Err(bad_arg_type(
+            engine,
+            Gc::new(ono.clone()),
+            0,
+            "transient string",
+        )),
+    }
+}
+
+/// Wrap the bytes back up, still unshared so they can keep getting appended to.
+fn wrap_builder(buf: Vec<u8>) -> EvalResult {
+    Ok(Expr::transient(Expr::String(ByteStr::unique(buf))))
+}
+
+/// `(sb/push! sb . strings-or-chars)`
+pub fn push(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Value]) -> EvalResult {
+    check_min_argc(engine, args, 1)?;
+
+    // Check everything first so we don't leave the builder taken
+    for (idx, arg) in args.iter().enumerate().skip(1) {
+        if !matches!(&**arg, Expr::String(_) | Expr::Char(_)) {
+            return Err(bad_arg_type(engine, arg.to_owned(), idx, "string or char"));
+        }
+    }
+
+    let mut buf = take_builder(engine, args)?;
+    for arg in &args[1..] {
+        match &**arg {
+            Expr::String(s) => buf.extend_from_slice(s),
+            Expr::Char(c) => buf.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            _ => unreachable!(),
+        }
+    }
+    wrap_builder(buf)
+}
+
+/// `(sb/push-bytes! sb . bytes)`
+pub fn push_bytes(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Value]) -> EvalResult {
+    check_min_argc(engine, args, 1)?;
+
+    let mut bytes = Vec::with_capacity(args.len() - 1);
+    for (idx, arg) in args.iter().enumerate().skip(1) {
+        match &**arg {
+            Expr::Integer(i @ 0..=255) => bytes.push(*i as u8),
+            _ => {
+                return Err(bad_arg_type(
+                    engine,
+                    arg.to_owned(),
+                    idx,
+                    "integer from 0 to 255",
+                ))
+            }
+        }
+    }
+
+    let mut buf = take_builder(engine, args)?;
+    buf.extend_from_slice(&bytes);
+    wrap_builder(buf)
+}
+
+/// `(sb/push-expr! sb . exprs)` appends each expr like `string` would.
+pub fn push_expr(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Value]) -> EvalResult {
+    check_min_argc(engine, args, 1)?;
+
+    let mut buf = take_builder(engine, args)?;
+    for arg in &args[1..] {
+        if let Err(ono) = push_printed(engine, &mut buf, arg.to_owned()) {
+            // Don't leave it empty-handed
+            if let Expr::Transient(t) = &*args[0] {
+                t.borrow_mut()
+                    .replace(Box::new(Expr::String(ByteStr::unique(buf))));
+            }
+            return Err(ono);
+        }
+    }
+    wrap_builder(buf)
+}
+
+pub fn len(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Value]) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+
+    let trans = match &*args[0] {
+        Expr::Transient(t) => t,
+        _ => {
+            return Err(bad_arg_type(
+                engine,
+                args[0].to_owned(),
+                0,
+                "transient string",
+            ))
+        }
+    };
+    let lock = borrow_transient(engine, trans)?;
+    let len = match &**lock.as_ref().unwrap() {
+        Expr::String(s) => s.len(),
+        _ => {
+            drop(lock);
+            return Err(bad_arg_type(
+                engine,
+                args[0].to_owned(),
+                0,
+                "transient string",
+            ));
+        }
+    };
+    Ok(Expr::integer(len as _))
+}
+
+pub fn clear(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Value]) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+
+    let mut buf = take_builder(engine, args)?;
+    // Keep the capacity around for the next round
+    buf.clear();
+    wrap_builder(buf)
+}
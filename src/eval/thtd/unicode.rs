@@ -0,0 +1,223 @@
+//! Strings as Unicode text, rather than bags of bytes.
+//!
+//! These all want valid UTF-8 and complain about where it isn't.
+
+use std::convert::TryFrom;
+
+use itertools::Itertools;
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::*;
+
+/// Byte offsets of every invalid sequence in the string.
+fn utf8_errors(mut s: &[u8]) -> Vec<usize> {
+    let mut out = Vec::new();
+    let mut offset = 0;
+    while let Err(err) = std::str::from_utf8(s) {
+        let bad = err.valid_up_to();
+        out.push(offset + bad);
+        // `None` means it got cut off at the end
+        let skip = bad + err.error_len().unwrap_or(s.len() - bad);
+        s = &s[skip..];
+        offset += skip;
+    }
+    out
+}
+
+fn text_arg<'a>(
+    engine: &mut Engine,
+    args: &'a [Gc<Expr>],
+    idx: usize,
+) -> Result<&'a str, Exception> {
+    let bytes = match &*args[idx] {
+        Expr::String(s) => s,
+        _ => return Err(bad_arg_type(engine, args[idx].to_owned(), idx, "string")),
+    };
+    std::str::from_utf8(bytes).map_err(|_| {
+        let errors = utf8_errors(bytes);
+        let msg = format!("invalid UTF-8 at byte {}", errors[0]);
+        let data = errors
+            .into_iter()
+            .map(|pos| Expr::integer(pos as _))
+            .collect_vec();
+        engine.make_err(
+            "string/invalid-utf8",
+            msg,
+            Some(Engine::list_to_sexp(&data)),
+        )
+    })
+}
+
+fn string_expr(s: impl Into<String>) -> Gc<Expr> {
+    Expr::string(s.into())
+}
+
+/// Share part of the string at `args[idx]`, which must already be checked to be a string.
+fn share(args: &[Gc<Expr>], idx: usize, range: std::ops::Range<usize>) -> Gc<Expr> {
+    match &*args[idx] {
+        Expr::String(s) => Gc::new(Expr::String(s.slice(range))),
+        _ => unreachable!(),
+    }
+}
+
+pub fn codepoints(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+    let s = text_arg(engine, args, 0)?;
+    let chars = s.chars().map(|c| Gc::new(Expr::Char(c))).collect_vec();
+    Ok(Engine::list_to_sexp(&chars))
+}
+
+pub fn graphemes(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+    let s = text_arg(engine, args, 0)?;
+    let graphemes = s
+        .grapheme_indices(true)
+        .map(|(idx, g)| share(args, 0, idx..idx + g.len()))
+        .collect_vec();
+    Ok(Engine::list_to_sexp(&graphemes))
+}
+
+pub fn len_chars(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+    let s = text_arg(engine, args, 0)?;
+    Ok(Expr::integer(s.chars().count() as _))
+}
+
+/// Like `string/slice`, but counting in codepoints instead of bytes.
+pub fn slice_chars(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 2, 3)?;
+    let s = text_arg(engine, args, 0)?;
+
+    // Byte offset of the start of every char, and the end
+    let bounds = s
+        .char_indices()
+        .map(|(idx, _)| idx)
+        .chain(std::iter::once(s.len()))
+        .collect_vec();
+    let char_len = bounds.len() - 1;
+
+    let mut get_idx = |idx: usize, default: usize| match args.get(idx) {
+        None => Ok(default),
+        Some(expr) => match &**expr {
+            Expr::Integer(i) if *i >= 0 => Ok(*i as usize),
+            _ if !engine.is_truthy(expr.to_owned()) => Ok(default),
+            _ => Err(bad_arg_type(
+                engine,
+                expr.to_owned(),
+                idx,
+                "positive int or falsy",
+            )),
+        },
+    };
+    let start = get_idx(1, 0)?;
+    let end = get_idx(2, char_len)?;
+
+    if start > end {
+        return Err(engine.make_err(
+            "string/slice-out-of-order",
+            format!("the start {} was after the end {}", start, end),
+            Some(Engine::list_to_sexp(&[
+                Expr::integer(start as _),
+                Expr::integer(end as _),
+            ])),
+        ));
+    }
+    if end > char_len {
+        return Err(engine.make_err(
+            "string/slice-too-far",
+            format!("{} was out of bounds (string had {} chars)", end, char_len),
+            Some(Engine::list_to_sexp(&[
+                Expr::integer(end as _),
+                Expr::integer(char_len as _),
+            ])),
+        ));
+    }
+
+    Ok(share(args, 0, bounds[start]..bounds[end]))
+}
+
+macro_rules! text_fns {
+    ($($name:ident => |$s:ident| $body:expr;)*) => {
+        $(
+            pub fn $name(
+                engine: &mut Engine,
+                _: Gc<GcCell<Namespace>>,
+                args: &[Gc<Expr>],
+            ) -> EvalResult {
+                check_argc(engine, args, 1, 1)?;
+                let $s = text_arg(engine, args, 0)?;
+                Ok(string_expr($body))
+            }
+        )*
+    };
+}
+
+text_fns! {
+    upper => |s| s.to_uppercase();
+    lower => |s| s.to_lowercase();
+    nfc => |s| s.nfc().collect::<String>();
+    nfd => |s| s.nfd().collect::<String>();
+    nfkc => |s| s.nfkc().collect::<String>();
+    nfkd => |s| s.nfkd().collect::<String>();
+}
+
+pub fn is_utf8(engine: &mut Engine, _: Gc<GcCell<Namespace>>, args: &[Gc<Expr>]) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+    match &*args[0] {
+        Expr::String(s) => Ok(Expr::bool(std::str::from_utf8(s).is_ok())),
+        _ => Err(bad_arg_type(engine, args[0].to_owned(), 0, "string")),
+    }
+}
+
+/// List the byte offsets where each invalid UTF-8 sequence starts.
+pub fn utf8_error_positions(
+    engine: &mut Engine,
+    _: Gc<GcCell<Namespace>>,
+    args: &[Gc<Expr>],
+) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+    match &*args[0] {
+        Expr::String(s) => {
+            let errors = utf8_errors(s)
+                .into_iter()
+                .map(|pos| Expr::integer(pos as _))
+                .collect_vec();
+            Ok(Engine::list_to_sexp(&errors))
+        }
+        _ => Err(bad_arg_type(engine, args[0].to_owned(), 0, "string")),
+    }
+}
+
+pub fn char2integer(
+    engine: &mut Engine,
+    _: Gc<GcCell<Namespace>>,
+    args: &[Gc<Expr>],
+) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+    match &*args[0] {
+        Expr::Char(c) => Ok(Expr::integer(*c as _)),
+        _ => Err(bad_arg_type(engine, args[0].to_owned(), 0, "char")),
+    }
+}
+
+pub fn integer2char(
+    engine: &mut Engine,
+    _: Gc<GcCell<Namespace>>,
+    args: &[Gc<Expr>],
+) -> EvalResult {
+    check_argc(engine, args, 1, 1)?;
+    let c = match &*args[0] {
+        Expr::Integer(i) => u32::try_from(*i).ok().and_then(char::from_u32),
+        _ => None,
+    };
+    match c {
+        Some(c) => Ok(Gc::new(Expr::Char(c))),
+        None => Err(bad_arg_type(
+            engine,
+            args[0].to_owned(),
+            0,
+            "integer that is a Unicode scalar value",
+        )),
+    }
+}
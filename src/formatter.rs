@@ -0,0 +1,225 @@
+//! `please fmt`: put source code in the canonical style.
+//!
+//! Line breaks are left where they were written, except that closers always hug
+//! the last thing in their list. What changes is the indentation and the spacing:
+//! - lists starting with a symbol, like calls and `let`s, indent the rest 2 past their paren;
+//! - anything else, like binding lists and maps, lines up just inside its paren;
+//! - every top-level form starts on its own line;
+//! - there's one space between things on the same line, and at most one blank line in a row.
+
+use crate::{
+    parse::{
+        cst::{Cst, Node, NodeKind, Prefix, Trivia, TriviaKind},
+        ExprParseError,
+    },
+    Engine,
+};
+
+impl Engine {
+    /// Format the source in the canonical style.
+    ///
+    /// Fails if it's not valid syntax.
+    #[allow(clippy::result_large_err)]
+    pub fn format_source(
+        &mut self,
+        source: &str,
+        source_name: String,
+    ) -> Result<String, ExprParseError> {
+        let source = source.replace('\r', "");
+        let cst = self.read_cst(&source, source_name)?;
+
+        let mut formatter = Formatter {
+            cst: &cst,
+            out: Vec::new(),
+            need_newline: false,
+        };
+        formatter.top_level();
+        Ok(String::from_utf8_lossy(&formatter.out).into_owned())
+    }
+}
+
+/// How far a body is indented past its opening paren.
+const BODY_INDENT: usize = 2;
+
+struct Formatter<'a> {
+    cst: &'a Cst,
+    out: Vec<u8>,
+    /// Set after a line comment or herestring, when the next thing has to be on a new line.
+    need_newline: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn top_level(&mut self) {
+        for (idx, node) in self.cst.nodes.iter().enumerate() {
+            // Every top-level form gets its own line
+            self.need_newline |= idx != 0;
+            self.separate(&node.leading, 0);
+            self.node(node, None);
+        }
+        self.comments(&self.cst.trailing, 0);
+        if !self.out.is_empty() {
+            self.out.push(b'\n');
+        }
+    }
+
+    /// Width of the current line so far.
+    fn col(&self) -> usize {
+        let line_start = self
+            .out
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |nl| nl + 1);
+        String::from_utf8_lossy(&self.out[line_start..])
+            .chars()
+            .count()
+    }
+
+    fn newline(&mut self, indent: usize, blank: bool) {
+        // No trailing whitespace, but `#\ ` is a char
+        while self.out.last() == Some(&b' ') && !self.out.ends_with(b"#\\ ") {
+            self.out.pop();
+        }
+        if blank {
+            self.out.push(b'\n');
+        }
+        self.out.push(b'\n');
+        self.out.resize(self.out.len() + indent, b' ');
+        self.need_newline = false;
+    }
+
+    /// Write the comments in some trivia, each on the same line as they were
+    /// or on its own line.
+    ///
+    /// Returns how many newlines were after the last comment.
+    fn comments(&mut self, trivia: &[Trivia], indent: usize) -> usize {
+        let mut newlines = 0;
+        for trivia in trivia {
+            let text = self.cst.text(&trivia.span);
+            match trivia.kind {
+                TriviaKind::Whitespace => {
+                    newlines += text.iter().filter(|&&b| b == b'\n').count();
+                }
+                TriviaKind::LineComment | TriviaKind::BlockComment | TriviaKind::DatumComment => {
+                    if self.after_opener() {
+                        // Nothing to separate it from
+                    } else if self.need_newline || newlines > 0 {
+                        self.newline(indent, newlines > 1);
+                    } else {
+                        self.out.push(b' ');
+                    }
+                    self.out.extend_from_slice(text);
+                    newlines = 0;
+                    self.need_newline = trivia.kind == TriviaKind::LineComment;
+                }
+            }
+        }
+        newlines
+    }
+
+    /// Write the trivia before something, and whatever space it needs from the
+    /// thing before it. Returns whether it's on a new line.
+    fn separate(&mut self, trivia: &[Trivia], indent: usize) -> bool {
+        let newlines = self.comments(trivia, indent);
+        if self.out.is_empty() {
+            false
+        } else if self.need_newline || newlines > 0 {
+            // No blank lines right inside a paren
+            self.newline(indent, newlines > 1 && !self.after_opener());
+            true
+        } else if self.after_opener() {
+            false
+        } else {
+            self.out.push(b' ');
+            false
+        }
+    }
+
+    /// Write the trivia before something that hugs the thing before it,
+    /// unless there's a line comment in the way.
+    ///
+    /// Returns whether it's on a new line.
+    fn hug(&mut self, trivia: &[Trivia], indent: usize) -> bool {
+        let newlines = self.comments(trivia, indent);
+        if self.need_newline {
+            self.newline(indent, newlines > 1);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn after_opener(&self) -> bool {
+        match self.out.split_last() {
+            // `#\(` is a char, not an opener
+            Some((b'(' | b'[' | b'{', before)) => !before.ends_with(b"#\\"),
+            Some(_) => false,
+            None => true,
+        }
+    }
+
+    /// Write a node. `start` is where it really starts, if it's inside a prefix.
+    fn node(&mut self, node: &Node, start: Option<usize>) {
+        match &node.kind {
+            NodeKind::Herestring { .. } => {
+                self.out.extend_from_slice(self.cst.text(&node.span));
+                self.need_newline = true;
+            }
+            NodeKind::Interpolated(_) => {
+                let text = self.cst.text(&node.span);
+                self.out.extend_from_slice(text);
+                // `$<<EOF` ends like a herestring
+                self.need_newline = text.starts_with(b"$<<");
+            }
+            NodeKind::Prefixed {
+                prefix,
+                prefix_span,
+                inner,
+            } => {
+                // Code inside `'(...)` lines up with the quote, not the paren,
+                // but map keys still line up with each other
+                let start = start.unwrap_or_else(|| self.col());
+                self.out.extend_from_slice(self.cst.text(prefix_span));
+                let col = self.col();
+                let broke = if *prefix == Prefix::Dispatch {
+                    // `#name"..."` would mean something else
+                    self.separate(&inner.leading, col)
+                } else {
+                    self.hug(&inner.leading, col)
+                };
+                let start = if broke || matches!(prefix, Prefix::Map | Prefix::Dispatch) {
+                    None
+                } else {
+                    Some(start)
+                };
+                self.node(inner, start);
+            }
+            NodeKind::List {
+                open,
+                close,
+                children,
+                inner,
+            } => {
+                let open_col = start.unwrap_or_else(|| self.col());
+                self.out.push(*open);
+
+                let indent = match children.first() {
+                    Some(head) if self.is_call(*open, head) => open_col + BODY_INDENT,
+                    _ => open_col + 1,
+                };
+                for child in children {
+                    self.separate(&child.leading, indent);
+                    self.node(child, None);
+                }
+                self.hug(inner, indent);
+                self.out.push(*close);
+            }
+            _ => self.out.extend_from_slice(self.cst.text(&node.span)),
+        }
+        self.comments(&node.trailing, 0);
+    }
+
+    /// Does this list start with a symbol, so it's probably code?
+    fn is_call(&self, open: u8, head: &Node) -> bool {
+        open == b'(' && head.kind == NodeKind::Symbol
+    }
+}
@@ -0,0 +1,77 @@
+//! Generic functions, made with `defgeneric` and extended with `defmethod`.
+
+use std::collections::HashMap;
+
+use gc::Gc;
+
+use crate::{Engine, Expr};
+
+#[derive(Debug, Clone, Default)]
+pub struct Generic {
+    /// Called with the arguments to get the key to dispatch on.
+    /// If there isn't one, we dispatch on the type of the first argument.
+    pub dispatcher: Option<Gc<Expr>>,
+    /// Maps dispatch keys to methods.
+    methods: HashMap<Gc<Expr>, Gc<Expr>>,
+    /// Maps dispatch keys to every method that applies, most specific first.
+    cache: HashMap<Gc<Expr>, Vec<Gc<Expr>>>,
+}
+
+impl Generic {
+    pub fn add_method(&mut self, key: Gc<Expr>, method: Gc<Expr>) {
+        self.methods.insert(key, method);
+        self.cache.clear();
+    }
+
+    /// How many methods apply to this key without falling back to `default`.
+    pub fn specific_method_count(&mut self, engine: &mut Engine, key: Gc<Expr>) -> usize {
+        let default = Expr::symbol(engine.intern_symbol("default"));
+        if self.methods.len() <= usize::from(self.methods.contains_key(&default)) {
+            // Nothing but the default, so don't bother working out the key's methods
+            return 0;
+        }
+        let count = self.methods_for(engine, key).len();
+        count - usize::from(self.methods.contains_key(&default))
+    }
+
+    /// Get every method that applies to this key, most specific first.
+    pub fn methods_for(&mut self, engine: &mut Engine, key: Gc<Expr>) -> Vec<Gc<Expr>> {
+        if let Some(methods) = self.cache.get(&key) {
+            return methods.clone();
+        }
+
+        let methods = std::iter::once(key.to_owned())
+            .chain(supertypes(engine, &key))
+            .filter_map(|key| self.methods.get(&key).cloned())
+            .collect::<Vec<_>>();
+        self.cache.insert(key, methods.clone());
+        methods
+    }
+}
+
+/// The key to dispatch on by default: the record type for records, and `typeof` for everything else.
+pub fn type_key(engine: &mut Engine, expr: &Expr) -> Gc<Expr> {
+    match expr {
+        Expr::Record(ty, _) => Gc::new(Expr::RecordType(ty.to_owned())),
+        it => Expr::symbol(engine.intern_symbol(it.type_name())),
+    }
+}
+
+/// Broader keys to fall back on if there's no method for this key, ending with `default`.
+fn supertypes(engine: &mut Engine, key: &Expr) -> Vec<Gc<Expr>> {
+    let parent = match key {
+        Expr::RecordType(_) => Some("record"),
+        Expr::Symbol(sym) => match engine.get_symbol_str(*sym) {
+            Some(b"integer" | b"rational" | b"float") => Some("number"),
+            Some(b"pair" | b"lazy-pair" | b"nil") => Some("list"),
+            Some(b"procedure" | b"native-procedure" | b"special-form") => Some("callable"),
+            _ => None,
+        },
+        _ => None,
+    };
+    parent
+        .into_iter()
+        .chain(std::iter::once("default"))
+        .map(|name| Expr::symbol(engine.intern_symbol(name)))
+        .collect()
+}
@@ -1,21 +1,31 @@
+mod bytestr;
 mod display;
 mod eval;
+mod formatter;
+mod generic;
 mod hash;
 mod lazy;
 mod parse;
+mod random;
+mod record;
 mod repl;
 mod type_predicates;
 
+pub use bytestr::ByteStr;
+pub use display::PrintLimits;
 use hash::GcMap;
-pub use parse::{ExprParseError, ExprParseErrorInfo};
+pub use parse::{cst, ExprParseError, ExprParseErrorInfo};
+pub use record::RecordType;
 
 use eval::TailRec;
+use generic::Generic;
 use itertools::Itertools;
+use random::Rng;
 
 use std::{
     borrow::Borrow,
     cmp::{Eq, PartialEq},
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     fmt::{self, Write},
     hash::Hash,
 };
@@ -33,7 +43,15 @@ use gc::{Finalize, Gc, GcCell, Trace};
 pub enum Expr {
     Integer(i64),
     Float(f64),
-    String(Vec<u8>),
+    /// Exact ratio of two integers, as `(numerator, denominator)`.
+    ///
+    /// This is always in lowest terms with a denominator greater than 1;
+    /// anything that would be a whole number is an `Integer` instead.
+    Rational(i64, i64),
+    /// Bag of bytes. Slices of it share the same buffer.
+    String(ByteStr),
+    /// A single Unicode codepoint.
+    Char(char),
     Bool(bool),
     /// Interned string.
     ///
@@ -71,6 +89,9 @@ pub enum Expr {
     },
 
     Map(GcMap),
+    /// Instance of a record type, with its fields in the order the type lists them.
+    Record(Gc<RecordType>, Vec<Gc<Expr>>),
+    RecordType(Gc<RecordType>),
 
     Transient(GcCell<Option<Box<Expr>>>),
 }
@@ -99,10 +120,18 @@ impl Expr {
         Gc::new(Self::Float(f))
     }
 
-    pub fn string<S: Into<Vec<u8>>>(s: S) -> Gc<Self> {
+    pub fn string<S: Into<ByteStr>>(s: S) -> Gc<Self> {
         Gc::new(Self::String(s.into()))
     }
 
+    /// If this is a string keeping a much bigger buffer alive, copy it out so the buffer can be freed.
+    pub fn compacted(expr: Gc<Self>) -> Gc<Self> {
+        match &*expr {
+            Self::String(s) if s.is_pinning() => Gc::new(Self::String(s.compact())),
+            _ => expr,
+        }
+    }
+
     pub fn bool(b: bool) -> Gc<Self> {
         Gc::new(Self::Bool(b))
     }
@@ -135,6 +164,7 @@ impl PartialEq for Expr {
         use Expr::*;
         match (self, other) {
             (Integer(a), Integer(b)) => a == b,
+            (Rational(an, ad), Rational(bn, bd)) => an == bn && ad == bd,
             (Float(a), Float(b)) => {
                 if a.is_nan() && b.is_nan() {
                     true
@@ -144,6 +174,7 @@ impl PartialEq for Expr {
             }
             (Bool(a), Bool(b)) => a == b,
             (String(a), String(b)) => a == b,
+            (Char(a), Char(b)) => a == b,
             (Symbol(a), Symbol(b)) => a == b,
             (Nil, Nil) => true,
             (Pair(ahead, atail), Pair(bhead, btail)) => ahead == bhead && atail == btail,
@@ -166,6 +197,8 @@ impl PartialEq for Expr {
                 },
             ) => a_args == b_args && a_body == b_body && a_env.is_some() == b_env.is_some(),
             (Map(a), Map(b)) => a == b,
+            (Record(a_ty, a), Record(b_ty, b)) => Gc::ptr_eq(a_ty, b_ty) && a == b,
+            (RecordType(a), RecordType(b)) => Gc::ptr_eq(a, b),
 
             (LazyPair(..), LazyPair(..)) => std::ptr::eq(self, other),
             (Transient(..), Transient(..)) => std::ptr::eq(self, other),
@@ -183,6 +216,10 @@ impl Hash for Expr {
 
         match self {
             Integer(x) => state.write_i64(*x),
+            Rational(n, d) => {
+                state.write_i64(*n);
+                state.write_i64(*d);
+            }
             Float(x) => state.write_u64(if x.is_nan() {
                 // nansbad!
                 0x6e616e7362616421
@@ -190,6 +227,7 @@ impl Hash for Expr {
                 x.to_bits()
             }),
             String(x) => x.hash(state),
+            Char(c) => c.hash(state),
             Symbol(x) => state.write_u64(*x),
             Bool(b) => b.hash(state),
             Nil => {}
@@ -211,6 +249,11 @@ impl Hash for Expr {
                 env.is_some().hash(state);
             }
             Map(map) => map.hash(state),
+            Record(ty, fields) => {
+                std::ptr::hash(&**ty, state);
+                fields.hash(state);
+            }
+            RecordType(ty) => std::ptr::hash(&**ty, state),
             Transient(..) => std::ptr::hash(self, state),
         }
     }
@@ -230,6 +273,21 @@ pub struct Engine {
     /// If this is Some, we're recording profiling information.
     /// Maps symbols to how many times we've evaled them and the total number of seconds we've been executing it form
     profiler: Option<HashMap<u64, (u64, f64)>>,
+
+    /// Default random number generator, for when the script doesn't bring its own.
+    rng: Rng,
+
+    /// Generic functions by name.
+    generics: HashMap<Symbol, Generic>,
+
+    /// How much of lazy lists to print.
+    print_limits: PrintLimits,
+
+    /// Procedures that read syntax scripts have added.
+    reader_macros: cst::ReaderMacros,
+
+    /// The last few PEG values whose bytecode has been checked, most recent last.
+    verified_pegs: VecDeque<Gc<Expr>>,
 }
 
 impl Default for Engine {
@@ -248,6 +306,11 @@ impl Engine {
                 parent: None,
             })),
             profiler: None,
+            rng: Rng::from_env(),
+            generics: HashMap::new(),
+            print_limits: PrintLimits::default(),
+            reader_macros: cst::ReaderMacros::default(),
+            verified_pegs: VecDeque::new(),
         };
         eval::add_thtandard_library(&mut out);
         out
@@ -271,19 +334,50 @@ impl Engine {
         parse::read_many(s.as_ref(), source_name, self)
     }
 
+    /// Reads the source losslessly, keeping comments, whitespace,
+    /// and how everything was spelled.
+    ///
+    /// Use [`cst::Cst::to_exprs`] to get what [`Engine::read_many`] would.
+    #[allow(clippy::result_large_err)]
+    pub fn read_cst<B: AsRef<[u8]>>(
+        &self,
+        s: B,
+        source_name: String,
+    ) -> Result<cst::Cst, ExprParseError> {
+        parse::read_cst(s.as_ref(), source_name, &self.reader_macros)
+    }
+
+    /// Reads the source losslessly, like [`Engine::read_cst`], but keeps going
+    /// after syntax errors.
+    ///
+    /// Top-level forms that can't be read become [`cst::NodeKind::Error`] nodes,
+    /// and all the errors go in one report.
+    pub fn read_cst_recovering<B: AsRef<[u8]>>(
+        &self,
+        s: B,
+        source_name: String,
+    ) -> (cst::Cst, Option<ExprParseError>) {
+        parse::read_cst_recovering(s.as_ref(), source_name, &self.reader_macros)
+    }
+
     /// Read and eval everything in the source file, returning
     /// the item in tail position (or `()` if there isn't anything).
+    ///
+    /// Each top-level form is read right before it's evaled, so reader macros
+    /// can be used right after they're defined.
     pub fn read_eval(
         &mut self,
         s: &str,
         source_name: String,
     ) -> Result<Result<Gc<Expr>, Exception>, ExprParseError> {
-        Ok(self
-            .read_many(s, source_name)?
-            .into_iter()
-            .map(|e| self.eval_inner(self.thtdlib(), Gc::new(e)))
-            .find_or_last(Result::is_err)
-            .unwrap_or_else(|| Ok(Gc::new(Expr::Nil))))
+        parse::read_eval(s.as_bytes(), source_name, self)
+    }
+
+    /// Reseed the default random number generator.
+    ///
+    /// By default it's seeded from the `PLEASE_SEED` environment variable, or the clock if that's not set.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = Rng::from_seed(seed);
     }
 
     /// Make or get the symbol handle of the symbol represented by the given string.
@@ -470,7 +564,7 @@ impl Exception {
         Engine::list_to_sexp(&[
             Gc::new(Expr::Symbol(engine.intern_symbol("!"))),
             Gc::new(Expr::Symbol(self.id)),
-            Gc::new(Expr::String(self.info.into_bytes())),
+            Expr::string(self.info.into_bytes()),
             trace,
             self.data,
         ])
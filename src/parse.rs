@@ -1,31 +1,95 @@
 use std::{fmt::Debug, num::ParseIntError, ops::Range};
 
 use ariadne::{CharSet, Label, Report, ReportKind};
-use gc::Gc;
-use itertools::{Either, Itertools};
+use itertools::Itertools;
 use thiserror::Error;
 
-use crate::{display::BstrFmt, hash::GcMap, Engine, Expr, Symbol};
+use crate::{
+    display::{BstrFmt, CHAR_NAMES},
+    Engine, EvalResult, Expr,
+};
+use gc::Gc;
+
+pub mod cst;
 
 /// Error when lexing or parsing an expression
 #[derive(Error)]
 #[error("{source:}")]
 pub struct ExprParseError {
     source: ExprParseErrorInfo,
+    /// Any other errors, from a recovering read.
+    more: Vec<ExprParseErrorInfo>,
     report: Report<(String, Range<usize>)>,
 }
 
 impl ExprParseError {
     fn new<'a>(s: &'a [u8], source: String, err: ExprParseErrorLimited<'a>) -> ExprParseError {
         let (start, end) = string_pos(s, err.offender);
+        ExprParseError::build(s, source, vec![(err.data, start..end, Vec::new())])
+    }
+
+    /// Put all the errors in one report.
+    ///
+    /// Each one has where it is, and hints about where it might really be from.
+    #[allow(clippy::type_complexity)]
+    fn build(
+        s: &[u8],
+        source: String,
+        errors: Vec<(
+            ExprParseErrorInfo,
+            Range<usize>,
+            Vec<(Range<usize>, String)>,
+        )>,
+    ) -> ExprParseError {
+        let mut report = Report::build(ReportKind::Error, &source, errors[0].1.start)
+            .with_config(report_config());
+        report = if let [(only, ..)] = &errors[..] {
+            report.with_message(only.to_string())
+        } else {
+            report.with_message(format!("{} syntax errors", errors.len()))
+        };
+
+        for (data, span, hints) in &errors {
+            let description = ExprParseError::describe(s, &source, data, span.clone());
+            if errors.len() == 1 {
+                if let Some(note) = description.note {
+                    report = report.with_note(note);
+                }
+            } else {
+                // The message up top can't say what each one is
+                let label = Label::new((source.clone(), span.clone())).with_message(data);
+                report = report.with_label(label);
+            }
+            for label in description.labels {
+                report = report.with_label(label);
+            }
+            for (span, hint) in hints {
+                let label = Label::new((source.clone(), span.clone())).with_message(hint);
+                report = report.with_label(label);
+            }
+        }
 
-        let mut report = Report::build(ReportKind::Error, &source, start)
-            .with_config(ariadne::Config::default().with_char_set(CharSet::Ascii))
-            .with_message(err.data.to_string());
+        let mut errors = errors.into_iter().map(|(data, ..)| data);
+        ExprParseError {
+            report: report.finish(),
+            source: errors.next().unwrap(),
+            more: errors.collect(),
+        }
+    }
 
+    /// The labels and note for one error.
+    fn describe(
+        s: &[u8],
+        source: &str,
+        data: &ExprParseErrorInfo,
+        span: Range<usize>,
+    ) -> Description {
+        let (start, end) = (span.start, span.end);
+        let offender = &s[span];
+        let mut report = Description::default();
         let all = (source.to_owned(), start..end);
 
-        match &err.data {
+        match data {
             ExprParseErrorInfo::ParseInt {
                 radix,
                 radix_prefix,
@@ -44,10 +108,41 @@ impl ExprParseError {
                     .with_label(Label::new(all).with_message(format!("this {:?} is not valid", radix)))
                     .with_note("the valid indicators are 'x' for base-16, 'o' for base-8, and 'b' for base-2");
             }
+            ExprParseErrorInfo::ZeroDenominator => {
+                report = report
+                    .with_label(
+                        Label::new(all).with_message("this rational has a denominator of 0"),
+                    )
+                    .with_note("if you wanted a symbol, symbols can't start with a digit");
+            }
+            ExprParseErrorInfo::MisplacedSeparator => {
+                report = report
+                    .with_label(Label::new(all).with_message("this '_' isn't between two digits"))
+                    .with_note("write numbers like 1_000_000 or 0xFF_FF");
+            }
+            ExprParseErrorInfo::BadChar(name) => {
+                let message = if name.is_empty() {
+                    "there's no char after this".to_owned()
+                } else if name.starts_with('x') && name[1..].bytes().all(|b| b.is_ascii_hexdigit())
+                {
+                    format!("{} isn't a unicode scalar value", &name[1..])
+                } else {
+                    format!("there's no char called {:?}", name)
+                };
+                report = report
+                    .with_label(Label::new(all).with_message(message))
+                    .with_note(format!(
+                        "chars are written like #\\a, as a hex code like #\\x41, or by name: {}",
+                        CHAR_NAMES
+                            .iter()
+                            .map(|(name, _)| format!("#\\{}", name))
+                            .join(", ")
+                    ));
+            }
             ExprParseErrorInfo::IndeterminableToken => {
                 report = report
                     .with_label(Label::new(all).with_message("this is unintelligible"))
-                    .with_note(format!("the problem is {:?}", BstrFmt(err.offender)));
+                    .with_note(format!("the problem is {:?}", BstrFmt(offender)));
             }
             ExprParseErrorInfo::ExpectedCloseParen { opener, closer } => {
                 report = report
@@ -124,11 +219,29 @@ impl ExprParseError {
             ExprParseErrorInfo::InvalidEscape(pos, problem) => {
                 report = report
                     .with_label(
-                        Label::new((source, start + pos..start + pos + 1))
+                        Label::new((source.to_owned(), start + pos..start + pos + 1))
                             .with_message(problem.to_string()),
                     )
                     .with_label(Label::new(all).with_message("the string is here".to_owned()));
             }
+            ExprParseErrorInfo::EmptyInterpolation => {
+                report = report
+                    .with_label(Label::new(all).with_message("there's nothing in here"))
+                    .with_note("write \"{{\" for a literal '{'");
+            }
+            ExprParseErrorInfo::ExpectedCloseBrace => {
+                report = report
+                    .with_label(
+                        Label::new(all)
+                            .with_message("this should be one expression and then a '}'"),
+                    )
+                    .with_note("wrap several expressions in a (do ...)");
+            }
+            ExprParseErrorInfo::UnexpectedCloseBrace => {
+                report = report
+                    .with_label(Label::new(all).with_message("this '}' doesn't close a '{'"))
+                    .with_note("write \"}}\" for a literal '}'");
+            }
             ExprParseErrorInfo::InvalidRemainder => {
                 report = report
                     .with_label(
@@ -150,6 +263,11 @@ impl ExprParseError {
             ExprParseErrorInfo::PrefixNothing => {
                 report = report.with_label(Label::new(all).with_message("you can't quote that"))
             }
+            ExprParseErrorInfo::DatumCommentNothing { .. } => {
+                report = report
+                    .with_label(Label::new(all).with_message("this comments out the next datum"))
+                    .with_note("use ; to comment out the rest of the line");
+            }
             ExprParseErrorInfo::Eof => {
                 report = report.with_label(Label::new(all).with_message("found nothing here"))
             }
@@ -158,15 +276,50 @@ impl ExprParseError {
                     Label::new(all).with_message(format!("the sexpr had length {}", *count)),
                 )
             }
+            ExprParseErrorInfo::MapSpliceValue => {
+                report = report.with_label(
+                    Label::new(all).with_message("this would be the value for the key before it"),
+                )
+            }
             ExprParseErrorInfo::MapNeedsSexpr => {
                 report = report.with_label(Label::new(all).with_message("should be a sexpr"))
             }
+            ExprParseErrorInfo::UnknownReaderMacro(name) => {
+                report = report
+                    .with_label(
+                        Label::new(all)
+                            .with_message(format!("{} was read as a reader macro", name)),
+                    )
+                    .with_note(
+                        "this can happen when lowering a CST with a different engine than read it",
+                    );
+            }
+            ExprParseErrorInfo::ReaderMacroFailed(_, exn) => {
+                report = report.with_label(Label::new(all).with_message(format!("raised {}", exn)))
+            }
+            ExprParseErrorInfo::Unreadable => {
+                report = report
+                    .with_label(Label::new(all).with_message("a recovering read skipped this"))
+            }
         }
+        report
+    }
 
-        ExprParseError {
-            report: report.finish(),
-            source: err.data,
-        }
+    pub(crate) fn from_cst(s: &[u8], source: String, err: cst::CstError) -> ExprParseError {
+        ExprParseError::from_csts(s, source, vec![err])
+    }
+
+    /// `errors` can't be empty.
+    pub(crate) fn from_csts(
+        s: &[u8],
+        source: String,
+        errors: Vec<cst::CstError>,
+    ) -> ExprParseError {
+        let errors = errors
+            .into_iter()
+            .map(|err| (err.info, err.span, err.hints))
+            .collect();
+        ExprParseError::build(s, source, errors)
     }
 
     pub fn report(&self) -> &Report<(String, Range<usize>)> {
@@ -174,9 +327,40 @@ impl ExprParseError {
     }
 
     /// Get a reference to the expr parse error's source.
+    ///
+    /// If there was more than one error, this is the first.
     pub fn source(&self) -> &ExprParseErrorInfo {
         &self.source
     }
+
+    /// All the errors, in the order they're in the source.
+    pub fn sources(&self) -> impl Iterator<Item = &ExprParseErrorInfo> {
+        std::iter::once(&self.source).chain(&self.more)
+    }
+}
+
+/// How reports of errors in text look.
+pub(crate) fn report_config() -> ariadne::Config {
+    ariadne::Config::default().with_char_set(CharSet::Ascii)
+}
+
+/// Labels and a note for one error, so several can go in one report.
+#[derive(Default)]
+struct Description {
+    labels: Vec<Label<(String, Range<usize>)>>,
+    note: Option<String>,
+}
+
+impl Description {
+    fn with_label(mut self, label: Label<(String, Range<usize>)>) -> Self {
+        self.labels.push(label);
+        self
+    }
+
+    fn with_note(mut self, note: impl ToString) -> Self {
+        self.note = Some(note.to_string());
+        self
+    }
 }
 
 impl Debug for ExprParseError {
@@ -195,8 +379,15 @@ pub enum ExprParseErrorInfo {
     },
     #[error("{0:?} is not a valid radix selector")]
     BadIntRadix(char),
+    #[error("cannot divide by zero in a rational literal")]
+    ZeroDenominator,
     #[error("could not figure out what kind of token this was meant to be")]
     IndeterminableToken,
+    #[error("a digit separator has to go between two digits")]
+    MisplacedSeparator,
+    /// Contains what was after the `#\\`
+    #[error("not a valid char literal")]
+    BadChar(String),
     /// This error should span all the way from the opening paren to the closer
     #[error("expected a closing {closer:?} to this sexpr")]
     ExpectedCloseParen { opener: char, closer: char },
@@ -223,12 +414,21 @@ pub enum ExprParseErrorInfo {
     /// where the bad is
     #[error("bad escape sequence")]
     InvalidEscape(usize, InvalidEscape),
+    #[error("expected an expression between the braces")]
+    EmptyInterpolation,
+    #[error("expected a '}}' after the expression")]
+    ExpectedCloseBrace,
+    #[error("did not expect a '}}' here")]
+    UnexpectedCloseBrace,
     #[error("after reading one datum, there was leftover")]
     InvalidRemainder,
     #[error("expected a closing \"*;\" to this block comment")]
     ExpectedCloseBlockComment,
     #[error("found nothing after a special prefix")]
     PrefixNothing,
+    /// `at_eof` is whether it's because the source ended
+    #[error("found nothing after a #; to comment out")]
+    DatumCommentNothing { at_eof: bool },
     #[error("expected a datum but found nothing")]
     Eof,
     #[error("expected a sexpr after a # for a map literal")]
@@ -236,6 +436,16 @@ pub enum ExprParseErrorInfo {
     /// Number is how many exprs ended up being there
     #[error("map literal requires an even number of exprs")]
     MapNeedsEven(usize),
+    #[error("a ,@ in a map literal has to go where a key would")]
+    MapSpliceValue,
+    /// Contains how the reader macro is spelled
+    #[error("there is no reader macro {0}")]
+    UnknownReaderMacro(String),
+    /// Contains how the reader macro is spelled, and the exception it raised
+    #[error("the reader macro {0} raised an exception")]
+    ReaderMacroFailed(String, String),
+    #[error("this couldn't be read")]
+    Unreadable,
 }
 
 struct ExprParseErrorLimited<'a> {
@@ -244,6 +454,8 @@ struct ExprParseErrorLimited<'a> {
 }
 
 /// Read as many datums as possible from the source string until it is exhausted.
+///
+/// If there are syntax errors, they're all reported, not just the first.
 pub fn read_many(
     whole: &[u8],
     source: String,
@@ -252,21 +464,82 @@ pub fn read_many(
     // ariadne doesn't like carriage returns, so we strip them
     // until zesterer gets their act together
     let whole = whole.iter().copied().filter(|b| *b != b'\r').collect_vec();
-    let whole = whole.as_slice();
+    let cst = read_cst(&whole, source.clone(), &engine.reader_macros)?;
+    cst.to_exprs(engine)
+        .map_err(|err| ExprParseError::from_cst(&whole, source, err))
+}
+
+/// Read the source losslessly, reporting all the syntax errors if there are any.
+#[allow(clippy::result_large_err)]
+pub fn read_cst(
+    whole: &[u8],
+    source: String,
+    macros: &cst::ReaderMacros,
+) -> Result<cst::Cst, ExprParseError> {
+    cst::read(whole, macros).map_err(|_| all_errors(whole, source, macros))
+}
+
+/// Read the source losslessly, skipping the parts that can't be read.
+pub fn read_cst_recovering(
+    whole: &[u8],
+    source: String,
+    macros: &cst::ReaderMacros,
+) -> (cst::Cst, Option<ExprParseError>) {
+    let (cst, errors) = cst::read_recovering(whole, macros);
+    let err = if errors.is_empty() {
+        None
+    } else {
+        Some(ExprParseError::from_csts(whole, source, errors))
+    };
+    (cst, err)
+}
+
+/// Find every syntax error in source that has at least one.
+fn all_errors(whole: &[u8], source: String, macros: &cst::ReaderMacros) -> ExprParseError {
+    let (_, errors) = cst::read_recovering(whole, macros);
+    ExprParseError::from_csts(whole, source, errors)
+}
+
+/// Read the whole source, then eval one top-level form at a time.
+///
+/// Nothing runs if any of it can't be read. If a form sets a reader macro,
+/// the rest is read again with it, and checked again before any more runs.
+///
+/// Returns the last value, or the first exception.
+#[allow(clippy::result_large_err)]
+pub fn read_eval(
+    whole: &[u8],
+    source: String,
+    engine: &mut Engine,
+) -> Result<EvalResult, ExprParseError> {
+    let whole = whole.iter().copied().filter(|b| *b != b'\r').collect_vec();
 
-    let mut out = Vec::new();
+    let mut pos = 0;
+    let mut out = Expr::nil();
+    'reading: loop {
+        let (cst, errors) = cst::read_recovering_from(&whole, pos, &engine.reader_macros);
+        if !errors.is_empty() {
+            return Err(ExprParseError::from_csts(&whole, source, errors));
+        }
 
-    let mut s = whole;
-    while !s.is_empty() {
-        let (expr, rest) = try_read_expr(s, engine)
-            .map_err(|err| ExprParseError::new(whole, source.to_owned(), err))?;
-        if let Some(expr) = expr {
-            out.push(expr);
+        let version = engine.reader_macros.version();
+        for node in &cst.nodes {
+            let expr = node
+                .to_expr(&whole, engine)
+                .map_err(|err| ExprParseError::from_cst(&whole, source.clone(), err))?;
+            out = match engine.eval_inner(engine.thtdlib(), Gc::new(expr)) {
+                Ok(it) => it,
+                Err(exn) => return Ok(Err(exn)),
+            };
+            if engine.reader_macros.version() != version {
+                // The rest might mean something else now
+                pos = node.span.end;
+                continue 'reading;
+            }
         }
-        s = rest;
+        break;
     }
-
-    Ok(out)
+    Ok(Ok(out))
 }
 
 /// Read exactly one datum and return it.
@@ -297,30 +570,20 @@ pub fn read_one(s: &[u8], source: String, engine: &mut Engine) -> Result<Expr, E
     }
 }
 
-/// Ok case means "we read this and had this string leftover";
-/// Err case means "oh no we couldn't read the thing"
-type ReadResult<'a, T> = Result<(T, &'a [u8]), ExprParseErrorLimited<'a>>;
-
 #[derive(Debug, Error)]
 pub enum InvalidEscape {
     #[error("{0:?} cannot be escaped")]
     BadChar(char),
+    #[error("\\x needs two hex digits after it")]
+    BadHex,
+    #[error("\\u needs a hex code in braces after it, like \\u{{1F600}}")]
+    BadUnicodeSyntax,
+    #[error("{0:X} isn't a unicode scalar value")]
+    BadUnicode(u32),
     #[error("cannot escape the end of file")]
     Eof,
 }
 
-/// also trim whitespace from start
-fn read_until_delim(s: &[u8]) -> (&[u8], &[u8]) {
-    let s = s.trim_start();
-    let idx = s.find_by(is_delim);
-    match idx {
-        // there is no remainder, it is all token
-        // make the "rest" string still part of s by splitting it at the end
-        None => s.split_at(s.len()),
-        Some(idx) => s.split_at(idx),
-    }
-}
-
 fn is_delim(b: u8) -> bool {
     match b {
         b'(' | b')' | b'[' | b']' | b'{' | b'}' => true,
@@ -329,288 +592,6 @@ fn is_delim(b: u8) -> bool {
     }
 }
 
-fn try_read_expr<'a>(whole: &'a [u8], state: &mut Engine) -> ReadResult<'a, Option<Expr>> {
-    let s = whole.trim_start();
-    if s.starts_with(b";*") {
-        // block comment
-        // find the next ;* and recur, or the next *; and return the rest.
-        fn recur(s: &[u8]) -> Result<&[u8], ()> {
-            if let Some(idx) = s.find(b";*") {
-                // go down the call stack and find its closer
-                // skip the 2 bytes
-                recur(&s[idx + 2..])
-            } else if let Some(idx) = s.find(b"*;") {
-                // we're done!
-                Ok(&s[idx + 2..])
-            } else {
-                // eof :(
-                Err(())
-            }
-        }
-
-        if let Ok(rest) = recur(s) {
-            return try_read_expr(rest, state);
-        } else {
-            return Err(ExprParseErrorLimited {
-                data: ExprParseErrorInfo::ExpectedCloseBlockComment,
-                offender: s,
-            });
-        }
-    } else if s.starts_with(b";") {
-        // line comment
-        let rest = if let Some(idx) = s.find(b"\n") {
-            &s[idx..]
-        } else {
-            // get the end of the string
-            s.split_at(s.len()).1
-        };
-        return try_read_expr(rest, state);
-    }
-
-    if let Some(quote) = try_read_prefix_family(s, state) {
-        let ((quotefunc, quoted), rest) = quote?;
-
-        let quotesym = state.intern_symbol(quotefunc);
-        let quote = Gc::new(Expr::Symbol(quotesym));
-        let quoted = Gc::new(quoted);
-
-        let list = Expr::Pair(quote, Expr::pair(quoted, Expr::nil()));
-        Ok((Some(list), rest))
-    } else if let Some(b) = try_read_bool(s, state) {
-        let (b, rest) = b?;
-        Ok((Some(Expr::Bool(b)), rest))
-    } else if let Some(int) = try_read_int(s, state) {
-        let (int, rest) = int?;
-        Ok((Some(Expr::Integer(int)), rest))
-    } else if let Some(float) = try_read_float(s, state) {
-        let (float, rest) = float?;
-        Ok((Some(Expr::Float(float)), rest))
-    } else if let Some(string) = try_read_string(s, state) {
-        let (string, rest) = string?;
-        Ok((Some(Expr::String(string)), rest))
-    } else if let Some(ur_mom) = try_read_sexpr(s, state) {
-        let (sexhaha, rest) = ur_mom?;
-        Ok((Some(sexhaha), rest))
-    } else if let Some(map) = try_read_map(s, state) {
-        let (map, rest) = map?;
-        Ok((Some(Expr::Map(map)), rest))
-    } else if let Some(symbol) = try_read_symbol(s, state) {
-        // Do symbols last so we need to specially omit as little as possible
-        let (id, rest) = symbol?;
-        Ok((Some(Expr::Symbol(id)), rest))
-    } else if s.starts_by(is_closer) {
-        let problem = s.first().unwrap();
-        Err(ExprParseErrorLimited {
-            data: ExprParseErrorInfo::UnexpectedCloseParen {
-                closer: *problem as char,
-            },
-            offender: &s[..1],
-        })
-    } else if s.contains_by(|b| !b.is_ascii_whitespace()) {
-        Err(ExprParseErrorLimited {
-            data: ExprParseErrorInfo::IndeterminableToken,
-            offender: s,
-        })
-    } else {
-        Ok((None, s))
-    }
-}
-
-fn try_read_float<'a>(s: &'a [u8], _state: &mut Engine) -> Option<ReadResult<'a, f64>> {
-    let (s, rest) = read_until_delim(s);
-    if s.starts_by(|b| b.is_ascii_digit() || b"+-.".contains(&b)) {
-        let f = (|| {
-            let stred = std::str::from_utf8(s).ok()?;
-            let f = stred.parse::<f64>().ok()?;
-            Some(f)
-        })();
-        f.map(|f| Ok((f, rest)))
-    } else {
-        None
-    }
-}
-
-fn try_read_int<'a>(s: &'a [u8], _state: &mut Engine) -> Option<ReadResult<'a, i64>> {
-    let (whole, rest) = read_until_delim(s);
-    if whole.contains(&b'.') {
-        // maybe a float, who knows? it's sure not an int
-        return None;
-    }
-
-    if whole.starts_by(|b| b.is_ascii_digit() || b"+-".contains(&b)) {
-        let (radix, prefix, s) = if whole.starts_by(|b| b == b'-' || b == b'+') {
-            (10, None, whole)
-        } else {
-            let base = whole.get(1);
-            match base {
-                None => (10, None, whole),
-                Some(b) => {
-                    if b.is_ascii_digit() {
-                        (10, None, whole)
-                    } else {
-                        let radix = match b {
-                            b'x' => 16,
-                            b'o' => 8,
-                            b'b' => 2,
-                            _ => {
-                                return Some(Err(ExprParseErrorLimited {
-                                    data: ExprParseErrorInfo::BadIntRadix(*b as char),
-                                    offender: &whole[1..2],
-                                }));
-                            }
-                        };
-                        (radix, Some(&whole[..2]), &whole[2..])
-                    }
-                }
-            }
-        };
-
-        let stred = std::str::from_utf8(s).ok()?;
-        match i64::from_str_radix(stred, radix) {
-            Ok(num) => Some(Ok((num, rest))),
-            Err(ono) => {
-                if whole.starts_by(|b| b == b'-' || b == b'+') {
-                    // Special-case allow symbols starting with `+` and `-`
-                    // by passing thru here
-                    None
-                } else {
-                    Some(Err(ExprParseErrorLimited {
-                        data: ExprParseErrorInfo::ParseInt {
-                            radix,
-                            radix_prefix: prefix.map(|s| String::from_utf8_lossy(s).into_owned()),
-                            source: ono,
-                        },
-                        offender: whole,
-                    }))
-                }
-            }
-        }
-    } else {
-        None
-    }
-}
-
-fn try_read_bool<'a>(s: &'a [u8], _: &mut Engine) -> Option<ReadResult<'a, bool>> {
-    let (s, rest) = read_until_delim(s);
-
-    match s {
-        b"true" => Some(Ok((true, rest))),
-        b"false" => Some(Ok((false, rest))),
-        _ => None,
-    }
-}
-
-fn try_read_symbol<'a>(s: &'a [u8], state: &mut Engine) -> Option<ReadResult<'a, Symbol>> {
-    let (s, rest) = read_until_delim(s);
-
-    if is_valid_symbol(s) {
-        let id = state.intern_symbol(s);
-        Some(Ok((id, rest)))
-    } else {
-        None
-    }
-}
-
-fn is_valid_symbol(s: &[u8]) -> bool {
-    !s.is_empty() && !s.starts_by(|b| b.is_ascii_digit())
-}
-
-/// May return a pair or null
-#[allow(clippy::type_complexity)]
-fn try_read_sexpr<'a>(s: &'a [u8], state: &mut Engine) -> Option<ReadResult<'a, Expr>> {
-    #[allow(clippy::type_complexity)]
-    fn recurse<'b>(
-        s: &'b [u8],
-        opener: u8,
-        closer: u8,
-        state: &mut Engine,
-        original_rest: &'b [u8],
-    ) -> Result<(Expr, &'b [u8]), ExprParseErrorLimited<'b>> {
-        let s = s.trim_start();
-
-        if let Some(rest) = s.strip_prefix(&[closer]) {
-            // we can finally rest
-            Ok((Expr::Nil, rest))
-        } else if s.starts_by(is_closer) {
-            let (start, _) = string_pos(original_rest, s);
-            Err(ExprParseErrorLimited {
-                data: ExprParseErrorInfo::WrongCloseParen {
-                    opener: opener as char,
-                    expected_closer: closer as char,
-                    got_closer: s[0] as char,
-                },
-                offender: &original_rest[..=start],
-            })
-        } else {
-            let (car, rest) = try_read_expr(s, state)?;
-            match car {
-                Some(car) => {
-                    let (rest_first, rest_last) = read_until_delim(rest);
-                    if rest_first == b"." {
-                        // ok we expect one more expr then leave
-                        let (expr, rest) = try_read_expr(rest_last, state)?;
-                        let cdr = match expr {
-                            Some(it) => it,
-                            None => {
-                                return Err(ExprParseErrorLimited {
-                                    data: ExprParseErrorInfo::WrongDotTrailCount,
-                                    offender: s,
-                                })
-                            }
-                        };
-
-                        return if let Some(rest) = rest.strip_prefix(&[closer]) {
-                            let pair = Expr::Pair(Gc::new(car), Gc::new(cdr));
-                            Ok((pair, rest))
-                        } else if let Some(problem) = rest.strip_prefix_by(is_closer) {
-                            Err(ExprParseErrorLimited {
-                                data: ExprParseErrorInfo::WrongCloseParen {
-                                    opener: opener as char,
-                                    expected_closer: closer as char,
-                                    got_closer: problem[0] as char,
-                                },
-                                offender: &s[..=problem.len()],
-                            })
-                        } else {
-                            Err(ExprParseErrorLimited {
-                                data: ExprParseErrorInfo::WrongDotTrailCount,
-                                offender: rest,
-                            })
-                        };
-                    }
-
-                    let (cdr, rest) = recurse(rest, opener, closer, state, original_rest)?;
-                    let pair = Expr::Pair(Gc::new(car), Gc::new(cdr));
-                    Ok((pair, rest))
-                }
-                None => Err(ExprParseErrorLimited {
-                    data: ExprParseErrorInfo::ExpectedCloseParen {
-                        opener: opener as char,
-                        closer: closer as char,
-                    },
-                    offender: original_rest,
-                }),
-            }
-        }
-    }
-    let (s, rest) = read_until_delim(s);
-    // The paren is always put in `rest`, this means we have only WS until a (
-    if s.is_empty() {
-        let opener = *rest.first()?;
-        if let Some(closer) = match_paren(opener) {
-            let s = &rest[1..];
-
-            let (list, rest) = match recurse(s, opener, closer, state, rest) {
-                Ok(it) => it,
-                Err(err) => return Some(Err(err)),
-            };
-            return Some(Ok((list, rest)));
-        }
-    }
-
-    None
-}
-
 /// Get the character matching with the given character
 fn match_paren(b: u8) -> Option<u8> {
     Some(match b {
@@ -625,114 +606,6 @@ fn is_closer(b: u8) -> bool {
     b == b')' || b == b']' || b == b'}'
 }
 
-fn try_read_string<'a>(s: &'a [u8], state: &mut Engine) -> Option<ReadResult<'a, Vec<u8>>> {
-    let whole = s.trim_start();
-    if let Some(rest) = whole.strip_prefix(b"<<") {
-        let newline_pos = match rest.find(b"\n") {
-            Some(it) => it,
-            None => {
-                return Some(Err(ExprParseErrorLimited {
-                    data: ExprParseErrorInfo::HerestringNoContents,
-                    offender: rest,
-                }))
-            }
-        };
-        let (here_starter, rest) = rest.split_at(newline_pos);
-        if here_starter.is_empty() {
-            return Some(Err(ExprParseErrorLimited {
-                data: ExprParseErrorInfo::HerestringNoContents,
-                offender: here_starter,
-            }));
-        }
-        // now find it with the newline to force it to be
-        // alone on the line
-        let here_ender = [b"\n", here_starter, b"\n"].concat();
-        let herestring_end = match rest[1..].find(&here_ender) {
-            Some(it) => it,
-            None => {
-                return Some(Err(ExprParseErrorLimited {
-                    data: ExprParseErrorInfo::HerestringNoEnding(
-                        String::from_utf8_lossy(here_starter).into_owned(),
-                    ),
-                    offender: here_starter,
-                }))
-            }
-        };
-        let (herestring, rest) = rest[1..].split_at(herestring_end);
-        Some(Ok((herestring.to_vec(), &rest[here_ender.len()..])))
-    } else {
-        try_read_normal_string(s, state)
-    }
-}
-
-fn try_read_normal_string<'a>(s: &'a [u8], _state: &mut Engine) -> Option<ReadResult<'a, Vec<u8>>> {
-    let whole = s.trim_start();
-    if let Some(mut s) = whole.strip_prefix(b"\"") {
-        // doesn't make sure \ is before "
-        // see which index is first, use Either to figure which to consume to.
-        let mut accumulated = Vec::new();
-        loop {
-            let bs_pos = s.find(b"\\");
-            let quote_pos = s.find(b"\"");
-            // See which index is first
-            // Left = backslash, Right = quote
-            let point_of_interest = match (bs_pos, quote_pos) {
-                (Some(bs), Some(quote)) => {
-                    // Worry about whichever is first
-                    if bs < quote {
-                        Either::Left(bs)
-                    } else {
-                        Either::Right(quote)
-                    }
-                }
-                (None, Some(quote)) => {
-                    // There's no backslashes in the entire rest of source so use the quote
-                    Either::Right(quote)
-                }
-                // There's no quote left so bail!
-                _ => {
-                    // fix bug with ariadne
-                    let remove_newline = if let Some(idx) = whole.find(b"\r\n") {
-                        &whole[..idx]
-                    } else {
-                        whole
-                    };
-                    return Some(Err(ExprParseErrorLimited {
-                        data: ExprParseErrorInfo::ExpectedCloseQuote,
-                        offender: remove_newline,
-                    }));
-                }
-            };
-
-            match point_of_interest {
-                Either::Left(esc_pos) => {
-                    // add 1 to skip the backslash
-                    let rest = &s[esc_pos + 1..];
-                    let (esc, rest) = match escape(rest) {
-                        Ok(it) => it,
-                        Err(ono) => {
-                            let (badpos, _) = string_pos(whole, rest);
-                            return Some(Err(ExprParseErrorLimited {
-                                data: ExprParseErrorInfo::InvalidEscape(badpos, ono),
-                                offender: &whole[..=badpos],
-                            }));
-                        }
-                    };
-                    accumulated.extend_from_slice(&s[..esc_pos]);
-                    accumulated.extend_from_slice(&esc);
-                    s = rest;
-                }
-                Either::Right(quote_pos) => {
-                    accumulated.extend_from_slice(&s[..quote_pos]);
-                    return Some(Ok((accumulated, &s[quote_pos + 1..])));
-                }
-            }
-        }
-    } else {
-        None
-    }
-}
-
 /// Consume an escape sequence with the backslash already gone.
 fn escape(s: &[u8]) -> Result<(Vec<u8>, &[u8]), InvalidEscape> {
     match s.first() {
@@ -754,80 +627,33 @@ fn escape(s: &[u8]) -> Result<(Vec<u8>, &[u8]), InvalidEscape> {
                 b'v' => Ok((b"\x0b".to_vec(), naive_rest)),
                 // Bell
                 b'a' => Ok((b"\x07".to_vec(), naive_rest)),
-
-                _ => Err(InvalidEscape::BadChar(sentinel as char)),
-            }
-        }
-    }
-}
-
-/// For prefixes that insert their own sexprs, like the quote family and #.
-///
-/// `true` means the substitution goes on the outside, `false` means on the inside.
-fn try_read_prefix_family<'a>(
-    whole: &'a [u8],
-    state: &mut Engine,
-) -> Option<ReadResult<'a, (&'static [u8], Expr)>> {
-    let whole = whole.trim_start();
-    special_prefixes(whole).map(|(quote, rest)| {
-        try_read_expr(rest, state).and_then(|(expr, rest)| {
-            if let Some(expr) = expr {
-                Ok(((quote, expr), rest))
-            } else {
-                Err(ExprParseErrorLimited {
-                    data: ExprParseErrorInfo::PrefixNothing,
-                    // include quote
-                    offender: whole,
-                })
-            }
-        })
-    })
-}
-
-/// Return the name of the form to put inside the parensm and the rest of the string.
-fn special_prefixes<'a>(s: &'a [u8]) -> Option<(&'static [u8], &'a [u8])> {
-    [
-        (&b"'"[..], &b"quote"[..]),
-        (b"`", b"quasiquote"),
-        // put this first so it looks for it first
-        (b",@", b"unquote-splicing"),
-        (b",", b"unquote"),
-    ]
-    .iter()
-    .find_map(|(header, quote)| s.strip_prefix(*header).map(|rest| (*quote, rest)))
-}
-
-fn try_read_map<'a>(s: &'a [u8], state: &mut Engine) -> Option<ReadResult<'a, GcMap>> {
-    let (s, sexp_str) = read_until_delim(s);
-    if s == b"#" {
-        match try_read_sexpr(sexp_str, state) {
-            Some(Ok((expr, rest))) => {
-                // safe to unwrap because it's not using any lazy pairs and because it will always be a sexpr
-                let kvs = state.sexp_to_list(Gc::new(expr)).unwrap().unwrap();
-                if kvs.len() % 2 != 0 {
-                    let (start, _) = string_pos(sexp_str, rest);
-                    let offender = &sexp_str[..start];
-                    return Some(Err(ExprParseErrorLimited {
-                        data: ExprParseErrorInfo::MapNeedsEven(kvs.len()),
-                        offender,
-                    }));
+                // Any byte, even if it's not UTF-8
+                b'x' => {
+                    let byte = naive_rest
+                        .get(..2)
+                        .and_then(|hex| std::str::from_utf8(hex).ok())
+                        .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                        .ok_or(InvalidEscape::BadHex)?;
+                    let byte = u8::from_str_radix(byte, 16).unwrap();
+                    Ok((vec![byte], &naive_rest[2..]))
                 }
-
-                let mut map = GcMap::new();
-                for kv in kvs.chunks_exact(2) {
-                    map.insert(kv[0].to_owned(), kv[1].to_owned());
+                b'u' => {
+                    let close = naive_rest
+                        .find(b"}")
+                        .filter(|&close| naive_rest[0] == b'{' && (2..=7).contains(&close))
+                        .ok_or(InvalidEscape::BadUnicodeSyntax)?;
+                    let code = std::str::from_utf8(&naive_rest[1..close])
+                        .ok()
+                        .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                        .ok_or(InvalidEscape::BadUnicodeSyntax)?;
+                    let c = char::from_u32(code).ok_or(InvalidEscape::BadUnicode(code))?;
+                    Ok((c.to_string().into_bytes(), &naive_rest[close + 1..]))
                 }
 
-                Some(Ok((map, rest)))
+                _ => Err(InvalidEscape::BadChar(sentinel as char)),
             }
-            Some(Err(ono)) => Some(Err(ono)),
-            None => Some(Err(ExprParseErrorLimited {
-                data: ExprParseErrorInfo::MapNeedsSexpr,
-                offender: sexp_str,
-            })),
         }
-    } else {
-        None
     }
 }
 
@@ -876,24 +702,12 @@ fn test_string_pos() {
 }
 
 trait BytestrExt {
-    fn trim_start(&self) -> &Self;
     fn find(&self, needle: &Self) -> Option<usize>;
     fn find_by(&self, searcher: impl FnMut(u8) -> bool) -> Option<usize>;
     fn starts_by(&self, searcher: impl FnMut(u8) -> bool) -> bool;
-    fn contains_by(&self, searcher: impl FnMut(u8) -> bool) -> bool;
-    fn strip_prefix_by(&self, searcher: impl FnMut(u8) -> bool) -> Option<&Self>;
 }
 
 impl BytestrExt for [u8] {
-    fn trim_start(&self) -> &Self {
-        // find the first position that is *not* whitespace and cut before it
-        let idx = self
-            .iter()
-            .position(|b| !b" \t\r\n\0\x0b\x0c".contains(b))
-            .unwrap_or_else(|| self.len());
-        &self[idx..]
-    }
-
     fn find(&self, needle: &Self) -> Option<usize> {
         self.windows(needle.len()).position(|slice| slice == needle)
     }
@@ -908,16 +722,4 @@ impl BytestrExt for [u8] {
             None => false,
         }
     }
-
-    fn contains_by(&self, searcher: impl FnMut(u8) -> bool) -> bool {
-        self.iter().copied().any(searcher)
-    }
-
-    fn strip_prefix_by(&self, searcher: impl FnMut(u8) -> bool) -> Option<&Self> {
-        if self.starts_by(searcher) {
-            Some(&self[1..])
-        } else {
-            None
-        }
-    }
 }
@@ -0,0 +1,1335 @@
+//! Lossless reading, for tools that care about how the source was written
+//! and not just what it means.
+//!
+//! Every byte of the source ends up in exactly one node or bit of trivia,
+//! so writing them all back out in order gives the original source.
+//! [`Cst::to_exprs`] turns it into the `Expr`s that [`Engine::read_many`] gives.
+
+use std::{collections::HashMap, ops::Range};
+
+use gc::Gc;
+
+use super::{escape, is_closer, is_delim, match_paren, BytestrExt, ExprParseErrorInfo};
+use crate::{display::CHAR_NAMES, eval::thtd::Num, hash::GcMap, Engine, Expr};
+
+/// Whitespace and comments: the stuff between datums.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    /// `;` up to the end of the line, not including the newline.
+    LineComment,
+    /// `;* ... *;`, which can nest.
+    BlockComment,
+    /// `#;` and the datum after it.
+    DatumComment,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub kind: NodeKind,
+    /// Where the node itself is, not counting its trivia.
+    pub span: Range<usize>,
+    /// Trivia between the previous thing and this node.
+    pub leading: Vec<Trivia>,
+    /// Comments after this node on the same line, and the whitespace before them.
+    pub trailing: Vec<Trivia>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeKind {
+    Symbol,
+    Bool(bool),
+    Integer {
+        value: i64,
+        radix: u32,
+        /// Where something like `0x` is.
+        radix_prefix: Option<Range<usize>>,
+    },
+    Rational {
+        numer: i64,
+        denom: i64,
+    },
+    Float(f64),
+    /// `#\a`, `#\space`, or `#\x41`.
+    Char(char),
+    /// The `.` before the last thing in a dotted list.
+    Dot,
+    /// `"..."`.
+    String {
+        /// The contents with the escapes processed.
+        value: Vec<u8>,
+        /// Where each escape sequence is, backslash and all.
+        escapes: Vec<Range<usize>>,
+    },
+    /// `<<EOF`, then lines up to the one saying `EOF`.
+    ///
+    /// The span ends after the closing `EOF`; the newline after it is trivia.
+    Herestring {
+        /// The `EOF` after the `<<`.
+        delimiter: Range<usize>,
+        /// The lines between the delimiters, not counting the newline before the closing one.
+        contents: Range<usize>,
+    },
+    /// `$"..."` or `$<<EOF`, with `{expr}`s in it.
+    ///
+    /// It reads as a call to `string` with the pieces.
+    Interpolated(Vec<Piece>),
+    /// `#name"..."`, for a dispatch reader macro that wants the raw text.
+    RawDispatch {
+        /// The `name` after the `#`.
+        name: Range<usize>,
+        /// Between the quotes, backslashes and all.
+        contents: Range<usize>,
+    },
+    List {
+        open: u8,
+        close: u8,
+        children: Vec<Node>,
+        /// Trivia after the last child, before the closer.
+        inner: Vec<Trivia>,
+    },
+    /// Source that couldn't be read, from a recovering read.
+    Error,
+    /// Something like `'x`, `#{a b}`, or a reader macro and its datum.
+    Prefixed {
+        prefix: Prefix,
+        prefix_span: Range<usize>,
+        inner: Box<Node>,
+    },
+}
+
+/// Part of an interpolated string.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Piece {
+    /// Text with the escapes processed, and `{{` and `}}` turned into braces.
+    Text(Vec<u8>),
+    /// What was between a `{` and `}`.
+    Expr(Node),
+}
+
+/// The reader prefixes that wrap the next datum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prefix {
+    /// `'`
+    Quote,
+    /// `` ` ``
+    Quasiquote,
+    /// `,`
+    Unquote,
+    /// `,@`
+    UnquoteSplicing,
+    /// `#` before a list, for a map literal.
+    Map,
+    /// A character with a reader macro, like `~`.
+    Macro(char),
+    /// `#name`, where `name` has a reader macro.
+    Dispatch,
+}
+
+impl Prefix {
+    /// The form it wraps the datum in, like `quote` for `'`.
+    ///
+    /// Maps and reader macros are their own thing, so they don't have one.
+    pub fn form_name(self) -> Option<&'static str> {
+        match self {
+            Prefix::Quote => Some("quote"),
+            Prefix::Quasiquote => Some("quasiquote"),
+            Prefix::Unquote => Some("unquote"),
+            Prefix::UnquoteSplicing => Some("unquote-splicing"),
+            Prefix::Map | Prefix::Macro(_) | Prefix::Dispatch => None,
+        }
+    }
+
+    /// Find a built-in prefix and how long it is.
+    fn builtin(s: &[u8]) -> Option<(Prefix, usize)> {
+        if s.starts_with(b",@") {
+            Some((Prefix::UnquoteSplicing, 2))
+        } else {
+            let prefix = match s.first()? {
+                b'\'' => Prefix::Quote,
+                b'`' => Prefix::Quasiquote,
+                b',' => Prefix::Unquote,
+                // just a `#` on its own, for maps
+                b'#' if s.get(1).is_none_or(|&b| is_delim(b)) => Prefix::Map,
+                _ => return None,
+            };
+            Some((prefix, 1))
+        }
+    }
+}
+
+/// Procedures scripts have registered to read their own syntax.
+///
+/// Each one gets the datum after its prefix, and whatever it returns is
+/// what the reader reads.
+#[derive(Debug, Clone, Default)]
+pub struct ReaderMacros {
+    /// Goes up every time a reader macro is set, so readers can tell
+    /// if what they've read might read differently now.
+    version: u64,
+    /// For characters that prefix the next datum, like `'` does.
+    pub prefixes: HashMap<char, Gc<Expr>>,
+    /// For `#name datum`, or `#name"raw text"` which gets the text as a string
+    /// without processing escapes.
+    pub dispatch: HashMap<Vec<u8>, Gc<Expr>>,
+}
+
+impl ReaderMacros {
+    pub fn set_prefix(&mut self, c: char, proc: Gc<Expr>) {
+        self.prefixes.insert(c, proc);
+        self.version += 1;
+    }
+
+    pub fn set_dispatch(&mut self, name: Vec<u8>, proc: Gc<Expr>) {
+        self.dispatch.insert(name, proc);
+        self.version += 1;
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// The reader macro character at the start of this, if there is one.
+    fn prefix_at(&self, s: &[u8]) -> Option<char> {
+        if self.prefixes.is_empty() {
+            return None;
+        }
+        // Only the first char has to be valid
+        let c = String::from_utf8_lossy(&s[..s.len().min(4)])
+            .chars()
+            .next()?;
+        Some(c).filter(|c| self.prefixes.contains_key(c))
+    }
+
+    /// The name of the `#name` dispatch macro at the start of this, if there is one.
+    fn dispatch_at<'s>(&self, s: &'s [u8]) -> Option<&'s [u8]> {
+        let rest = s.strip_prefix(b"#")?;
+        let name = &rest[..rest
+            .find_by(|b| is_delim(b) || b == b'"')
+            .unwrap_or(rest.len())];
+        Some(name).filter(|name| self.dispatch.contains_key(*name))
+    }
+}
+
+/// A whole file's worth of nodes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cst {
+    pub source: Vec<u8>,
+    pub nodes: Vec<Node>,
+    /// Trivia after the last node.
+    pub trailing: Vec<Trivia>,
+}
+
+impl Cst {
+    pub fn text(&self, span: &Range<usize>) -> &[u8] {
+        &self.source[span.clone()]
+    }
+
+    /// Lower all the nodes to what they mean.
+    pub fn to_exprs(&self, engine: &mut Engine) -> CstResult<Vec<Expr>> {
+        self.nodes
+            .iter()
+            .map(|node| node.to_expr(&self.source, engine))
+            .collect()
+    }
+}
+
+impl Node {
+    /// Lower this to what it means, forgetting how it was written.
+    ///
+    /// This runs any reader macros in it, which can fail.
+    pub fn to_expr(&self, source: &[u8], engine: &mut Engine) -> CstResult<Expr> {
+        let text = |span: &Range<usize>| &source[span.clone()];
+        Ok(match &self.kind {
+            NodeKind::Symbol | NodeKind::Dot => {
+                Expr::Symbol(engine.intern_symbol(text(&self.span)))
+            }
+            &NodeKind::Bool(b) => Expr::Bool(b),
+            &NodeKind::Integer { value, .. } => Expr::Integer(value),
+            &NodeKind::Rational { numer, denom } => {
+                Num::ratio(numer as _, denom as _).to_bare_expr()
+            }
+            &NodeKind::Float(f) => Expr::Float(f),
+            &NodeKind::Char(c) => Expr::Char(c),
+            NodeKind::String { value, .. } => Expr::String(value.clone().into()),
+            NodeKind::Herestring { contents, .. } => Expr::String(text(contents).to_vec().into()),
+            NodeKind::Interpolated(pieces) => {
+                let mut list = Expr::Nil;
+                for piece in pieces.iter().rev() {
+                    let piece = match piece {
+                        Piece::Text(text) => Expr::String(text.clone().into()),
+                        Piece::Expr(node) => node.to_expr(source, engine)?,
+                    };
+                    list = Expr::Pair(Gc::new(piece), Gc::new(list));
+                }
+                let string = Expr::Symbol(engine.intern_symbol(b"string"));
+                Expr::Pair(Gc::new(string), Gc::new(list))
+            }
+            NodeKind::Error => {
+                return Err(CstError::new(
+                    ExprParseErrorInfo::Unreadable,
+                    self.span.clone(),
+                ))
+            }
+            NodeKind::RawDispatch { name, contents } => {
+                let raw = Expr::String(text(contents).to_vec().into());
+                self.run_macro(engine, MacroKey::Dispatch(text(name)), raw)?
+            }
+            NodeKind::List { children, .. } => {
+                let (children, mut list) = match &children[..] {
+                    [init @ .., dot, last] if dot.kind == NodeKind::Dot => {
+                        (init, last.to_expr(source, engine)?)
+                    }
+                    children => (children, Expr::Nil),
+                };
+                for child in children.iter().rev() {
+                    list = Expr::Pair(Gc::new(child.to_expr(source, engine)?), Gc::new(list));
+                }
+                list
+            }
+            NodeKind::Prefixed {
+                prefix: Prefix::Map,
+                inner,
+                ..
+            } => {
+                let kvs = match &inner.kind {
+                    NodeKind::List { children, .. } => children,
+                    _ => unreachable!("the reader makes sure maps have a list"),
+                };
+                let mut map = GcMap::new();
+                let mut kvs = kvs.iter();
+                while let Some(k) = kvs.next() {
+                    // A `,@` for quasiquote to fill in doesn't have a value
+                    let v = if k.is_splice() {
+                        Expr::Nil
+                    } else {
+                        kvs.next().unwrap().to_expr(source, engine)?
+                    };
+                    map.insert(Gc::new(k.to_expr(source, engine)?), Gc::new(v));
+                }
+                Expr::Map(map)
+            }
+            NodeKind::Prefixed {
+                prefix,
+                prefix_span,
+                inner,
+            } => {
+                let datum = inner.to_expr(source, engine)?;
+                match *prefix {
+                    Prefix::Macro(c) => self.run_macro(engine, MacroKey::Prefix(c), datum)?,
+                    Prefix::Dispatch => {
+                        let name = &text(prefix_span)[1..];
+                        self.run_macro(engine, MacroKey::Dispatch(name), datum)?
+                    }
+                    _ => {
+                        let name = prefix.form_name().unwrap();
+                        let quote = Gc::new(Expr::Symbol(engine.intern_symbol(name)));
+                        Expr::Pair(quote, Expr::pair(Gc::new(datum), Expr::nil()))
+                    }
+                }
+            }
+        })
+    }
+
+    /// Is this a `,@`?
+    fn is_splice(&self) -> bool {
+        matches!(
+            self.kind,
+            NodeKind::Prefixed {
+                prefix: Prefix::UnquoteSplicing,
+                ..
+            }
+        )
+    }
+
+    fn run_macro(&self, engine: &mut Engine, key: MacroKey, datum: Expr) -> CstResult<Expr> {
+        let (name, proc) = match key {
+            MacroKey::Prefix(c) => (c.to_string(), engine.reader_macros.prefixes.get(&c)),
+            MacroKey::Dispatch(name) => (
+                format!("#{}", String::from_utf8_lossy(name)),
+                engine.reader_macros.dispatch.get(name),
+            ),
+        };
+        let proc = match proc {
+            Some(it) => it.to_owned(),
+            None => {
+                return Err(CstError::new(
+                    ExprParseErrorInfo::UnknownReaderMacro(name),
+                    self.span.clone(),
+                ))
+            }
+        };
+        match engine.call(engine.thtdlib(), proc, vec![Gc::new(datum)]) {
+            Ok(it) => Ok((*it).clone()),
+            Err(exn) => {
+                let exn = exn.into_expr(engine);
+                let exn = engine
+                    .write_expr(exn)
+                    .unwrap_or_else(|_| "<unprintable>".to_owned());
+                Err(CstError::new(
+                    ExprParseErrorInfo::ReaderMacroFailed(name, exn),
+                    self.span.clone(),
+                ))
+            }
+        }
+    }
+}
+
+enum MacroKey<'a> {
+    Prefix(char),
+    Dispatch(&'a [u8]),
+}
+
+#[derive(Debug)]
+pub struct CstError {
+    pub info: ExprParseErrorInfo,
+    pub span: Range<usize>,
+    /// Places that are probably the real cause, and why.
+    pub hints: Vec<(Range<usize>, String)>,
+}
+
+impl CstError {
+    pub fn new(info: ExprParseErrorInfo, span: Range<usize>) -> CstError {
+        CstError {
+            info,
+            span,
+            hints: Vec::new(),
+        }
+    }
+}
+
+pub type CstResult<T> = Result<T, CstError>;
+/// Read a whole source file losslessly.
+///
+/// `macros` says which reader macros there are, so it knows how to read them.
+pub fn read(source: &[u8], macros: &ReaderMacros) -> CstResult<Cst> {
+    let mut reader = Reader {
+        src: source,
+        pos: 0,
+        macros,
+    };
+    let mut nodes = Vec::new();
+    loop {
+        let leading = reader.trivia()?;
+        if reader.pos == source.len() {
+            return Ok(Cst {
+                source: source.to_vec(),
+                nodes,
+                trailing: leading,
+            });
+        }
+        nodes.push(reader.node(leading)?);
+    }
+}
+
+/// Read as much as possible, skipping past top-level forms that can't be read.
+///
+/// Those become [`NodeKind::Error`] nodes, so the CST is still lossless.
+/// Reading picks back up at the next line that isn't indented.
+pub fn read_recovering(source: &[u8], macros: &ReaderMacros) -> (Cst, Vec<CstError>) {
+    read_recovering_from(source, 0, macros)
+}
+
+/// Like [`read_recovering`], but only the top-level forms from `pos` on.
+///
+/// This is for picking up reading a file again after running part of it
+/// added reader macros the rest might use. Spans are still from the start of `source`.
+pub fn read_recovering_from(
+    source: &[u8],
+    pos: usize,
+    macros: &ReaderMacros,
+) -> (Cst, Vec<CstError>) {
+    let mut reader = Reader {
+        src: source,
+        pos,
+        macros,
+    };
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut errors = Vec::new();
+    let trailing = loop {
+        let trivia_start = reader.pos;
+        let leading = match reader.trivia() {
+            Ok(it) => it,
+            Err(err) => {
+                // An unclosed block comment eats the rest of the file
+                errors.push(err);
+                nodes.push(Node {
+                    kind: NodeKind::Error,
+                    span: trivia_start..source.len(),
+                    leading: Vec::new(),
+                    trailing: Vec::new(),
+                });
+                break Vec::new();
+            }
+        };
+        if reader.pos == source.len() {
+            break leading;
+        }
+
+        let start = reader.pos;
+        let mut err = match reader.node(leading.clone()) {
+            Ok(node) => {
+                nodes.push(node);
+                continue;
+            }
+            Err(err) => err,
+        };
+        let end = match err.info {
+            // Just skip a stray closer
+            ExprParseErrorInfo::UnexpectedCloseParen { .. } if err.span.start == start => {
+                err.hints = closed_early_hints(source, &nodes);
+                start + 1
+            }
+            _ => {
+                let next = next_top_level(source, start);
+                // Whitespace before the next form is its trivia
+                let end = start
+                    + source[start..next]
+                        .iter()
+                        .rposition(|&b| !is_whitespace(b))
+                        .map_or(0, |idx| idx + 1);
+                let opener = source[start..end]
+                    .iter()
+                    .position(|&b| match_paren(b).is_some());
+                if let (true, Some(opener)) = (err.span.start >= next, opener) {
+                    // It ran into the next form, so the problem is really that
+                    // this one wasn't closed. Reading from the next form
+                    // will find the error again if it's really there.
+                    let opener = source[start + opener];
+                    let info = ExprParseErrorInfo::ExpectedCloseParen {
+                        opener: opener as char,
+                        closer: match_paren(opener).unwrap() as char,
+                    };
+                    err = CstError::new(info, start..end);
+                }
+                let scan_end = match err.info {
+                    ExprParseErrorInfo::ExpectedCloseParen { .. } => source.len(),
+                    _ => err.span.end,
+                };
+                err.hints = unclosed_hints(source, start, scan_end);
+                end
+            }
+        };
+        errors.push(err);
+        nodes.push(Node {
+            kind: NodeKind::Error,
+            span: start..end,
+            leading,
+            trailing: Vec::new(),
+        });
+        reader.pos = end;
+    };
+    let cst = Cst {
+        source: source.to_vec(),
+        nodes,
+        trailing,
+    };
+    (cst, errors)
+}
+
+/// Where the next line that isn't indented starts, after `start`.
+///
+/// Lines starting with a closer don't count; they're probably the end of
+/// whatever was at `start`.
+fn next_top_level(source: &[u8], start: usize) -> usize {
+    let mut idx = start;
+    while let Some(nl) = source[idx..].find(b"\n") {
+        idx += nl + 1;
+        match source.get(idx) {
+            Some(&b) if !is_whitespace(b) && !is_closer(b) => return idx,
+            _ => {}
+        }
+    }
+    source.len()
+}
+
+/// Guess which opener is missing its closer, by finding the first line
+/// that's indented as if that opener was already closed.
+fn unclosed_hints(source: &[u8], start: usize, end: usize) -> Vec<(Range<usize>, String)> {
+    let col = |pos: usize| {
+        let line_start = source[..pos]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |nl| nl + 1);
+        String::from_utf8_lossy(&source[line_start..pos])
+            .chars()
+            .count()
+    };
+
+    // Openers that haven't been closed, and their columns
+    let mut open: Vec<(usize, usize)> = Vec::new();
+    let mut idx = start;
+    let mut line_start = false;
+    while idx < end {
+        let rest = &source[idx..end];
+        let b = rest[0];
+        if line_start && !is_whitespace(b) {
+            line_start = false;
+            if !is_closer(b) && b != b';' {
+                let here = col(idx);
+                if let Some(&(opener, _)) = open.iter().rev().find(|(_, col)| *col >= here) {
+                    let opener_char = source[opener] as char;
+                    let closer = match_paren(source[opener]).unwrap() as char;
+                    return vec![
+                        (
+                            opener..opener + 1,
+                            format!(
+                                "this {:?} is probably missing its {:?}",
+                                opener_char, closer
+                            ),
+                        ),
+                        (
+                            idx..idx + 1,
+                            "judging by the indentation, it should have been closed before this"
+                                .to_owned(),
+                        ),
+                    ];
+                }
+            }
+        }
+
+        if b == b'\n' {
+            line_start = true;
+            idx += 1;
+        } else if rest.starts_with(b";*") {
+            idx += block_comment_len(rest).unwrap_or(rest.len());
+        } else if b == b';' {
+            idx += rest.find(b"\n").unwrap_or(rest.len());
+        } else if rest.starts_with(b"#\\") {
+            // `#\(` isn't an opener
+            idx += 3;
+        } else if rest.starts_with(b"#;") {
+            // and `#;` doesn't start a line comment
+            idx += 2;
+        } else if b == b'"' {
+            let mut len = 1;
+            while len < rest.len() && rest[len] != b'"' {
+                len += if rest[len] == b'\\' { 2 } else { 1 };
+            }
+            idx += len + 1;
+        } else if match_paren(b).is_some() {
+            open.push((idx, col(idx)));
+            idx += 1;
+        } else if is_closer(b) {
+            open.pop();
+            idx += 1;
+        } else {
+            idx += 1;
+        }
+    }
+    Vec::new()
+}
+
+/// For a stray closer: if the top-level forms before it are indented,
+/// the last one that isn't was probably closed too early.
+fn closed_early_hints(source: &[u8], nodes: &[Node]) -> Vec<(Range<usize>, String)> {
+    let line_indent = |pos: usize| {
+        let line_start = source[..pos]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |nl| nl + 1);
+        let before = &source[line_start..pos];
+        before
+            .iter()
+            .all(|&b| is_whitespace(b))
+            .then_some(before.len())
+    };
+    let unindented = nodes
+        .iter()
+        .rposition(|node| node.kind != NodeKind::Error && line_indent(node.span.start) == Some(0));
+    let (prev, after) = match unindented {
+        Some(idx) => (&nodes[idx], &nodes[idx + 1..]),
+        None => return Vec::new(),
+    };
+    let indented_after = after
+        .first()
+        .is_some_and(|node| line_indent(node.span.start).is_some_and(|indent| indent > 0));
+    let closes = source[..prev.span.end]
+        .last()
+        .is_some_and(|&b| is_closer(b));
+    if indented_after && closes {
+        vec![(
+            prev.span.end - 1..prev.span.end,
+            "judging by the indentation, this probably closed the form too early".to_owned(),
+        )]
+    } else {
+        Vec::new()
+    }
+}
+
+struct Reader<'a> {
+    src: &'a [u8],
+    pos: usize,
+    macros: &'a ReaderMacros,
+}
+
+impl<'a> Reader<'a> {
+    fn rest(&self) -> &'a [u8] {
+        &self.src[self.pos..]
+    }
+
+    fn err<T>(&self, info: ExprParseErrorInfo, span: Range<usize>) -> CstResult<T> {
+        Err(CstError::new(info, span))
+    }
+
+    /// Read all the whitespace and comments here.
+    fn trivia(&mut self) -> CstResult<Vec<Trivia>> {
+        let mut out = Vec::new();
+        while let Some(trivia) = self.one_trivia(true)? {
+            out.push(trivia);
+        }
+        Ok(out)
+    }
+
+    /// Read comments on the rest of this line, if there are any.
+    fn trailing_trivia(&mut self) -> CstResult<Vec<Trivia>> {
+        let mut out = Vec::new();
+        let mut before_whitespace = self.pos;
+        while let Some(trivia) = self.one_trivia(false)? {
+            if trivia.kind == TriviaKind::Whitespace {
+                before_whitespace = trivia.span.start;
+            } else {
+                before_whitespace = self.pos;
+            }
+            out.push(trivia);
+        }
+        // Whitespace after the last comment belongs to the next thing
+        if matches!(
+            out.last(),
+            Some(Trivia {
+                kind: TriviaKind::Whitespace,
+                ..
+            })
+        ) {
+            out.pop();
+            self.pos = before_whitespace;
+        }
+        Ok(out)
+    }
+
+    fn one_trivia(&mut self, newlines: bool) -> CstResult<Option<Trivia>> {
+        let start = self.pos;
+        let rest = self.rest();
+        let kind = if rest.starts_with(b";*") {
+            let len = block_comment_len(rest).ok_or_else(|| {
+                CstError::new(
+                    ExprParseErrorInfo::ExpectedCloseBlockComment,
+                    start..start + 2,
+                )
+            })?;
+            self.pos += len;
+            TriviaKind::BlockComment
+        } else if rest.starts_with(b";") {
+            self.pos += rest.find(b"\n").unwrap_or(rest.len());
+            TriviaKind::LineComment
+        } else if rest.starts_with(b"#;") {
+            self.pos += 2;
+            let leading = self.trivia()?;
+            if self.pos == self.src.len() || self.rest().starts_by(is_closer) {
+                let at_eof = self.pos == self.src.len();
+                return self.err(
+                    ExprParseErrorInfo::DatumCommentNothing { at_eof },
+                    start..start + 2,
+                );
+            }
+            let datum = self.node(leading)?;
+            // Comments after the datum aren't part of this
+            self.pos = datum.span.end;
+            TriviaKind::DatumComment
+        } else {
+            let len = rest
+                .iter()
+                .position(|&b| !is_whitespace(b) || (!newlines && b == b'\n'))
+                .unwrap_or(rest.len());
+            if len == 0 {
+                return Ok(None);
+            }
+            self.pos += len;
+            TriviaKind::Whitespace
+        };
+        Ok(Some(Trivia {
+            kind,
+            span: start..self.pos,
+        }))
+    }
+
+    fn node(&mut self, leading: Vec<Trivia>) -> CstResult<Node> {
+        let start = self.pos;
+        let rest = self.rest();
+        let interpolated = rest.starts_with(b"$\"") || rest.starts_with(b"$<<");
+        let prefix = if rest.starts_with(b"#\\") || interpolated {
+            // Not a dispatch macro called `\`, or a prefix macro `$`
+            None
+        } else if let Some(name) = self.macros.dispatch_at(rest) {
+            if rest.get(1 + name.len()) == Some(&b'"') {
+                self.pos += 1 + name.len();
+                let name = start + 1..self.pos;
+                let kind = self.raw_dispatch(name)?;
+                let span = start..self.pos;
+                let trailing = self.trailing_trivia()?;
+                return Ok(Node {
+                    kind,
+                    span,
+                    leading,
+                    trailing,
+                });
+            }
+            Some((Prefix::Dispatch, 1 + name.len()))
+        } else if let Some(c) = self.macros.prefix_at(rest) {
+            Some((Prefix::Macro(c), c.len_utf8()))
+        } else {
+            Prefix::builtin(rest)
+        };
+
+        let kind = if let Some((prefix, len)) = prefix {
+            self.pos += len;
+            let prefix_span = start..self.pos;
+            let inner_leading = self.trivia()?;
+            if self.pos == self.src.len() || self.rest().starts_by(is_closer) {
+                return self.err(ExprParseErrorInfo::PrefixNothing, start..self.pos);
+            }
+            let mut inner = self.node(inner_leading)?;
+            if prefix == Prefix::Map {
+                self.check_map(&inner)?;
+            }
+            // The inner node's trailing trivia is really ours
+            let trailing = std::mem::take(&mut inner.trailing);
+            let end = inner.span.end;
+            return Ok(Node {
+                kind: NodeKind::Prefixed {
+                    prefix,
+                    prefix_span,
+                    inner: Box::new(inner),
+                },
+                span: start..end,
+                leading,
+                trailing,
+            });
+        } else if rest.starts_with(b"#\\") {
+            self.char_literal()?
+        } else if interpolated {
+            self.interpolated()?
+        } else if rest.starts_with(b"<<") {
+            self.herestring()?
+        } else if rest.starts_with(b"\"") {
+            self.string()?
+        } else if let Some(close) = rest.first().and_then(|&b| match_paren(b)) {
+            self.pos += 1;
+            let mut children = Vec::new();
+            loop {
+                let leading = self.trivia()?;
+                match self.rest().first() {
+                    Some(&b) if b == close => {
+                        self.pos += 1;
+                        let dot = children
+                            .iter()
+                            .position(|it: &Node| it.kind == NodeKind::Dot);
+                        if dot.is_some_and(|dot| dot + 2 != children.len()) {
+                            return self.err(
+                                ExprParseErrorInfo::WrongDotTrailCount,
+                                children[dot.unwrap()].span.start..self.pos,
+                            );
+                        }
+                        break NodeKind::List {
+                            open: rest[0],
+                            close,
+                            children,
+                            inner: leading,
+                        };
+                    }
+                    Some(&b) if is_closer(b) => {
+                        return self.err(
+                            ExprParseErrorInfo::WrongCloseParen {
+                                opener: rest[0] as char,
+                                expected_closer: close as char,
+                                got_closer: b as char,
+                            },
+                            start..self.pos + 1,
+                        )
+                    }
+                    Some(_) => {
+                        let mut child = self.node(leading)?;
+                        // A `.` is only special after the first thing
+                        if !children.is_empty() && self.src[child.span.clone()] == *b"." {
+                            child.kind = NodeKind::Dot;
+                        }
+                        children.push(child);
+                    }
+                    None => {
+                        return self.err(
+                            ExprParseErrorInfo::ExpectedCloseParen {
+                                opener: rest[0] as char,
+                                closer: close as char,
+                            },
+                            start..self.pos,
+                        )
+                    }
+                }
+            }
+        } else if let Some(&b) = rest.first().filter(|&&b| is_closer(b)) {
+            return self.err(
+                ExprParseErrorInfo::UnexpectedCloseParen { closer: b as char },
+                start..start + 1,
+            );
+        } else {
+            self.pos += rest.find_by(is_delim).unwrap_or(rest.len());
+            self.atom(start)?
+        };
+
+        let span = start..self.pos;
+        let trailing = self.trailing_trivia()?;
+        Ok(Node {
+            kind,
+            span,
+            leading,
+            trailing,
+        })
+    }
+
+    fn check_map(&self, inner: &Node) -> CstResult<()> {
+        match &inner.kind {
+            NodeKind::List { children, .. }
+                if !children.iter().any(|it| it.kind == NodeKind::Dot) =>
+            {
+                // `,@`s go by themselves, but only where a key would be
+                let mut kvs = children.iter();
+                while let Some(k) = kvs.next() {
+                    if k.is_splice() {
+                        continue;
+                    }
+                    match kvs.next() {
+                        Some(v) if v.is_splice() => {
+                            return self.err(ExprParseErrorInfo::MapSpliceValue, v.span.clone())
+                        }
+                        Some(_) => {}
+                        None => {
+                            let count = children.iter().filter(|it| !it.is_splice()).count();
+                            return self
+                                .err(ExprParseErrorInfo::MapNeedsEven(count), inner.span.clone());
+                        }
+                    }
+                }
+                Ok(())
+            }
+            _ => self.err(ExprParseErrorInfo::MapNeedsSexpr, inner.span.clone()),
+        }
+    }
+
+    /// Figure out what the token from `start` to here is.
+    fn atom(&self, start: usize) -> CstResult<NodeKind> {
+        let whole = &self.src[start..self.pos];
+        let span = start..self.pos;
+        let signed = whole.starts_by(|b| b == b'-' || b == b'+');
+
+        match whole {
+            b"true" => return Ok(NodeKind::Bool(true)),
+            b"false" => return Ok(NodeKind::Bool(false)),
+            b"+inf.0" => return Ok(NodeKind::Float(f64::INFINITY)),
+            b"-inf.0" => return Ok(NodeKind::Float(f64::NEG_INFINITY)),
+            b"+nan.0" | b"-nan.0" => return Ok(NodeKind::Float(f64::NAN)),
+            _ => {}
+        }
+        // Take out the `_`s, or complain about the first one that isn't between
+        // two digits. Symbols can start with `+`, `-` and `.`, so things like
+        // that that don't work out are symbols instead.
+        let separated = |s: &[u8], offset: usize, radix: u32| match strip_separators(s, radix) {
+            Ok(it) => Ok(Some(it)),
+            Err(_) if !whole.starts_by(|b| b.is_ascii_digit()) => Ok(None),
+            Err(idx) => self.err(
+                ExprParseErrorInfo::MisplacedSeparator,
+                start + offset + idx..start + offset + idx + 1,
+            ),
+        };
+        let float = |s: &[u8]| {
+            std::str::from_utf8(s)
+                .ok()
+                .and_then(|s| s.parse::<f64>().ok())
+        };
+
+        // This has to go before ints so the / isn't mistaken for a radix
+        if let Some(slash) = whole.find(b"/") {
+            let (numer, denom) = (&whole[..slash], &whole[slash + 1..]);
+            let unsigned_numer = if signed { &numer[1..] } else { numer };
+            let all_digits = |s: &[u8]| {
+                s.starts_by(|b| b.is_ascii_digit())
+                    && s.iter().all(|&b| b.is_ascii_digit() || b == b'_')
+            };
+            // Otherwise it's probably a symbol like `peg/match` or `-/`
+            if all_digits(unsigned_numer) && all_digits(denom) {
+                let parse = |s: &[u8], offset: usize| {
+                    let s = match separated(s, offset, 10)? {
+                        Some(it) => it,
+                        None => return Ok(None),
+                    };
+                    // we just checked it's all ascii
+                    std::str::from_utf8(&s)
+                        .unwrap()
+                        .parse::<i64>()
+                        .map(Some)
+                        .map_err(|ono| {
+                            let info = ExprParseErrorInfo::ParseInt {
+                                radix: 10,
+                                radix_prefix: None,
+                                source: ono,
+                            };
+                            CstError::new(info, span.clone())
+                        })
+                };
+                if let (Some(numer), Some(denom)) = (parse(numer, 0)?, parse(denom, slash + 1)?) {
+                    if denom == 0 {
+                        return self.err(ExprParseErrorInfo::ZeroDenominator, span);
+                    }
+                    return Ok(NodeKind::Rational { numer, denom });
+                }
+            }
+        }
+
+        // Anything with a `.` is maybe a float, who knows? It's sure not an int
+        if !whole.contains(&b'.') && whole.starts_by(|b| b.is_ascii_digit() || signed) {
+            let (radix, radix_prefix) = match whole.get(1) {
+                Some(b) if !signed && !b.is_ascii_digit() && *b != b'_' => {
+                    let radix = match b {
+                        b'x' => 16,
+                        b'o' => 8,
+                        b'b' => 2,
+                        // Floats like `1e5`
+                        b'e' | b'E' => 10,
+                        _ => {
+                            return self.err(
+                                ExprParseErrorInfo::BadIntRadix(*b as char),
+                                start + 1..start + 2,
+                            )
+                        }
+                    };
+                    let prefix = (radix != 10).then_some(start..start + 2);
+                    (radix, prefix)
+                }
+                _ => (10, None),
+            };
+            let offset = radix_prefix.as_ref().map_or(0, |it| it.len());
+            let digits = separated(&whole[offset..], offset, radix)?;
+            if let Some(Ok(digits)) = digits.as_deref().map(std::str::from_utf8) {
+                match i64::from_str_radix(digits, radix) {
+                    Ok(value) => {
+                        return Ok(NodeKind::Integer {
+                            value,
+                            radix,
+                            radix_prefix,
+                        })
+                    }
+                    Err(_) if signed => {}
+                    // Floats like `1e5` aren't ints either, but ints too big to fit are still errors
+                    Err(_)
+                        if radix_prefix.is_none()
+                            && digits.contains(['e', 'E'])
+                            && float(digits.as_bytes()).is_some() => {}
+                    Err(ono) => {
+                        let radix_prefix = radix_prefix
+                            .map(|it| String::from_utf8_lossy(&self.src[it]).into_owned());
+                        return self.err(
+                            ExprParseErrorInfo::ParseInt {
+                                radix,
+                                radix_prefix,
+                                source: ono,
+                            },
+                            span,
+                        );
+                    }
+                }
+            }
+        }
+
+        if whole.starts_by(|b| b.is_ascii_digit() || b"+-.".contains(&b)) {
+            if let Some(f) = separated(whole, 0, 10)?.as_deref().and_then(float) {
+                return Ok(NodeKind::Float(f));
+            }
+        }
+
+        if whole.starts_by(|b| b.is_ascii_digit()) {
+            self.err(ExprParseErrorInfo::IndeterminableToken, span)
+        } else {
+            Ok(NodeKind::Symbol)
+        }
+    }
+
+    /// Read a `#\` char literal. The char right after the `#\` is always part
+    /// of it, even if it's something like a paren, so `#\(` works.
+    fn char_literal(&mut self) -> CstResult<NodeKind> {
+        let start = self.pos;
+        let after = &self.rest()[2..];
+        let first = match after
+            .utf8_chunks()
+            .next()
+            .and_then(|it| it.valid().chars().next())
+        {
+            Some(it) => it,
+            None => {
+                let end = (start + 3).min(self.src.len());
+                return self.err(ExprParseErrorInfo::BadChar(String::new()), start..end);
+            }
+        };
+        let rest = &after[first.len_utf8()..];
+        let len = first.len_utf8() + rest.find_by(is_delim).unwrap_or(rest.len());
+        self.pos += 2 + len;
+
+        let name = String::from_utf8_lossy(&after[..len]);
+        let named = CHAR_NAMES
+            .iter()
+            .find(|(it, _)| *it == name)
+            .map(|&(_, c)| c);
+        let hex = name
+            .strip_prefix('x')
+            .filter(|hex| {
+                (1..=6).contains(&hex.len()) && hex.bytes().all(|b| b.is_ascii_hexdigit())
+            })
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(char::from_u32);
+        match (len == first.len_utf8(), named, hex) {
+            (true, ..) => Ok(NodeKind::Char(first)),
+            (_, Some(c), _) | (_, _, Some(c)) => Ok(NodeKind::Char(c)),
+            _ => self.err(
+                ExprParseErrorInfo::BadChar(name.into_owned()),
+                start..self.pos,
+            ),
+        }
+    }
+
+    /// Find the `"` closing the string starting here, skipping escaped ones.
+    fn closing_quote(&self) -> CstResult<usize> {
+        let rest = self.rest();
+        let mut idx = 1;
+        loop {
+            match rest.get(idx) {
+                Some(b'\\') => idx += 2,
+                Some(b'"') => return Ok(idx),
+                Some(_) => idx += 1,
+                None => {
+                    let end = rest.find(b"\n").unwrap_or(rest.len());
+                    return self.err(
+                        ExprParseErrorInfo::ExpectedCloseQuote,
+                        self.pos..self.pos + end,
+                    );
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> CstResult<NodeKind> {
+        let start = self.pos;
+        let rest = self.rest();
+        let idx = self.closing_quote()?;
+        self.pos += idx + 1;
+
+        let mut value = Vec::new();
+        let mut escapes = Vec::new();
+        // Where we are in the contents, from the opening quote
+        let mut here = 1;
+        while let Some(bs) = rest[here..idx].find(b"\\") {
+            let bs = here + bs;
+            value.extend_from_slice(&rest[here..bs]);
+            match escape(&rest[bs + 1..idx]) {
+                Ok((esc, after)) => {
+                    value.extend_from_slice(&esc);
+                    here = idx - after.len();
+                    escapes.push(start + bs..start + here);
+                }
+                Err(ono) => {
+                    return self.err(
+                        ExprParseErrorInfo::InvalidEscape(bs + 1, ono),
+                        start..start + bs + 2,
+                    );
+                }
+            }
+        }
+        value.extend_from_slice(&rest[here..idx]);
+        Ok(NodeKind::String { value, escapes })
+    }
+
+    /// Read a `$"..."` or `$<<EOF` string, and the expressions in its `{}`s.
+    fn interpolated(&mut self) -> CstResult<NodeKind> {
+        let start = self.pos;
+        self.pos += 1;
+        // Where the herestring ends and where its contents end. Finding the end
+        // first means the expressions can't run past it.
+        let (after, end) = if self.rest().starts_with(b"<<") {
+            let contents = match self.herestring()? {
+                NodeKind::Herestring { contents, .. } => contents,
+                _ => unreachable!(),
+            };
+            let after = self.pos;
+            self.pos = contents.start;
+            (Some(after), contents.end)
+        } else {
+            self.pos += 1;
+            (None, self.src.len())
+        };
+
+        let mut pieces = Vec::new();
+        let mut text = Vec::new();
+        loop {
+            let rest = &self.src[self.pos..end];
+            match rest.first() {
+                None => match after {
+                    Some(after) => {
+                        self.pos = after;
+                        break;
+                    }
+                    None => {
+                        let line_end = self.src[start..].find(b"\n").map_or(end, |nl| start + nl);
+                        return self.err(ExprParseErrorInfo::ExpectedCloseQuote, start..line_end);
+                    }
+                },
+                Some(b'"') if after.is_none() => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(b'\\') if after.is_none() => match escape(&rest[1..]) {
+                    Ok((esc, rest)) => {
+                        text.extend_from_slice(&esc);
+                        self.pos = end - rest.len();
+                    }
+                    Err(ono) => {
+                        let bs = self.pos - start;
+                        return self.err(
+                            ExprParseErrorInfo::InvalidEscape(bs + 1, ono),
+                            start..start + bs + 2,
+                        );
+                    }
+                },
+                Some(&brace @ (b'{' | b'}')) if rest.get(1) == Some(&brace) => {
+                    text.push(brace);
+                    self.pos += 2;
+                }
+                Some(b'}') => {
+                    return self.err(
+                        ExprParseErrorInfo::UnexpectedCloseBrace,
+                        self.pos..self.pos + 1,
+                    )
+                }
+                Some(b'{') => {
+                    let open = self.pos;
+                    self.pos += 1;
+                    let leading = self.trivia()?;
+                    if self.pos >= end || self.rest().starts_with(b"}") {
+                        return self.err(
+                            ExprParseErrorInfo::EmptyInterpolation,
+                            open..(self.pos + 1).min(end),
+                        );
+                    }
+                    let node = self.node(leading)?;
+                    self.trivia()?;
+                    if self.pos >= end || !self.rest().starts_with(b"}") {
+                        return self.err(
+                            ExprParseErrorInfo::ExpectedCloseBrace,
+                            open..self.pos.min(end),
+                        );
+                    }
+                    self.pos += 1;
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(Piece::Expr(node));
+                }
+                Some(&b) => {
+                    text.push(b);
+                    self.pos += 1;
+                }
+            }
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        Ok(NodeKind::Interpolated(pieces))
+    }
+
+    /// Read the `"..."` after a `#name`. Backslashes don't escape anything,
+    /// except that a `\"` doesn't end it.
+    fn raw_dispatch(&mut self, name: Range<usize>) -> CstResult<NodeKind> {
+        let start = self.pos;
+        let idx = self.closing_quote()?;
+        self.pos += idx + 1;
+        Ok(NodeKind::RawDispatch {
+            name,
+            contents: start + 1..start + idx,
+        })
+    }
+
+    fn herestring(&mut self) -> CstResult<NodeKind> {
+        let start = self.pos;
+        let rest = &self.rest()[2..];
+        let newline = match rest.find(b"\n") {
+            Some(0) | None => {
+                return self.err(ExprParseErrorInfo::HerestringNoContents, start..start + 2)
+            }
+            Some(it) => it,
+        };
+        let name = &rest[..newline];
+        let delimiter = start + 2..start + 2 + newline;
+        let ender = [b"\n", name, b"\n"].concat();
+        match rest[newline + 1..].find(&ender) {
+            Some(end) => {
+                let contents = delimiter.end + 1..delimiter.end + 1 + end;
+                // the contents, the newline, and the name again
+                self.pos = contents.end + 1 + name.len();
+                Ok(NodeKind::Herestring {
+                    delimiter,
+                    contents,
+                })
+            }
+            None => self.err(
+                ExprParseErrorInfo::HerestringNoEnding(String::from_utf8_lossy(name).into_owned()),
+                delimiter,
+            ),
+        }
+    }
+}
+
+/// Take the `_`s out of a number like `1_000`.
+///
+/// Errs with where the first `_` that isn't between two digits is.
+fn strip_separators(s: &[u8], radix: u32) -> Result<Vec<u8>, usize> {
+    let is_digit = |idx: Option<usize>| {
+        idx.and_then(|idx| s.get(idx))
+            .is_some_and(|&b| (b as char).is_digit(radix))
+    };
+    let mut out = Vec::with_capacity(s.len());
+    for (idx, &b) in s.iter().enumerate() {
+        if b != b'_' {
+            out.push(b);
+        } else if !is_digit(idx.checked_sub(1)) || !is_digit(Some(idx + 1)) {
+            return Err(idx);
+        }
+    }
+    Ok(out)
+}
+
+/// Length of the block comment at the start of this, counting nested ones.
+fn block_comment_len(s: &[u8]) -> Option<usize> {
+    let mut depth = 0;
+    let mut idx = 0;
+    while idx < s.len() {
+        if s[idx..].starts_with(b";*") {
+            depth += 1;
+            idx += 2;
+        } else if s[idx..].starts_with(b"*;") {
+            depth -= 1;
+            idx += 2;
+            if depth == 0 {
+                return Some(idx);
+            }
+        } else {
+            idx += 1;
+        }
+    }
+    None
+}
+
+fn is_whitespace(b: u8) -> bool {
+    b" \t\r\n\0\x0b\x0c".contains(&b)
+}
@@ -0,0 +1,102 @@
+//! A small seedable PRNG, so we don't need to pull in all of `rand`.
+//!
+//! This is xoshiro256**, seeded with splitmix64, as recommended at <https://prng.di.unimi.it/>.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Environment variable to read the default seed from.
+pub const SEED_VAR: &str = "PLEASE_SEED";
+
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: [u64; 4],
+}
+
+impl Rng {
+    pub fn from_seed(seed: u64) -> Self {
+        // splitmix64 to spread the seed out over all the state
+        let mut seed = seed;
+        let mut state = [0; 4];
+        for slot in state.iter_mut() {
+            seed = seed.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            *slot = z ^ (z >> 31);
+        }
+        Self { state }
+    }
+
+    /// Seed from `PLEASE_SEED` if it's set, or from the clock otherwise.
+    pub fn from_env() -> Self {
+        let seed = std::env::var(SEED_VAR)
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_nanos() as u64)
+                    .unwrap_or_default()
+            });
+        Self::from_seed(seed)
+    }
+
+    /// Restore a generator from its state. An all-zero state is invalid.
+    pub fn from_state(state: [u64; 4]) -> Option<Self> {
+        if state == [0; 4] {
+            None
+        } else {
+            Some(Self { state })
+        }
+    }
+
+    pub fn state(&self) -> [u64; 4] {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let out = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+
+        out
+    }
+
+    /// Uniformly pick a number in `0..n`. `n` must not be 0.
+    pub fn below(&mut self, n: u64) -> u64 {
+        debug_assert_ne!(n, 0);
+        // Throw out the top sliver that would make lower numbers more likely
+        let limit = u64::MAX - u64::MAX % n;
+        loop {
+            let x = self.next_u64();
+            if x < limit {
+                return x % n;
+            }
+        }
+    }
+
+    /// Uniformly pick a float in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        // 53 bits of mantissa
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Fisher-Yates shuffle the first `count` items into random places, and leave the
+    /// rest in some order.
+    ///
+    /// Shuffling the whole thing is `partial_shuffle(slice, slice.len())`.
+    pub fn partial_shuffle<T>(&mut self, slice: &mut [T], count: usize) {
+        let len = slice.len();
+        for idx in 0..count.min(len) {
+            let swap = idx + self.below((len - idx) as u64) as usize;
+            slice.swap(idx, swap);
+        }
+    }
+}
@@ -0,0 +1,26 @@
+//! Nominal record types, made by `define-record`.
+
+use gc::{Finalize, Trace};
+
+use crate::Symbol;
+
+/// The shape of a record type.
+///
+/// Types are compared by identity, so two `define-record`s with the same
+/// name and fields still make different types.
+#[derive(Debug)]
+pub struct RecordType {
+    pub name: Symbol,
+    pub fields: Vec<Symbol>,
+}
+
+impl RecordType {
+    pub fn field_idx(&self, field: Symbol) -> Option<usize> {
+        self.fields.iter().position(|f| *f == field)
+    }
+}
+
+impl Finalize for RecordType {}
+unsafe impl Trace for RecordType {
+    unsafe_empty_trace!();
+}
@@ -1,6 +1,9 @@
 use super::{Engine, Expr, ExprParseErrorInfo};
 use gc::Gc;
-use termwiz::lineedit::{line_editor_terminal, LineEditor, LineEditorHost, NopLineEditorHost};
+use termwiz::{
+    lineedit::{line_editor_terminal, LineEditor, LineEditorHost, NopLineEditorHost},
+    terminal::Terminal,
+};
 
 impl Engine {
     pub fn repl(&mut self) -> termwiz::Result<()> {
@@ -10,6 +13,10 @@ impl Engine {
         let ps2 = self.intern_symbol("ps2");
 
         let mut terminal = line_editor_terminal()?;
+        // Results get pretty-printed to fit the terminal
+        let width = terminal
+            .get_screen_size()
+            .map_or(80, |size| size.cols.max(20));
         let mut editor = LineEditor::new(&mut terminal);
         let mut host = NopLineEditorHost::default();
 
@@ -36,6 +43,7 @@ impl Engine {
                         ExprParseErrorInfo::ExpectedCloseParen { .. }
                         | ExprParseErrorInfo::ExpectedCloseQuote
                         | ExprParseErrorInfo::ExpectedCloseBlockComment
+                        | ExprParseErrorInfo::DatumCommentNothing { at_eof: true }
                         | ExprParseErrorInfo::HerestringNoEnding(..)
                         | ExprParseErrorInfo::HerestringNoContents => {
                             in_parens = true;
@@ -53,7 +61,7 @@ impl Engine {
             match expr {
                 Ok(expr) => {
                     let result = self.eval(self.thtdlib(), Gc::new(expr));
-                    let string = match self.write_expr(result) {
+                    let string = match self.pprint_expr(result, width) {
                         Ok(s) => s,
                         Err(e) => {
                             let expr = e.into_expr(self);
@@ -22,11 +22,13 @@ macro_rules! predicates {
 predicates! {
     @impl
     (is_pair (Expr::Pair(..) | Expr::LazyPair(..)))
-    (is_number (Expr::Integer(_) | Expr::Float(_)))
-    (is_exact Expr::Integer(..))
+    (is_number (Expr::Integer(_) | Expr::Float(_) | Expr::Rational(..)))
+    (is_integer Expr::Integer(_))
+    (is_exact (Expr::Integer(..) | Expr::Rational(..)))
     (is_inexact Expr::Float(..))
     (is_nil Expr::Nil)
     (is_string Expr::String(_))
+    (is_char Expr::Char(_))
     (is_symbol Expr::Symbol(_))
     (is_bool Expr::Bool(_))
     (is_map Expr::Map(_))
@@ -34,6 +36,7 @@ predicates! {
     (is_procedure (Expr::NativeProcedure { .. } | Expr::Procedure { env: Some(_), .. }))
     (is_macro (Expr::SpecialForm { .. } | Expr::Procedure { env: None, .. }))
     (is_transient (Expr::Transient(_)))
+    (is_record (Expr::Record(..)))
 }
 
 impl Expr {
@@ -41,8 +44,10 @@ impl Expr {
         match self {
             Expr::Integer(_) => "integer",
             Expr::Float(_) => "float",
+            Expr::Rational(..) => "rational",
             Expr::Bool(_) => "bool",
             Expr::String(_) => "string",
+            Expr::Char(_) => "char",
             Expr::Symbol(_) => "symbol",
             Expr::Pair(_, _) => "pair",
             Expr::LazyPair(_, _, _) => "lazy-pair",
@@ -51,6 +56,8 @@ impl Expr {
             Expr::NativeProcedure { .. } => "native-procedure",
             Expr::Procedure { .. } => "procedure",
             Expr::Map(_) => "map",
+            Expr::Record(..) => "record",
+            Expr::RecordType(_) => "record-type",
             Expr::Transient(_) => "transient",
         }
     }
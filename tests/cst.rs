@@ -0,0 +1,427 @@
+use gc::Gc;
+use please::{
+    cst::{Cst, Node, NodeKind, Prefix, TriviaKind},
+    Engine, ExprParseErrorInfo,
+};
+
+fn read(source: &str) -> Cst {
+    Engine::new().read_cst(source, "<test>".to_owned()).unwrap()
+}
+
+fn text<'a>(cst: &'a Cst, node: &Node) -> &'a str {
+    std::str::from_utf8(cst.text(&node.span)).unwrap()
+}
+
+/// Writing out all the trivia and nodes in order gives back the source.
+fn unparse(cst: &Cst) -> String {
+    fn node(cst: &Cst, node_: &Node, out: &mut Vec<u8>) {
+        for trivia in &node_.leading {
+            out.extend_from_slice(cst.text(&trivia.span));
+        }
+        match &node_.kind {
+            NodeKind::List {
+                open,
+                close,
+                children,
+                inner,
+            } => {
+                out.push(*open);
+                for child in children {
+                    node(cst, child, out);
+                }
+                for trivia in inner {
+                    out.extend_from_slice(cst.text(&trivia.span));
+                }
+                out.push(*close);
+            }
+            NodeKind::Prefixed {
+                prefix_span, inner, ..
+            } => {
+                out.extend_from_slice(cst.text(prefix_span));
+                node(cst, inner, out);
+            }
+            _ => out.extend_from_slice(cst.text(&node_.span)),
+        }
+        for trivia in &node_.trailing {
+            out.extend_from_slice(cst.text(&trivia.span));
+        }
+    }
+
+    let mut out = Vec::new();
+    for it in &cst.nodes {
+        node(cst, it, &mut out);
+    }
+    for trivia in &cst.trailing {
+        out.extend_from_slice(cst.text(&trivia.span));
+    }
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn cst_is_lossless() {
+    let sources = [
+        "(define (f x) ; doubles it\n  (* 2 x))\n\n;* block ;* nested *; *;\n(f 0x10)",
+        "  '( a . b )  `(x ,y ,@zs) # (1 2)",
+        "(display <<EOF\nhello\n  there\nEOF\n) \"esc\\\"aped\\n\" 1/2 -3.5e2",
+        "(a ; comment right before the closer\n)",
+        "($\"a {(f \"}\")} {{b}}\" $<<END\n{x}\nEND\n)",
+        "(a #; (b\n c) d #;#;e f) #;; comment\n g",
+        "(#\\( #\\) #\\space) 1_000 1e5 +inf.0 \"\\x41\\u{e9}\"",
+        "",
+        "; nothing but a comment",
+    ];
+    for source in sources {
+        let cst = read(source);
+        assert_eq!(unparse(&cst), source);
+    }
+}
+
+#[test]
+fn cst_lowers_like_read_many() {
+    let source = "(define (f x) ; doubles it\n  (* 2 x))\n'(a . b) #(k v) `(x ,y ,@zs) 0b101 1/2 -0.5 \"a\\tb\" <<END\nhere\nEND\n(- 1) (. a) .";
+
+    let mut engine = Engine::new();
+    let cst = engine.read_cst(source, "<test>".to_owned()).unwrap();
+    let lowered = cst.to_exprs(&mut engine).unwrap();
+    let read = engine.read_many(source, "<test>".to_owned()).unwrap();
+    assert!(lowered == read);
+
+    let printed = lowered
+        .iter()
+        .map(|expr| engine.write_expr(Gc::new(expr.clone())).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        printed,
+        [
+            "(define (f x) (* 2 x))",
+            "(quote (a . b))",
+            "#{k v}",
+            "(quasiquote (x (unquote y) (unquote-splicing zs)))",
+            "5",
+            "1/2",
+            "-0.5",
+            "\"a\\tb\"",
+            "\"here\"",
+            "(- 1)",
+            "(. a)",
+            ".",
+        ]
+    );
+}
+
+#[test]
+fn cst_keeps_spelling() {
+    let cst = read("0x1F \"a\\nb\\\\\" <<DELIM\nstuff\nDELIM\n#(a 1) ,@xs (a . b) true");
+    let kinds = cst.nodes.iter().map(|it| &it.kind).collect::<Vec<_>>();
+
+    match kinds[0] {
+        &NodeKind::Integer {
+            value,
+            radix,
+            ref radix_prefix,
+        } => {
+            assert_eq!((value, radix), (31, 16));
+            assert_eq!(cst.text(radix_prefix.as_ref().unwrap()), b"0x");
+        }
+        it => panic!("expected an int, got {:?}", it),
+    }
+    match kinds[1] {
+        NodeKind::String { value, escapes } => {
+            assert_eq!(value, b"a\nb\\");
+            let escapes = escapes.iter().map(|it| cst.text(it)).collect::<Vec<_>>();
+            assert_eq!(escapes, [&b"\\n"[..], b"\\\\"]);
+        }
+        it => panic!("expected a string, got {:?}", it),
+    }
+    match kinds[2] {
+        NodeKind::Herestring {
+            delimiter,
+            contents,
+        } => {
+            assert_eq!(cst.text(delimiter), b"DELIM");
+            assert_eq!(cst.text(contents), b"stuff");
+        }
+        it => panic!("expected a herestring, got {:?}", it),
+    }
+    assert_eq!(text(&cst, &cst.nodes[2]), "<<DELIM\nstuff\nDELIM");
+    match kinds[3] {
+        NodeKind::Prefixed { prefix, inner, .. } => {
+            assert_eq!(*prefix, Prefix::Map);
+            assert_eq!(text(&cst, inner), "(a 1)");
+        }
+        it => panic!("expected a map, got {:?}", it),
+    }
+    match kinds[4] {
+        NodeKind::Prefixed {
+            prefix,
+            prefix_span,
+            ..
+        } => {
+            assert_eq!(*prefix, Prefix::UnquoteSplicing);
+            assert_eq!(cst.text(prefix_span), b",@");
+        }
+        it => panic!("expected a prefix, got {:?}", it),
+    }
+    match kinds[5] {
+        NodeKind::List { children, .. } => {
+            assert_eq!(children[1].kind, NodeKind::Dot);
+        }
+        it => panic!("expected a list, got {:?}", it),
+    }
+    assert_eq!(*kinds[6], NodeKind::Bool(true));
+}
+
+#[test]
+fn cst_trivia() {
+    let cst = read(";; header\n\n(a ;* inline *; b) ; after\n(c)");
+    let first = &cst.nodes[0];
+    let leading = first.leading.iter().map(|it| it.kind).collect::<Vec<_>>();
+    assert_eq!(leading, [TriviaKind::LineComment, TriviaKind::Whitespace]);
+
+    let commented = read("(a #;(b ; c\n) d)");
+    match &commented.nodes[0].kind {
+        NodeKind::List { children, .. } => {
+            assert_eq!(children.len(), 2);
+            let comment = &children[0].trailing[1];
+            assert_eq!(comment.kind, TriviaKind::DatumComment);
+            assert_eq!(commented.text(&comment.span), b"#;(b ; c\n)");
+        }
+        it => panic!("expected a list, got {:?}", it),
+    }
+    let trailing = first
+        .trailing
+        .iter()
+        .map(|it| cst.text(&it.span))
+        .collect::<Vec<_>>();
+    assert_eq!(trailing, [&b" "[..], b"; after"]);
+    // The newline goes with the next form
+    assert_eq!(cst.text(&cst.nodes[1].leading[0].span), b"\n");
+
+    match &first.kind {
+        NodeKind::List { children, .. } => {
+            // Comments on the same line go with the thing before them
+            let a = &children[0];
+            assert_eq!(text(&cst, a), "a");
+            let comment = a
+                .trailing
+                .iter()
+                .find(|it| it.kind == TriviaKind::BlockComment)
+                .unwrap();
+            assert_eq!(cst.text(&comment.span), b";* inline *;");
+        }
+        it => panic!("expected a list, got {:?}", it),
+    }
+}
+
+#[test]
+fn cst_errors() {
+    let cases = [
+        ("(a b", "ExpectedCloseParen"),
+        ("(a . b c)", "WrongDotTrailCount"),
+        ("#(a)", "MapNeedsEven"),
+        ("# a", "MapNeedsSexpr"),
+        ("\"\\q\"", "InvalidEscape"),
+        ("0z12", "BadIntRadix"),
+        ("1/0", "ZeroDenominator"),
+        ("1__0", "MisplacedSeparator"),
+        ("#\\foo", "BadChar"),
+        ("'", "PrefixNothing"),
+        (";* open", "ExpectedCloseBlockComment"),
+        (";* a ;* b *;", "ExpectedCloseBlockComment"),
+        ("(a #;", "DatumCommentNothing { at_eof: true }"),
+        ("(a #;)", "DatumCommentNothing { at_eof: false }"),
+    ];
+    let engine = Engine::new();
+    for (source, expected) in cases {
+        let err = engine.read_cst(source, "<test>".to_owned()).unwrap_err();
+        let name = format!("{:?}", err.source());
+        assert!(
+            name.starts_with(expected),
+            "{:?} gave {}, not {}",
+            source,
+            name,
+            expected
+        );
+    }
+
+    match engine.read_cst("\"ab\\q\"", "<test>".to_owned()) {
+        Err(err) => assert!(matches!(
+            err.source(),
+            ExprParseErrorInfo::InvalidEscape(4, _)
+        )),
+        Ok(it) => panic!("expected an error, got {:?}", it),
+    }
+}
+
+#[test]
+fn cst_reader_macros() {
+    let mut engine = Engine::new();
+    engine
+        .read_eval(
+            "(reader/set-prefix! \"~\" list) (reader/set-dispatch! 're list)",
+            "<test>".to_owned(),
+        )
+        .unwrap()
+        .unwrap();
+
+    let cst = engine
+        .read_cst("~x #re (a) #re\"\\d+\" #other", "<test>".to_owned())
+        .unwrap();
+    match &cst.nodes[0].kind {
+        NodeKind::Prefixed { prefix, inner, .. } => {
+            assert_eq!(*prefix, Prefix::Macro('~'));
+            assert_eq!(text(&cst, inner), "x");
+        }
+        it => panic!("expected a prefix, got {:?}", it),
+    }
+    match &cst.nodes[1].kind {
+        NodeKind::Prefixed {
+            prefix,
+            prefix_span,
+            inner,
+        } => {
+            assert_eq!(*prefix, Prefix::Dispatch);
+            assert_eq!(cst.text(prefix_span), b"#re");
+            assert_eq!(text(&cst, inner), "(a)");
+        }
+        it => panic!("expected a dispatch, got {:?}", it),
+    }
+    match &cst.nodes[2].kind {
+        NodeKind::RawDispatch { name, contents } => {
+            assert_eq!(cst.text(name), b"re");
+            assert_eq!(cst.text(contents), b"\\d+");
+        }
+        it => panic!("expected a raw dispatch, got {:?}", it),
+    }
+    assert_eq!(cst.nodes[3].kind, NodeKind::Symbol);
+
+    let printed = cst
+        .to_exprs(&mut engine)
+        .unwrap()
+        .into_iter()
+        .map(|expr| engine.write_expr(Gc::new(expr)).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(printed, ["(x)", "((a))", "(\"\\\\d+\")", "#other"]);
+
+    // Without the macros it's just symbols
+    let cst = Engine::new()
+        .read_cst("~x #re\"a\"", "<test>".to_owned())
+        .unwrap();
+    assert!(cst.nodes.iter().all(|it| it.kind == NodeKind::Symbol));
+}
+
+#[test]
+fn read_eval_reads_everything_first() {
+    let ran = |engine: &mut Engine| {
+        let res = engine.read_eval("ran", "<test>".to_owned()).unwrap();
+        res.is_ok()
+    };
+
+    // A syntax error anywhere means nothing runs
+    let mut engine = Engine::new();
+    let err = engine
+        .read_eval("(define ran true)\n(print \"x\"\n", "<test>".to_owned())
+        .unwrap_err();
+    assert_eq!(err.sources().count(), 1);
+    assert!(!ran(&mut engine));
+
+    // Even with a reader macro set first
+    let source = "(reader/set-prefix! \"~\" list)\n(define ran ~x)\n(oops\n";
+    assert!(engine.read_eval(source, "<test>".to_owned()).is_err());
+    assert!(!ran(&mut engine));
+
+    // Errors after a form that would raise are still found
+    let err = engine
+        .read_eval("(error 'no)\n(a\n(b\n", "<test>".to_owned())
+        .unwrap_err();
+    assert_eq!(err.sources().count(), 2);
+
+    // The rest is read again once a reader macro is set
+    let source = "(reader/set-prefix! \"~\" list)\n(define ran '~x)\n";
+    engine
+        .read_eval(source, "<test>".to_owned())
+        .unwrap()
+        .unwrap();
+    let got = engine
+        .read_eval("ran", "<test>".to_owned())
+        .unwrap()
+        .unwrap();
+    assert_eq!(engine.write_expr(got).unwrap(), "(x)");
+}
+
+#[test]
+fn cst_recovers() {
+    let source = "(define (f x)\n  (let ([y 1]\n    (+ x y)))\n\n(print \"fine\")\n(define h 0x1z)\n(define (k)\n  (foo))\n  (bar))\n";
+    let (cst, err) = Engine::new().read_cst_recovering(source, "<test>".to_owned());
+    assert_eq!(unparse(&cst), source);
+
+    let kinds = cst
+        .nodes
+        .iter()
+        .map(|it| (text(&cst, it), it.kind == NodeKind::Error))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            ("(define (f x)\n  (let ([y 1]\n    (+ x y)))", true),
+            ("(print \"fine\")", false),
+            ("(define h 0x1z)", true),
+            ("(define (k)\n  (foo))", false),
+            ("(bar)", false),
+            (")", true),
+        ]
+    );
+
+    let errs = err
+        .unwrap()
+        .sources()
+        .map(|it| format!("{:?}", it))
+        .collect::<Vec<_>>();
+    assert_eq!(errs.len(), 3);
+    assert!(errs[0].starts_with("ExpectedCloseParen"));
+    assert!(errs[1].starts_with("ParseInt"));
+    assert!(errs[2].starts_with("UnexpectedCloseParen"));
+
+    // Read with the normal reader too, it's the same errors
+    let err = Engine::new()
+        .read_many(source, "<test>".to_owned())
+        .unwrap_err();
+    assert_eq!(err.sources().count(), 3);
+
+    let (cst, err) = Engine::new().read_cst_recovering("(a) (b)", "<test>".to_owned());
+    assert!(err.is_none());
+    assert_eq!(cst.nodes.len(), 2);
+}
+
+#[test]
+fn cst_recovery_hints() {
+    fn hinted(source: &str) -> Vec<&str> {
+        let (_, errors) = please::cst::read_recovering(source.as_bytes(), &Default::default());
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        errors[0]
+            .hints
+            .iter()
+            .map(|(span, _)| &source[span.clone()])
+            .collect()
+    }
+
+    // The `let` is missing its closer, so `(g x)` looks like it's in it
+    let source = "(define (f x)\n  (let ([y 1])\n    (+ x y)\n  (g x))\n(h)\n";
+    let (_, errors) = please::cst::read_recovering(source.as_bytes(), &Default::default());
+    let hints = errors[0]
+        .hints
+        .iter()
+        .map(|(span, _)| span.start)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        hints,
+        [source.find("(let").unwrap(), source.find("(g").unwrap()]
+    );
+
+    // An extra closer ends the form before the indented line after it,
+    // so the closer that doesn't match anything comes later
+    let source = "(define (k)\n  (foo)))\n  (bar)\n";
+    assert_eq!(hinted(source), Vec::<&str>::new());
+    let source = "(define (k)\n  (foo))\n  (bar))\n";
+    assert_eq!(hinted(source), [")"]);
+}
@@ -0,0 +1,72 @@
+use std::{ffi::OsString, fs, path::PathBuf};
+
+use please::Engine;
+
+/// Formatting every file in the repo shouldn't change what any of it means,
+/// and formatting it again shouldn't change anything.
+#[test]
+fn format_is_faithful_and_stable() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+
+    let mut engine = Engine::new();
+
+    let mut paths = Vec::new();
+    let mut todo = ["thtdlib", "tests", "examples", "aoc2019", "aoc2020"]
+        .iter()
+        .map(|dir| root.join(dir))
+        .collect::<Vec<_>>();
+    while let Some(path) = todo.pop() {
+        if path.is_dir() {
+            for entry in fs::read_dir(path).unwrap() {
+                todo.push(entry.unwrap().path())
+            }
+        } else if path.extension() == Some(&OsString::from("please")) {
+            paths.push(path);
+        }
+    }
+
+    for path in paths {
+        let name = path.to_string_lossy().into_owned();
+        let source = fs::read_to_string(&path).unwrap();
+        let formatted = engine.format_source(&source, name.clone()).unwrap();
+
+        let before = engine.read_many(&source, name.clone()).unwrap();
+        let after = engine.read_many(&formatted, name.clone()).unwrap();
+        assert!(
+            before == after,
+            "formatting changed the meaning of {}",
+            name
+        );
+
+        let again = engine.format_source(&formatted, name.clone()).unwrap();
+        assert!(formatted == again, "formatting {} twice changed it", name);
+    }
+}
+
+#[test]
+fn format_examples() {
+    let mut engine = Engine::new();
+    let mut check = |source: &str, expected: &str| {
+        let formatted = engine.format_source(source, "<test>".to_owned()).unwrap();
+        assert_eq!(formatted, expected);
+    };
+
+    check("(a   b\n\n\n  c )", "(a b\n\n  c)\n");
+    check(
+        "(defun f (x)\n(let (\n[y x])\ny))",
+        "(defun f (x)\n  (let (\n        [y x])\n    y))\n",
+    );
+    check("(a) (b)", "(a)\n(b)\n");
+    check("(a   #;b   c\n#;d)", "(a #;b c\n  #;d)\n");
+    check("(#\\( #\\[ #\\ \n x)", "(#\\( #\\[ #\\ \n x)\n");
+    check("'( a\nb)", "'(a\n  b)\n");
+    check("`(if x\n,y)", "`(if x\n  ,y)\n");
+    check("#{a 1\nb 2}", "#{a 1\n  b 2}\n");
+    check("(a ; hi\nb)", "(a ; hi\n  b)\n");
+    check(";; top\n\n\n(a)   ; end", ";; top\n\n(a) ; end\n");
+    check("(a ;* block *; b)", "(a ;* block *; b)\n");
+    check(
+        "(a <<EOF\n  keep  \nEOF\n)",
+        "(a <<EOF\n  keep  \nEOF\n  )\n",
+    );
+}